@@ -0,0 +1,20 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../tests/roundtrip/mod.rs"]
+mod roundtrip;
+
+thread_local! {
+    static CONTEXT: rink_core::Context = roundtrip::sandbox_context();
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        CONTEXT.with(|ctx| roundtrip::check_formula(ctx, input));
+    }
+});
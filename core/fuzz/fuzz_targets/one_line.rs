@@ -0,0 +1,27 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rink_core::limits::Limits;
+use rink_core::{simple_context, Context};
+
+thread_local! {
+    static CONTEXT: std::cell::RefCell<Context> = {
+        let mut ctx = simple_context().unwrap();
+        ctx.use_humanize = false;
+        ctx.limits = Limits::sandbox();
+        std::cell::RefCell::new(ctx)
+    };
+}
+
+// Any input is allowed to produce an error, but never a panic.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        CONTEXT.with(|ctx| {
+            let _ = rink_core::one_line(&mut ctx.borrow_mut(), input);
+        });
+    }
+});
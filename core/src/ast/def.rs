@@ -4,8 +4,7 @@ use std::convert::TryFrom;
 use std::ops::Deref;
 use std::rc::Rc;
 
-#[derive(Debug, Clone)]
-#[cfg_attr(test, derive(PartialEq))]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DatePattern {
     Literal(String),
     Match(String),
@@ -58,6 +57,33 @@ pub struct Property {
     pub doc: Option<String>,
 }
 
+/// One row of a `PropertyTable`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TablePoint {
+    pub state: ExprString,
+    pub value: ExprString,
+}
+
+/// Values of a property against a state variable like temperature,
+/// written in a substance as
+///
+/// ```text
+/// density at temperature °C in g/cm^3 {
+///     0   0.99984
+///     20  0.99821
+/// }
+/// ```
+///
+/// Values in between rows are interpolated linearly.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PropertyTable {
+    pub property: String,
+    /// The quantity of the states, like `temperature`.
+    pub variable: String,
+    pub points: Vec<TablePoint>,
+    pub doc: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "type")]
@@ -82,10 +108,16 @@ pub enum Def {
     Substance {
         symbol: Option<String>,
         properties: Vec<Property>,
+        #[serde(default)]
+        tables: Vec<PropertyTable>,
     },
     Category {
         display_name: String,
     },
+    /// A `!datepattern` directive, which adds to `datepatterns.txt`.
+    DatePattern {
+        pattern: String,
+    },
     Error {
         message: String,
     },
@@ -1,6 +1,8 @@
 use super::*;
+use crate::bigint::BigInt;
+use crate::text_query::{Token, TokenIterator};
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 #[serde(tag = "type")]
 pub enum Expr {
@@ -13,6 +15,10 @@ pub enum Expr {
     Mul { exprs: Vec<Expr> },
     Of { property: String, expr: Box<Expr> },
     Call { func: Function, args: Vec<Expr> },
+    Yields { expr: Box<Expr>, product: String },
+    Mixture { expr: Box<Expr>, by: MixtureBasis },
+    At { expr: Box<Expr>, states: Vec<Expr> },
+    After { expr: Box<Expr>, time: Box<Expr> },
     Error { message: String },
 }
 
@@ -63,12 +69,46 @@ impl Expr {
         Expr::new_bin(BinOpType::Equals, numer, denom)
     }
 
+    pub fn new_range(start: Expr, end: Expr) -> Expr {
+        Expr::new_bin(BinOpType::Range, start, end)
+    }
+
     pub fn new_of(property: &str, expr: Expr) -> Expr {
         let property = property.to_owned();
         let expr = Box::new(expr);
         Expr::Of { property, expr }
     }
 
+    /// `<amount> yields <formula>`, how much of a product an amount of
+    /// a reagent turns into.
+    pub fn new_yields(expr: Expr, product: &str) -> Expr {
+        let product = product.to_owned();
+        let expr = Box::new(expr);
+        Expr::Yields { expr, product }
+    }
+
+    /// `<substances> by <basis>`, a mixture of the substances added
+    /// together in `expr`.
+    pub fn new_mixture(expr: Expr, basis: MixtureBasis) -> Expr {
+        let expr = Box::new(expr);
+        Expr::Mixture { expr, by: basis }
+    }
+
+    /// `<substance> at <state>, ...`, a substance at a temperature or
+    /// pressure, which picks values out of its property tables.
+    pub fn new_at(expr: Expr, states: Vec<Expr>) -> Expr {
+        let expr = Box::new(expr);
+        Expr::At { expr, states }
+    }
+
+    /// `<substance> after <time>`, what is left of a radioactive
+    /// substance after it decays for a time.
+    pub fn new_after(expr: Expr, time: Expr) -> Expr {
+        let expr = Box::new(expr);
+        let time = Box::new(time);
+        Expr::After { expr, time }
+    }
+
     pub fn new_unary(op: UnaryOpType, expr: Expr) -> Expr {
         let expr = Box::new(expr);
         Expr::UnaryOp(UnaryOpExpr { op, expr })
@@ -96,6 +136,7 @@ pub enum Precedence {
     Mul,
     Div,
     Add,
+    Range,
     Equals,
 }
 
@@ -106,6 +147,7 @@ impl Precedence {
             BinOpType::Sub => Precedence::Add,
             BinOpType::Pow => Precedence::Pow,
             BinOpType::Frac => Precedence::Div,
+            BinOpType::Range => Precedence::Range,
             BinOpType::Equals => Precedence::Equals,
         }
     }
@@ -116,31 +158,117 @@ impl Precedence {
             BinOpType::Sub => Precedence::Div,
             BinOpType::Pow => Precedence::Term,
             BinOpType::Frac => Precedence::Mul,
-            BinOpType::Equals => Precedence::Add,
+            BinOpType::Range => Precedence::Add,
+            BinOpType::Equals => Precedence::Range,
+        }
+    }
+}
+
+/// Writes a name, quoting it if it wouldn't otherwise be read back as
+/// a single identifier.
+fn write_ident(name: &str, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut iter = TokenIterator::new(name);
+    match (iter.next(), iter.next()) {
+        (Some(Token::Ident(ref ident)), Some(Token::Eof)) if ident == name => {
+            write!(fmt, "{}", name)
+        }
+        _ => {
+            write!(fmt, "\"")?;
+            for c in name.chars() {
+                if c == '"' || c == '\\' {
+                    write!(fmt, "\\")?;
+                }
+                write!(fmt, "{}", c)?;
+            }
+            write!(fmt, "\"")
         }
     }
 }
 
+/// Writes a constant so that it parses back to the same value. Parsed
+/// constants always have a terminating decimal expansion, so anything
+/// that doesn't fit the default output is written in scientific
+/// notation with an integer mantissa instead of being rounded.
+fn write_const(value: &Numeric, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let (exact, val) = value.to_string(10, Digits::Default);
+    let (num, den) = match value.to_rational() {
+        Some(rational) if !exact => rational,
+        _ => return write!(fmt, "{}", val),
+    };
+    let (two, five, ten) = (BigInt::from(2u64), BigInt::from(5u64), BigInt::from(10u64));
+    if den == BigInt::one() {
+        // Large integers, written without their trailing zeros. The
+        // number of trailing zeros is found by binary search since
+        // dividing one at a time is quadratic.
+        let (mut low, mut high) = (0, num.trailing_zeros().unwrap_or(0) as u32 + 1);
+        while high - low > 1 {
+            let mid = (low + high) / 2;
+            if &num % &ten.pow(mid) == BigInt::zero() {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        return write!(fmt, "{}e{}", &num / &ten.pow(low), low);
+    }
+    // A terminating expansion means den = 2^twos * 5^fives.
+    let twos = den.trailing_zeros().unwrap_or(0) as u32;
+    let odd = &den / &two.pow(twos);
+    let mut fives = (odd.bits() as f64 * std::f64::consts::LN_2 / 5f64.ln()) as u32;
+    let mut power = five.pow(fives);
+    while power < odd {
+        power = &power * &five;
+        fives += 1;
+    }
+    if power != odd {
+        return write!(fmt, "({} / {})", num, den);
+    }
+    let exp = twos.max(fives);
+    let mantissa = &(&num * &two.pow(exp - twos)) * &five.pow(exp - fives);
+    write!(fmt, "{}e-{}", mantissa, exp)
+}
+
 impl fmt::Display for Expr {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         fn recurse(expr: &Expr, fmt: &mut fmt::Formatter<'_>, prec: Precedence) -> fmt::Result {
             match *expr {
-                Expr::Unit { ref name } => write!(fmt, "{}", name),
-                Expr::Quote { ref string } => write!(fmt, "'{}'", string),
-                Expr::Const { ref value } => {
-                    let (_exact, val) = value.to_string(10, Digits::Default);
-                    write!(fmt, "{}", val)
+                Expr::Unit { ref name } => write_ident(name, fmt),
+                Expr::Quote { ref string } => {
+                    write!(fmt, "'")?;
+                    for c in string.chars() {
+                        match c {
+                            '\'' => write!(fmt, "\\'")?,
+                            '\n' => write!(fmt, "\\n")?,
+                            '\t' => write!(fmt, "\\t")?,
+                            c => write!(fmt, "{}", c)?,
+                        }
+                    }
+                    write!(fmt, "'")
+                }
+                Expr::Const { ref value } => write_const(value, fmt),
+                Expr::Date { ref tokens } => {
+                    write!(fmt, "#")?;
+                    for token in tokens {
+                        write!(fmt, "{}", token)?;
+                    }
+                    write!(fmt, "#")
                 }
-                Expr::Date { .. } => write!(fmt, "NYI: date expr Display"),
                 Expr::BinOp(ref binop) => {
                     let op_prec = Precedence::from(binop.op);
                     let succ = Precedence::next(binop.op);
+                    // The side that would associate differently
+                    // without parentheses gets the tighter precedence.
+                    let (left_prec, right_prec) = match binop.op {
+                        BinOpType::Add | BinOpType::Sub | BinOpType::Frac => (op_prec, succ),
+                        BinOpType::Pow => (succ, op_prec),
+                        BinOpType::Range | BinOpType::Equals => (succ, succ),
+                    };
                     if prec < op_prec {
                         write!(fmt, "(")?;
                     }
-                    recurse(&binop.left, fmt, succ)?;
+                    recurse(&binop.left, fmt, left_prec)?;
                     write!(fmt, "{}", binop.op.symbol())?;
-                    recurse(&binop.right, fmt, op_prec)?;
+                    recurse(&binop.right, fmt, right_prec)?;
                     if prec < op_prec {
                         write!(fmt, ")")?;
                     }
@@ -176,7 +304,19 @@ impl fmt::Display for Expr {
                     }
                     for expr in exprs.iter().skip(1) {
                         write!(fmt, " ")?;
-                        recurse(expr, fmt, Precedence::Pow)?;
+                        let term = Wrap(expr, Precedence::Pow).to_string();
+                        // `a -b` would be subtraction, and `a of b` would
+                        // be a property lookup.
+                        let ambiguous = match TokenIterator::new(&term).next() {
+                            Some(Token::Minus) | Some(Token::Plus) => true,
+                            Some(Token::Ident(ref ident)) => ident == "of",
+                            _ => false,
+                        };
+                        if ambiguous {
+                            write!(fmt, "({})", term)?;
+                        } else {
+                            write!(fmt, "{}", term)?;
+                        }
                     }
                     if prec < Precedence::Mul {
                         write!(fmt, ")")?;
@@ -201,17 +341,84 @@ impl fmt::Display for Expr {
                     if prec < Precedence::Add {
                         write!(fmt, "(")?;
                     }
-                    write!(fmt, "{} of ", property)?;
-                    recurse(expr, fmt, Precedence::Div)?;
+                    write_ident(property, fmt)?;
+                    write!(fmt, " of ")?;
+                    recurse(expr, fmt, Precedence::Mul)?;
                     if prec < Precedence::Add {
                         write!(fmt, ")")?;
                     }
                     Ok(())
                 }
+                Expr::Yields {
+                    ref expr,
+                    ref product,
+                } => {
+                    if prec < Precedence::Equals {
+                        write!(fmt, "(")?;
+                    }
+                    recurse(expr, fmt, Precedence::Range)?;
+                    write!(fmt, " yields ")?;
+                    write_ident(product, fmt)?;
+                    if prec < Precedence::Equals {
+                        write!(fmt, ")")?;
+                    }
+                    Ok(())
+                }
+                Expr::Mixture { ref expr, by } => {
+                    if prec < Precedence::Equals {
+                        write!(fmt, "(")?;
+                    }
+                    recurse(expr, fmt, Precedence::Range)?;
+                    write!(fmt, " by {}", by)?;
+                    if prec < Precedence::Equals {
+                        write!(fmt, ")")?;
+                    }
+                    Ok(())
+                }
+                Expr::At {
+                    ref expr,
+                    ref states,
+                } => {
+                    if prec < Precedence::Equals {
+                        write!(fmt, "(")?;
+                    }
+                    recurse(expr, fmt, Precedence::Range)?;
+                    write!(fmt, " at ")?;
+                    for (i, state) in states.iter().enumerate() {
+                        if i > 0 {
+                            write!(fmt, ", ")?;
+                        }
+                        recurse(state, fmt, Precedence::Range)?;
+                    }
+                    if prec < Precedence::Equals {
+                        write!(fmt, ")")?;
+                    }
+                    Ok(())
+                }
+                Expr::After { ref expr, ref time } => {
+                    if prec < Precedence::Equals {
+                        write!(fmt, "(")?;
+                    }
+                    recurse(expr, fmt, Precedence::Range)?;
+                    write!(fmt, " after ")?;
+                    recurse(time, fmt, Precedence::Range)?;
+                    if prec < Precedence::Equals {
+                        write!(fmt, ")")?;
+                    }
+                    Ok(())
+                }
                 Expr::Error { ref message } => write!(fmt, "<error: {}>", message),
             }
         }
 
+        struct Wrap<'a>(&'a Expr, Precedence);
+
+        impl<'a> fmt::Display for Wrap<'a> {
+            fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+                recurse(self.0, fmt, self.1)
+            }
+        }
+
         recurse(self, fmt, Precedence::Equals)
     }
 }
@@ -4,6 +4,7 @@
 
 use crate::numeric::{Digits, Numeric};
 use chrono_tz::Tz;
+use std::convert::TryFrom;
 use std::fmt;
 
 mod def;
@@ -12,11 +13,11 @@ mod query;
 #[cfg(test)]
 mod test;
 
-pub use def::{DatePattern, Def, DefEntry, Defs, ExprString, Property};
+pub use def::{DatePattern, Def, DefEntry, Defs, ExprString, Property, PropertyTable, TablePoint};
 pub use expr::{Expr, Precedence};
-pub use query::{Conversion, Query};
+pub use query::{Calendar, Conversion, DateFormat, Query};
 
-#[derive(Debug, Clone, Serialize, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, Copy, Eq, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum Degree {
     Celsius,
@@ -27,7 +28,7 @@ pub enum Degree {
     Newton,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum DateToken {
     Literal(String),
     Number(String, Option<String>),
@@ -38,13 +39,14 @@ pub enum DateToken {
     Error(String),
 }
 
-#[derive(Debug, Clone, Serialize, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, Copy, Eq, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum BinOpType {
     Add,
     Sub,
     Frac,
     Pow,
+    Range,
     Equals,
 }
 
@@ -55,21 +57,54 @@ impl BinOpType {
             BinOpType::Sub => " - ",
             BinOpType::Frac => " / ",
             BinOpType::Pow => "^",
+            BinOpType::Range => " .. ",
             BinOpType::Equals => " = ",
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// What the fractions of a mixture like `40% ethanol + 60% water by
+/// volume` are measured by.
+#[derive(Debug, Clone, Serialize, Deserialize, Copy, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum MixtureBasis {
+    Mass,
+    Volume,
+    Mole,
+}
+
+impl MixtureBasis {
+    pub fn from_name(name: &str) -> Option<MixtureBasis> {
+        Some(match name {
+            "mass" | "weight" => MixtureBasis::Mass,
+            "volume" => MixtureBasis::Volume,
+            "mole" | "moles" | "amount" => MixtureBasis::Mole,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for MixtureBasis {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            MixtureBasis::Mass => write!(fmt, "mass"),
+            MixtureBasis::Volume => write!(fmt, "volume"),
+            MixtureBasis::Mole => write!(fmt, "mole"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct BinOpExpr {
     pub op: BinOpType,
     pub left: Box<Expr>,
     pub right: Box<Expr>,
 }
 
-#[derive(Debug, Clone, Serialize, Copy, Eq, PartialEq)]
-#[serde(rename_all = "camelCase")]
-#[serde(untagged)]
+/// Serialized as `"negative"`, `"positive"`, or the name of the degree
+/// scale, e.g. `"celsius"`.
+#[derive(Debug, Clone, Serialize, Deserialize, Copy, Eq, PartialEq)]
+#[serde(into = "String", try_from = "String")]
 pub enum UnaryOpType {
     Negative,
     Positive,
@@ -85,13 +120,47 @@ impl UnaryOpType {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl From<UnaryOpType> for String {
+    fn from(op: UnaryOpType) -> String {
+        match op {
+            UnaryOpType::Negative => "negative",
+            UnaryOpType::Positive => "positive",
+            UnaryOpType::Degree(Degree::Celsius) => "celsius",
+            UnaryOpType::Degree(Degree::Fahrenheit) => "fahrenheit",
+            UnaryOpType::Degree(Degree::Reaumur) => "reaumur",
+            UnaryOpType::Degree(Degree::Romer) => "romer",
+            UnaryOpType::Degree(Degree::Delisle) => "delisle",
+            UnaryOpType::Degree(Degree::Newton) => "newton",
+        }
+        .to_owned()
+    }
+}
+
+impl TryFrom<String> for UnaryOpType {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Ok(match &*name {
+            "negative" => UnaryOpType::Negative,
+            "positive" => UnaryOpType::Positive,
+            "celsius" => UnaryOpType::Degree(Degree::Celsius),
+            "fahrenheit" => UnaryOpType::Degree(Degree::Fahrenheit),
+            "reaumur" => UnaryOpType::Degree(Degree::Reaumur),
+            "romer" => UnaryOpType::Degree(Degree::Romer),
+            "delisle" => UnaryOpType::Degree(Degree::Delisle),
+            "newton" => UnaryOpType::Degree(Degree::Newton),
+            _ => return Err(format!("Unknown unary operator {}", name)),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct UnaryOpExpr {
     pub op: UnaryOpType,
     pub expr: Box<Expr>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum Function {
     Sqrt,
@@ -114,6 +183,7 @@ pub enum Function {
     Log,
     Hypot,
     Atan2,
+    Overlap,
 }
 
 impl Function {
@@ -139,6 +209,7 @@ impl Function {
             Function::Log => "log",
             Function::Hypot => "hypot",
             Function::Atan2 => "atan2",
+            Function::Overlap => "overlap",
         }
     }
 
@@ -164,6 +235,7 @@ impl Function {
             "log" => Function::Log,
             "hypot" => Function::Hypot,
             "atan2" => Function::Atan2,
+            "overlap" => Function::Overlap,
             _ => return None,
         };
         Some(func)
@@ -184,6 +256,18 @@ impl fmt::Display for Degree {
 }
 
 impl Degree {
+    pub fn from_name(name: &str) -> Option<Degree> {
+        match name {
+            "degC" | "°C" | "celsius" | "℃" => Some(Degree::Celsius),
+            "degF" | "°F" | "fahrenheit" | "℉" => Some(Degree::Fahrenheit),
+            "degRé" | "°Ré" | "degRe" | "°Re" | "réaumur" | "reaumur" => Some(Degree::Reaumur),
+            "degRø" | "°Rø" | "degRo" | "°Ro" | "rømer" | "romer" => Some(Degree::Romer),
+            "degDe" | "°De" | "delisle" => Some(Degree::Delisle),
+            "degN" | "°N" | "degnewton" => Some(Degree::Newton),
+            _ => None,
+        }
+    }
+
     pub fn name_base_scale(&self) -> (&str, &str, &str) {
         match *self {
             Degree::Celsius => ("C", "zerocelsius", "kelvin"),
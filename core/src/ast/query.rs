@@ -1,17 +1,113 @@
 use super::*;
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Conversion {
     None,
     Expr(Expr),
     Degree(Degree),
     List(Vec<String>),
     Offset(i64),
-    #[serde(skip)]
-    Timezone(Tz),
+    Timezone(#[serde(with = "tz_name")] Tz),
+    DateFormat(DateFormat),
+    Calendar(Calendar),
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// Textual representations that dates can be converted to, like
+/// `-> unix` or `-> strftime '%A'`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DateFormat {
+    /// Seconds since the Unix epoch.
+    Unix,
+    /// Milliseconds since the Unix epoch.
+    UnixMillis,
+    Iso8601,
+    /// ISO 8601 week date, such as `2020-W20-3`.
+    IsoWeek,
+    /// ISO 8601 ordinal date, such as `2020-134`.
+    IsoOrdinal,
+    /// Just the day of the year, such as `134`.
+    DayOfYear,
+    Rfc2822,
+    JulianDay,
+    ModifiedJulianDay,
+    /// International Atomic Time, which has no leap seconds.
+    Tai,
+    /// Terrestrial Time, TAI + 32.184 s.
+    Tt,
+    /// GPS time, TAI - 19 s.
+    Gps,
+    /// A user supplied strftime pattern.
+    Strftime(String),
+}
+
+impl DateFormat {
+    /// Looks up the keyword used after `->`. `strftime` is handled by
+    /// the parser since it takes an argument.
+    pub fn from_name(name: &str) -> Option<DateFormat> {
+        Some(match name {
+            "unix" => DateFormat::Unix,
+            "unixms" => DateFormat::UnixMillis,
+            "iso8601" => DateFormat::Iso8601,
+            "isoweek" => DateFormat::IsoWeek,
+            "isoordinal" => DateFormat::IsoOrdinal,
+            "dayofyear" => DateFormat::DayOfYear,
+            "rfc2822" => DateFormat::Rfc2822,
+            "jd" => DateFormat::JulianDay,
+            "mjd" => DateFormat::ModifiedJulianDay,
+            "tai" => DateFormat::Tai,
+            "tt" => DateFormat::Tt,
+            "gps" => DateFormat::Gps,
+            _ => return None,
+        })
+    }
+}
+
+/// Calendars other than the proleptic Gregorian one, which dates can be
+/// converted to with `-> hebrew` and written in like `#15 Nisan 5784 AM#`.
+/// See `calendar.rs`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Calendar {
+    Julian,
+    Hebrew,
+    /// The tabular Islamic calendar, which can differ by a day or two
+    /// from calendars based on sighting the moon.
+    Islamic,
+    /// The arithmetic Persian calendar, which matches the official
+    /// astronomical one for the years 1178 to 1634 AP.
+    Persian,
+}
+
+impl Calendar {
+    pub fn from_name(name: &str) -> Option<Calendar> {
+        Some(match name {
+            "julian" => Calendar::Julian,
+            "hebrew" | "jewish" => Calendar::Hebrew,
+            "islamic" | "hijri" => Calendar::Islamic,
+            "persian" | "jalali" => Calendar::Persian,
+            _ => return None,
+        })
+    }
+}
+
+/// Timezones are serialized by their IANA name.
+mod tz_name {
+    use chrono_tz::Tz;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(tz: &Tz, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(tz.name())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Tz, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(D::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "type", content = "value")]
 pub enum Query {
@@ -20,6 +116,26 @@ pub enum Query {
     Factorize(Expr),
     UnitsFor(Expr),
     Search(String),
+    /// `[calendar] businessdays between <start> and <end>`.
+    BusinessDays(Expr, Expr, Option<String>),
+    /// `every <step> from <start> until <end>`.
+    Recurrence(Expr, Expr, Expr),
+    /// `datepatterns #date#`, shows how a date literal fares against
+    /// each date pattern.
+    DatePatterns(Vec<DateToken>),
+    /// `composition of <formula>`, optionally with an amount of the
+    /// formula like `composition of 10 g C3H8`.
+    Composition(Expr),
+    /// `properties of <substance>`, lists the substance's properties.
+    Properties(Expr),
+    /// `substances with <property>`, lists the substances that have a
+    /// property.
+    SubstancesWith(String),
+    /// `dilute <volume> of <concentration> to <concentration>`.
+    Dilution(Expr, Expr),
+    /// `balance <reactants> -> <products> [for <amount>]`, with each
+    /// side a list of chemical formulas separated by `+`.
+    Balance(Vec<String>, Vec<String>, Option<Expr>),
     Error(String),
 }
 
@@ -39,6 +155,45 @@ impl fmt::Display for Conversion {
             }
             Conversion::Offset(off) => write!(fmt, "{:02}:{:02}", off / 3600, (off / 60) % 60),
             Conversion::Timezone(ref tz) => write!(fmt, "{:?}", tz),
+            Conversion::DateFormat(ref format) => write!(fmt, "{}", format),
+            Conversion::Calendar(ref calendar) => write!(fmt, "{}", calendar),
+        }
+    }
+}
+
+impl fmt::Display for DateFormat {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DateFormat::Unix => write!(fmt, "unix"),
+            DateFormat::UnixMillis => write!(fmt, "unixms"),
+            DateFormat::Iso8601 => write!(fmt, "iso8601"),
+            DateFormat::IsoWeek => write!(fmt, "isoweek"),
+            DateFormat::IsoOrdinal => write!(fmt, "isoordinal"),
+            DateFormat::DayOfYear => write!(fmt, "dayofyear"),
+            DateFormat::Rfc2822 => write!(fmt, "rfc2822"),
+            DateFormat::JulianDay => write!(fmt, "jd"),
+            DateFormat::ModifiedJulianDay => write!(fmt, "mjd"),
+            DateFormat::Tai => write!(fmt, "tai"),
+            DateFormat::Tt => write!(fmt, "tt"),
+            DateFormat::Gps => write!(fmt, "gps"),
+            DateFormat::Strftime(ref pattern) => write!(
+                fmt,
+                "strftime {}",
+                Expr::Quote {
+                    string: pattern.clone()
+                }
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Calendar {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Calendar::Julian => write!(fmt, "julian"),
+            Calendar::Hebrew => write!(fmt, "hebrew"),
+            Calendar::Islamic => write!(fmt, "islamic"),
+            Calendar::Persian => write!(fmt, "persian"),
         }
     }
 }
@@ -7,9 +7,9 @@ use num::cast::ToPrimitive;
 use num::traits::{Num, One, Zero};
 use std::cmp::Ord;
 use std::fmt;
-use std::ops::{Div, Mul, Rem};
+use std::ops::{Div, Mul, Rem, Sub};
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct BigInt {
     inner: NumInt,
 }
@@ -52,6 +52,16 @@ impl BigInt {
         }
     }
 
+    /// The number of bits needed to represent the magnitude.
+    pub fn bits(&self) -> u64 {
+        self.inner.bits()
+    }
+
+    /// The number of factors of two, or None for zero.
+    pub fn trailing_zeros(&self) -> Option<u64> {
+        self.inner.trailing_zeros()
+    }
+
     pub fn size_in_base(&self, base: u8) -> usize {
         1 + ((self.inner.bits()) as f64 * std::f64::consts::LN_2 / (base as f64).ln()).floor()
             as usize
@@ -100,6 +110,16 @@ impl<'a> Mul for &'a BigInt {
     }
 }
 
+impl<'a> Sub for &'a BigInt {
+    type Output = BigInt;
+
+    fn sub(self, rhs: &'a BigInt) -> BigInt {
+        BigInt {
+            inner: &self.inner - &rhs.inner,
+        }
+    }
+}
+
 impl<'a> Div for &'a BigInt {
     type Output = BigInt;
 
@@ -36,6 +36,42 @@ impl BigRat {
         }
     }
 
+    /// Constructs `mantissa * 10^exponent`. This avoids the gcd that
+    /// `ratio` performs, which is very slow for large powers of ten.
+    pub fn from_decimal(mantissa: &BigInt, exponent: i32) -> BigRat {
+        let mut numer = mantissa.inner().clone();
+        if exponent >= 0 || numer.is_zero() {
+            numer *= num::BigInt::from(10u32).pow(exponent.unsigned_abs());
+            return BigRat {
+                inner: NumRat::from_integer(numer),
+            };
+        }
+        // The denominator is 2^twos * 5^fives, so cancelling those
+        // factors from the numerator leaves the fraction in lowest terms.
+        let (two, five) = (num::BigInt::from(2u32), num::BigInt::from(5u32));
+        let (mut twos, mut fives) = (exponent.unsigned_abs(), exponent.unsigned_abs());
+        while twos > 0 && (&numer % &two).is_zero() {
+            numer /= &two;
+            twos -= 1;
+        }
+        while fives > 0 && (&numer % &five).is_zero() {
+            numer /= &five;
+            fives -= 1;
+        }
+        let denom = two.pow(twos) * five.pow(fives);
+        BigRat {
+            inner: NumRat::new_raw(numer, denom),
+        }
+    }
+
+    /// Raises the fraction to a power. The result doesn't need to be
+    /// reduced, which makes this much faster than using `ratio`.
+    pub fn pow(&self, exp: u32) -> BigRat {
+        BigRat {
+            inner: NumRat::new_raw(self.inner.numer().pow(exp), self.inner.denom().pow(exp)),
+        }
+    }
+
     pub fn small_ratio(numerator: i64, denominator: i64) -> BigRat {
         BigRat {
             inner: NumRat::new(
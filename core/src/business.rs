@@ -0,0 +1,493 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Business day calendars, used for queries like
+//! `#2024-03-01# + 10 businessdays` and
+//! `businessdays between #2024-03-01# and #2024-04-01#`.
+//!
+//! Only whole days are counted; there are no working hours within a
+//! day.
+
+use crate::date::{self, GenericDateTime};
+use crate::limits::LimitExceeded;
+use crate::reply::{HolidayError, QueryError};
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use std::iter::successors;
+use std::str::FromStr;
+
+/// Longest span that business days are counted over, about 4000 years.
+/// Days are walked one at a time, each charged as a step against
+/// `Context::limits`, and this bounds them when there are no limits.
+const MAX_DAYS: i64 = 1_500_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HolidayRule {
+    /// A single date.
+    Date(NaiveDate),
+    /// The same month and day every year.
+    Yearly { month: u32, day: u32 },
+    /// The nth weekday of a month, like the 4th Thursday of November.
+    /// Negative values count from the end of the month, so -1 is the
+    /// last one.
+    NthWeekday {
+        month: u32,
+        nth: i32,
+        weekday: Weekday,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Holiday {
+    pub name: String,
+    pub rule: HolidayRule,
+}
+
+/// Which days of the week are worked, and which dates are holidays.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BusinessCalendar {
+    pub name: String,
+    pub weekend: Vec<Weekday>,
+    pub holidays: Vec<Holiday>,
+}
+
+impl Default for BusinessCalendar {
+    /// Saturday and Sunday off, with no holidays.
+    fn default() -> BusinessCalendar {
+        BusinessCalendar {
+            name: "default".to_owned(),
+            weekend: vec![Weekday::Sat, Weekday::Sun],
+            holidays: vec![],
+        }
+    }
+}
+
+impl HolidayRule {
+    pub fn matches(&self, date: NaiveDate) -> bool {
+        match *self {
+            HolidayRule::Date(day) => day == date,
+            HolidayRule::Yearly { month, day } => date.month() == month && date.day() == day,
+            HolidayRule::NthWeekday {
+                month,
+                nth,
+                weekday,
+            } => {
+                if date.month() != month || date.weekday() != weekday {
+                    return false;
+                }
+                let index = (date.day() as i32 - 1) / 7 + 1;
+                if nth > 0 {
+                    index == nth
+                } else {
+                    let last = date::days_in_month(date.year(), month).unwrap_or(31) as i32;
+                    let from_end = (last - date.day() as i32) / 7 + 1;
+                    from_end == -nth
+                }
+            }
+        }
+    }
+}
+
+impl BusinessCalendar {
+    pub fn is_business_day(&self, date: NaiveDate) -> bool {
+        !self.weekend.contains(&date.weekday())
+            && !self
+                .holidays
+                .iter()
+                .any(|holiday| holiday.rule.matches(date))
+    }
+
+    fn check(&self) -> Result<(), QueryError> {
+        let mut days = self
+            .weekend
+            .iter()
+            .map(|day| day.num_days_from_monday())
+            .collect::<Vec<_>>();
+        days.sort_unstable();
+        days.dedup();
+        if days.len() >= 7 {
+            Err(QueryError::generic(format!(
+                "Business calendar {} has no working days",
+                self.name
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Moves `date` forward by `days` business days, or backward if it
+    /// is negative. The starting day isn't counted, so adding 1 to a
+    /// Friday gives the following Monday. `step` is called for each day
+    /// walked.
+    pub fn add_business_days(
+        &self,
+        date: NaiveDate,
+        days: i64,
+        step: &dyn Fn() -> Result<(), LimitExceeded>,
+    ) -> Result<NaiveDate, QueryError> {
+        self.check()?;
+        let direction = if days < 0 { -1 } else { 1 };
+        let mut date = date;
+        let mut remaining = days.abs();
+        let mut walked = 0;
+        while remaining > 0 {
+            walked += 1;
+            if walked > MAX_DAYS {
+                return Err(QueryError::generic("Too many business days".to_owned()));
+            }
+            step()?;
+            date = date
+                .checked_add_signed(Duration::days(direction))
+                .ok_or_else(|| QueryError::generic("Date is out of range".to_owned()))?;
+            if self.is_business_day(date) {
+                remaining -= 1;
+            }
+        }
+        Ok(date)
+    }
+
+    /// Counts the business days after `start` up to and including
+    /// `end`, so that adding the result to `start` lands on `end` when
+    /// it is a business day. Negative when `end` is before `start`.
+    /// `step` is called for each day walked.
+    pub fn business_days_between(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+        step: &dyn Fn() -> Result<(), LimitExceeded>,
+    ) -> Result<i64, QueryError> {
+        self.check()?;
+        let (from, to, sign) = if end < start {
+            (end, start, -1)
+        } else {
+            (start, end, 1)
+        };
+        if to.signed_duration_since(from).num_days() > MAX_DAYS {
+            return Err(QueryError::generic(
+                "Date range is too long to count business days".to_owned(),
+            ));
+        }
+        let mut count = 0;
+        for date in
+            successors(from.succ_opt(), |date| date.succ_opt()).take_while(|&date| date <= to)
+        {
+            step()?;
+            if self.is_business_day(date) {
+                count += 1;
+            }
+        }
+        Ok(sign * count)
+    }
+}
+
+/// Adds business days to a date, keeping its time of day and timezone.
+pub fn add_business_days(
+    date: &GenericDateTime,
+    days: i64,
+    calendar: &BusinessCalendar,
+    step: &dyn Fn() -> Result<(), LimitExceeded>,
+) -> Result<GenericDateTime, QueryError> {
+    let mut result = Ok(());
+    let out = date::map_local(date, |naive| {
+        match calendar.add_business_days(naive.date(), days, step) {
+            Ok(day) => Some(day.and_time(naive.time())),
+            Err(e) => {
+                result = Err(e);
+                None
+            }
+        }
+    });
+    result.and(out.map_err(QueryError::generic))
+}
+
+/// Counts business days between two dates, see
+/// `BusinessCalendar::business_days_between`. The calendar date of
+/// `right` is taken in the timezone of `left`.
+pub fn business_days_between(
+    left: &GenericDateTime,
+    right: &GenericDateTime,
+    calendar: &BusinessCalendar,
+    step: &dyn Fn() -> Result<(), LimitExceeded>,
+) -> Result<i64, QueryError> {
+    let (left, right) = date::local_pair(left, right);
+    calendar.business_days_between(left.date(), right.date(), step)
+}
+
+fn parse_weekday(word: &str) -> Option<Weekday> {
+    Weekday::from_str(word).ok()
+}
+
+fn parse_rule(words: &[&str]) -> Option<(HolidayRule, usize)> {
+    let first = *words.first()?;
+    if let Some(rest) = first.strip_prefix("--") {
+        let mut parts = rest.split('-');
+        let month = parts.next()?.parse::<u32>().ok()?;
+        if !(1..=12).contains(&month) {
+            return None;
+        }
+        return match parts.next() {
+            Some(day) => {
+                let day = day.parse::<u32>().ok()?;
+                // Checked against a leap year so that --02-29 is allowed.
+                NaiveDate::from_ymd_opt(2000, month, day)?;
+                Some((HolidayRule::Yearly { month, day }, 1))
+            }
+            None => {
+                let nth = words.get(1)?.parse::<i32>().ok()?;
+                if nth == 0 || nth.abs() > 5 {
+                    return None;
+                }
+                let weekday = parse_weekday(words.get(2)?)?;
+                Some((
+                    HolidayRule::NthWeekday {
+                        month,
+                        nth,
+                        weekday,
+                    },
+                    3,
+                ))
+            }
+        };
+    }
+    let date = NaiveDate::parse_from_str(first, "%Y-%m-%d").ok()?;
+    Some((HolidayRule::Date(date), 1))
+}
+
+/// Parses the format of `holidays.txt`. Each calendar starts with a
+/// `calendar <name>` line, optionally followed by `weekend <days>`, and
+/// then one holiday per line. Lines that can't be parsed are skipped
+/// and returned as errors.
+pub fn parse_holidays(file: &str) -> (Vec<BusinessCalendar>, Vec<HolidayError>) {
+    let mut calendars: Vec<BusinessCalendar> = vec![];
+    let mut errors = vec![];
+    for (num, line) in file.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let message = match (words[0], calendars.last_mut()) {
+            ("calendar", _) if words.len() == 2 => {
+                calendars.push(BusinessCalendar {
+                    name: words[1].to_owned(),
+                    ..BusinessCalendar::default()
+                });
+                continue;
+            }
+            ("calendar", _) => "Expected a calendar name",
+            (_, None) => "Expected a calendar line first",
+            ("weekend", Some(calendar)) => {
+                let weekend = words[1..]
+                    .iter()
+                    .map(|word| parse_weekday(word))
+                    .collect::<Option<Vec<_>>>();
+                match weekend {
+                    Some(weekend) => {
+                        calendar.weekend = weekend;
+                        continue;
+                    }
+                    None => "Expected weekday names",
+                }
+            }
+            (_, Some(calendar)) => match parse_rule(&words) {
+                Some((rule, len)) => {
+                    calendar.holidays.push(Holiday {
+                        name: words[len..].join(" "),
+                        rule,
+                    });
+                    continue;
+                }
+                None => "Expected a date, --MM-DD or --MM N weekday",
+            },
+        };
+        errors.push(HolidayError {
+            line: num + 1,
+            text: line.to_owned(),
+            message: message.to_owned(),
+        });
+    }
+    (calendars, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn us() -> BusinessCalendar {
+        parse_holidays(crate::HOLIDAYS_FILE)
+            .0
+            .into_iter()
+            .find(|cal| cal.name == "us")
+            .unwrap()
+    }
+
+    fn unlimited() -> Result<(), LimitExceeded> {
+        Ok(())
+    }
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    #[test]
+    fn test_parse_holidays() {
+        let (cals, errors) = parse_holidays(
+            "calendar test\n\
+             weekend fri sat\n\
+             2024-05-06 One off\n\
+             --12-25 Christmas # comment\n\
+             --05 -1 mon Memorial Day\n",
+        );
+        assert_eq!(cals.len(), 1);
+        assert!(errors.is_empty());
+        assert_eq!(cals[0].weekend, vec![Weekday::Fri, Weekday::Sat]);
+        assert_eq!(
+            cals[0].holidays,
+            vec![
+                Holiday {
+                    name: "One off".to_owned(),
+                    rule: HolidayRule::Date(ymd(2024, 5, 6)),
+                },
+                Holiday {
+                    name: "Christmas".to_owned(),
+                    rule: HolidayRule::Yearly { month: 12, day: 25 },
+                },
+                Holiday {
+                    name: "Memorial Day".to_owned(),
+                    rule: HolidayRule::NthWeekday {
+                        month: 5,
+                        nth: -1,
+                        weekday: Weekday::Mon,
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_holiday_errors() {
+        let (cals, errors) = parse_holidays(
+            "--12-25 Before any calendar\n\
+             \n\
+             calendar test\n\
+             weekend someday\n\
+             --13-01 Bad month\n\
+             calendar\n",
+        );
+        assert_eq!(cals.len(), 1);
+        assert_eq!(cals[0].weekend, vec![Weekday::Sat, Weekday::Sun]);
+        assert!(cals[0].holidays.is_empty());
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.line, &*error.message))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, "Expected a calendar line first"),
+                (4, "Expected weekday names"),
+                (5, "Expected a date, --MM-DD or --MM N weekday"),
+                (6, "Expected a calendar name"),
+            ]
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "Line 4: Expected weekday names: weekend someday"
+        );
+    }
+
+    #[test]
+    fn test_holiday_rules() {
+        let cal = us();
+        // Thanksgiving 2024, the 4th Thursday of November.
+        assert!(!cal.is_business_day(ymd(2024, 11, 28)));
+        assert!(cal.is_business_day(ymd(2024, 11, 21)));
+        // Memorial Day 2024, the last Monday of May.
+        assert!(!cal.is_business_day(ymd(2024, 5, 27)));
+        assert!(cal.is_business_day(ymd(2024, 5, 20)));
+        assert!(!cal.is_business_day(ymd(2024, 7, 4)));
+        assert!(!cal.is_business_day(ymd(2024, 7, 6)));
+    }
+
+    #[test]
+    fn test_add_business_days() {
+        let cal = BusinessCalendar::default();
+        // Friday.
+        let fri = ymd(2024, 3, 1);
+        assert_eq!(
+            cal.add_business_days(fri, 1, &unlimited).ok(),
+            Some(ymd(2024, 3, 4))
+        );
+        assert_eq!(
+            cal.add_business_days(fri, 10, &unlimited).ok(),
+            Some(ymd(2024, 3, 15))
+        );
+        assert_eq!(
+            cal.add_business_days(fri, -1, &unlimited).ok(),
+            Some(ymd(2024, 2, 29))
+        );
+        assert_eq!(cal.add_business_days(fri, 0, &unlimited).ok(), Some(fri));
+        // Starting on a weekend.
+        assert_eq!(
+            cal.add_business_days(ymd(2024, 3, 2), 1, &unlimited).ok(),
+            Some(ymd(2024, 3, 4))
+        );
+        assert_eq!(
+            us().add_business_days(ymd(2024, 12, 24), 1, &unlimited)
+                .ok(),
+            Some(ymd(2024, 12, 26))
+        );
+        assert!(cal.add_business_days(fri, i64::MAX, &unlimited).is_err());
+        let never = BusinessCalendar {
+            weekend: vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+                Weekday::Sat,
+                Weekday::Sun,
+            ],
+            ..BusinessCalendar::default()
+        };
+        assert!(never.add_business_days(fri, 1, &unlimited).is_err());
+        // Repeated days only count once.
+        let repeated = BusinessCalendar {
+            weekend: vec![
+                Weekday::Sat,
+                Weekday::Sun,
+                Weekday::Sat,
+                Weekday::Sun,
+                Weekday::Sat,
+                Weekday::Sun,
+                Weekday::Sat,
+            ],
+            ..BusinessCalendar::default()
+        };
+        assert_eq!(
+            repeated.add_business_days(fri, 1, &unlimited).ok(),
+            Some(ymd(2024, 3, 4))
+        );
+    }
+
+    #[test]
+    fn test_business_days_between() {
+        let cal = BusinessCalendar::default();
+        let start = ymd(2024, 3, 1);
+        let end = ymd(2024, 4, 1);
+        assert_eq!(
+            cal.business_days_between(start, end, &unlimited).ok(),
+            Some(21)
+        );
+        assert_eq!(
+            cal.business_days_between(end, start, &unlimited).ok(),
+            Some(-21)
+        );
+        assert_eq!(
+            cal.business_days_between(start, start, &unlimited).ok(),
+            Some(0)
+        );
+        let later = cal.add_business_days(start, 21, &unlimited).unwrap();
+        assert_eq!(later, end);
+    }
+}
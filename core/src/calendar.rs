@@ -0,0 +1,551 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Conversions between the proleptic Gregorian calendar that dates are
+//! stored in and the calendars in `ast::Calendar`, used for `-> hebrew`
+//! and date literals like `#15 Nisan 5784 AM#`.
+//!
+//! Dates are converted through fixed day numbers, where day 1 is
+//! 0001-01-01 in the Gregorian calendar. The algorithms follow
+//! Calendrical Calculations by Reingold and Dershowitz, except for the
+//! Persian calendar which uses the 33 year cycle that ICU does. Years
+//! are astronomical, so 1 BC is year 0.
+
+use crate::ast::Calendar;
+use chrono::{Datelike, NaiveDate};
+use std::convert::TryFrom;
+use std::fmt;
+
+const JULIAN_EPOCH: i64 = -1;
+const HEBREW_EPOCH: i64 = -1_373_427;
+const ISLAMIC_EPOCH: i64 = 227_015;
+const PERSIAN_EPOCH: i64 = 226_895;
+
+const GREGORIAN_MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Numbered from Nisan as in the Torah, although the year starts in
+/// Tishrei. In leap years the 12th month is Adar I.
+const HEBREW_MONTHS: [&str; 13] = [
+    "Nisan", "Iyyar", "Sivan", "Tammuz", "Av", "Elul", "Tishrei", "Heshvan", "Kislev", "Tevet",
+    "Shevat", "Adar", "Adar II",
+];
+
+const ISLAMIC_MONTHS: [&str; 12] = [
+    "Muharram",
+    "Safar",
+    "Rabi I",
+    "Rabi II",
+    "Jumada I",
+    "Jumada II",
+    "Rajab",
+    "Shaban",
+    "Ramadan",
+    "Shawwal",
+    "Dhul Qadah",
+    "Dhul Hijjah",
+];
+
+const PERSIAN_MONTHS: [&str; 12] = [
+    "Farvardin",
+    "Ordibehesht",
+    "Khordad",
+    "Tir",
+    "Mordad",
+    "Shahrivar",
+    "Mehr",
+    "Aban",
+    "Azar",
+    "Dey",
+    "Bahman",
+    "Esfand",
+];
+
+/// Other spellings accepted in date literals.
+const MONTH_ALIASES: [(&str, Calendar, u32); 6] = [
+    ("Iyar", Calendar::Hebrew, 2),
+    ("Tishri", Calendar::Hebrew, 7),
+    ("Cheshvan", Calendar::Hebrew, 8),
+    ("Marheshvan", Calendar::Hebrew, 8),
+    ("Shvat", Calendar::Hebrew, 11),
+    ("Adar I", Calendar::Hebrew, 12),
+];
+
+/// A date in one of the calendars in `Calendar`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CalendarDate {
+    pub calendar: Calendar,
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+fn julian_leap(year: i64) -> bool {
+    year.rem_euclid(4) == 0
+}
+
+fn julian_to_fixed(year: i64, month: u32, day: u32) -> i64 {
+    let correction = if month <= 2 {
+        0
+    } else if julian_leap(year) {
+        -1
+    } else {
+        -2
+    };
+    JULIAN_EPOCH - 1
+        + 365 * (year - 1)
+        + (year - 1).div_euclid(4)
+        + (367 * i64::from(month) - 362) / 12
+        + correction
+        + i64::from(day)
+}
+
+fn julian_from_fixed(fixed: i64) -> (i64, u32, u32) {
+    let year = (4 * (fixed - JULIAN_EPOCH) + 1464).div_euclid(1461);
+    let prior_days = fixed - julian_to_fixed(year, 1, 1);
+    let correction = if fixed < julian_to_fixed(year, 3, 1) {
+        0
+    } else if julian_leap(year) {
+        1
+    } else {
+        2
+    };
+    let month = ((12 * (prior_days + correction) + 373) / 367) as u32;
+    let day = fixed - julian_to_fixed(year, month, 1) + 1;
+    (year, month, day as u32)
+}
+
+fn julian_month_length(year: i64, month: u32) -> u32 {
+    match month {
+        2 if julian_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn hebrew_leap(year: i64) -> bool {
+    (7 * year + 1).rem_euclid(19) < 7
+}
+
+fn hebrew_last_month(year: i64) -> u32 {
+    if hebrew_leap(year) {
+        13
+    } else {
+        12
+    }
+}
+
+/// Days from the epoch to the molad of Tishrei, postponed when it falls
+/// on a Sunday, Wednesday or Friday.
+fn hebrew_elapsed_days(year: i64) -> i64 {
+    let months = (235 * year - 234).div_euclid(19);
+    let parts = 12084 + 13753 * months;
+    let days = 29 * months + parts.div_euclid(25920);
+    if (3 * (days + 1)).rem_euclid(7) < 3 {
+        days + 1
+    } else {
+        days
+    }
+}
+
+/// Further postponements that keep years to an allowed length.
+fn hebrew_year_delay(year: i64) -> i64 {
+    let before = hebrew_elapsed_days(year - 1);
+    let start = hebrew_elapsed_days(year);
+    let after = hebrew_elapsed_days(year + 1);
+    if after - start == 356 {
+        2
+    } else if start - before == 382 {
+        1
+    } else {
+        0
+    }
+}
+
+fn hebrew_new_year(year: i64) -> i64 {
+    HEBREW_EPOCH + hebrew_elapsed_days(year) + hebrew_year_delay(year)
+}
+
+fn hebrew_month_length(year: i64, month: u32) -> u32 {
+    let year_length = hebrew_new_year(year + 1) - hebrew_new_year(year);
+    match month {
+        2 | 4 | 6 | 10 | 13 => 29,
+        12 if !hebrew_leap(year) => 29,
+        // Heshvan is long in complete years, of 355 or 385 days.
+        8 if year_length % 10 != 5 => 29,
+        // Kislev is short in deficient years, of 353 or 383 days.
+        9 if year_length % 10 == 3 => 29,
+        _ => 30,
+    }
+}
+
+fn hebrew_to_fixed(year: i64, month: u32, day: u32) -> i64 {
+    let months_before = if month < 7 {
+        (7..=hebrew_last_month(year))
+            .chain(1..month)
+            .collect::<Vec<_>>()
+    } else {
+        (7..month).collect()
+    };
+    hebrew_new_year(year)
+        + months_before
+            .into_iter()
+            .map(|month| i64::from(hebrew_month_length(year, month)))
+            .sum::<i64>()
+        + i64::from(day)
+        - 1
+}
+
+fn hebrew_from_fixed(fixed: i64) -> (i64, u32, u32) {
+    // 35975351 / 98496 is the average length of a year in days.
+    let mut year = (98_496 * (fixed - HEBREW_EPOCH)).div_euclid(35_975_351);
+    while hebrew_new_year(year + 1) <= fixed {
+        year += 1;
+    }
+    let first = if fixed < hebrew_to_fixed(year, 1, 1) {
+        7
+    } else {
+        1
+    };
+    let last = hebrew_last_month(year);
+    let month = (first..=last)
+        .find(|&month| {
+            fixed < hebrew_to_fixed(year, month, 1) + i64::from(hebrew_month_length(year, month))
+        })
+        .unwrap_or(last);
+    let day = fixed - hebrew_to_fixed(year, month, 1) + 1;
+    (year, month, day as u32)
+}
+
+fn islamic_leap(year: i64) -> bool {
+    (14 + 11 * year).rem_euclid(30) < 11
+}
+
+fn islamic_to_fixed(year: i64, month: u32, day: u32) -> i64 {
+    let month = i64::from(month);
+    i64::from(day)
+        + 29 * (month - 1)
+        + (6 * month - 1) / 11
+        + (year - 1) * 354
+        + (3 + 11 * year).div_euclid(30)
+        + ISLAMIC_EPOCH
+        - 1
+}
+
+fn islamic_from_fixed(fixed: i64) -> (i64, u32, u32) {
+    let year = (30 * (fixed - ISLAMIC_EPOCH) + 10646).div_euclid(10631);
+    let prior_days = fixed - islamic_to_fixed(year, 1, 1);
+    let month = ((11 * prior_days + 330) / 325) as u32;
+    let day = fixed - islamic_to_fixed(year, month, 1) + 1;
+    (year, month, day as u32)
+}
+
+fn islamic_month_length(year: i64, month: u32) -> u32 {
+    if month % 2 == 1 || (month == 12 && islamic_leap(year)) {
+        30
+    } else {
+        29
+    }
+}
+
+/// Days before each month in the Persian calendar.
+const PERSIAN_MONTH_STARTS: [i64; 12] = [0, 31, 62, 93, 124, 155, 186, 216, 246, 276, 306, 336];
+
+fn persian_new_year(year: i64) -> i64 {
+    PERSIAN_EPOCH + 365 * (year - 1) + (8 * year + 21).div_euclid(33)
+}
+
+fn persian_to_fixed(year: i64, month: u32, day: u32) -> i64 {
+    persian_new_year(year) + PERSIAN_MONTH_STARTS[month as usize - 1] + i64::from(day) - 1
+}
+
+fn persian_from_fixed(fixed: i64) -> (i64, u32, u32) {
+    let year = 1 + (33 * (fixed - PERSIAN_EPOCH) + 3).div_euclid(12053);
+    let day_of_year = fixed - persian_new_year(year);
+    let month = if day_of_year < 216 {
+        day_of_year / 31
+    } else {
+        (day_of_year - 6) / 30
+    };
+    let day = day_of_year - PERSIAN_MONTH_STARTS[month as usize] + 1;
+    (year, month as u32 + 1, day as u32)
+}
+
+fn persian_month_length(year: i64, month: u32) -> u32 {
+    match month {
+        1..=6 => 31,
+        7..=11 => 30,
+        _ => (persian_new_year(year + 1) - persian_new_year(year) - 336) as u32,
+    }
+}
+
+fn months_in_year(calendar: Calendar, year: i64) -> u32 {
+    match calendar {
+        Calendar::Hebrew => hebrew_last_month(year),
+        _ => 12,
+    }
+}
+
+fn month_length(calendar: Calendar, year: i64, month: u32) -> u32 {
+    match calendar {
+        Calendar::Julian => julian_month_length(year, month),
+        Calendar::Hebrew => hebrew_month_length(year, month),
+        Calendar::Islamic => islamic_month_length(year, month),
+        Calendar::Persian => persian_month_length(year, month),
+    }
+}
+
+fn title(calendar: Calendar) -> &'static str {
+    match calendar {
+        Calendar::Julian => "Julian",
+        Calendar::Hebrew => "Hebrew",
+        Calendar::Islamic => "Islamic",
+        Calendar::Persian => "Persian",
+    }
+}
+
+impl CalendarDate {
+    pub fn from_gregorian(calendar: Calendar, date: NaiveDate) -> CalendarDate {
+        let fixed = i64::from(date.num_days_from_ce());
+        let (year, month, day) = match calendar {
+            Calendar::Julian => julian_from_fixed(fixed),
+            Calendar::Hebrew => hebrew_from_fixed(fixed),
+            Calendar::Islamic => islamic_from_fixed(fixed),
+            Calendar::Persian => persian_from_fixed(fixed),
+        };
+        CalendarDate {
+            calendar,
+            year,
+            month,
+            day,
+        }
+    }
+
+    /// Fails if the month or day doesn't exist in that year, or the
+    /// date is out of chrono's range.
+    pub fn to_gregorian(&self) -> Result<NaiveDate, String> {
+        let (calendar, year, month, day) = (self.calendar, self.year, self.month, self.day);
+        if month < 1 || month > months_in_year(calendar, year) {
+            return Err(format!(
+                "Year {} of the {} calendar has no month {}",
+                year,
+                title(calendar),
+                month
+            ));
+        }
+        let length = month_length(calendar, year, month);
+        if day < 1 || day > length {
+            return Err(format!(
+                "{} {} of the {} calendar has {} days",
+                self.month_name(),
+                year,
+                title(calendar),
+                length
+            ));
+        }
+        let fixed = match calendar {
+            Calendar::Julian => julian_to_fixed(year, month, day),
+            Calendar::Hebrew => hebrew_to_fixed(year, month, day),
+            Calendar::Islamic => islamic_to_fixed(year, month, day),
+            Calendar::Persian => persian_to_fixed(year, month, day),
+        };
+        i32::try_from(fixed)
+            .ok()
+            .and_then(NaiveDate::from_num_days_from_ce_opt)
+            .ok_or_else(|| "Date is out of range".to_owned())
+    }
+
+    pub fn month_name(&self) -> &'static str {
+        let index = self.month as usize - 1;
+        match self.calendar {
+            Calendar::Julian => GREGORIAN_MONTHS[index],
+            Calendar::Hebrew if self.month == 12 && hebrew_leap(self.year) => "Adar I",
+            Calendar::Hebrew => HEBREW_MONTHS[index],
+            Calendar::Islamic => ISLAMIC_MONTHS[index],
+            Calendar::Persian => PERSIAN_MONTHS[index],
+        }
+    }
+}
+
+impl fmt::Display for CalendarDate {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{} {} ", self.day, self.month_name())?;
+        match self.calendar {
+            Calendar::Julian if self.year <= 0 => write!(fmt, "{} BC Julian", 1 - self.year),
+            Calendar::Julian => write!(fmt, "{} Julian", self.year),
+            Calendar::Hebrew => write!(fmt, "{} AM", self.year),
+            Calendar::Islamic => write!(fmt, "{} AH", self.year),
+            Calendar::Persian => write!(fmt, "{} AP", self.year),
+        }
+    }
+}
+
+/// Recognizes the calendar names and eras that can end a date literal,
+/// like `julian` or `AH`.
+pub fn parse_calendar(word: &str) -> Option<Calendar> {
+    let word = word.to_lowercase();
+    Calendar::from_name(&word).or(match &*word {
+        "am" => Some(Calendar::Hebrew),
+        "ah" => Some(Calendar::Islamic),
+        "ap" | "sh" => Some(Calendar::Persian),
+        _ => None,
+    })
+}
+
+/// Looks up a month name from any calendar, along with the calendar it
+/// implies. Gregorian month names imply none, since the Julian calendar
+/// shares them.
+pub fn parse_month_name(name: &str) -> Option<(Option<Calendar>, u32)> {
+    let name = name.to_lowercase();
+    let find = |months: &[&str]| {
+        months
+            .iter()
+            .position(|month| month.to_lowercase() == name)
+            .map(|index| index as u32 + 1)
+    };
+    if let Some(month) = GREGORIAN_MONTHS
+        .iter()
+        .position(|month| {
+            let month = month.to_lowercase();
+            month == name || (name.len() == 3 && month.starts_with(&name))
+        })
+        .map(|index| index as u32 + 1)
+    {
+        return Some((None, month));
+    }
+    let found = find(&HEBREW_MONTHS)
+        .map(|month| (Calendar::Hebrew, month))
+        .or_else(|| find(&ISLAMIC_MONTHS).map(|month| (Calendar::Islamic, month)))
+        .or_else(|| find(&PERSIAN_MONTHS).map(|month| (Calendar::Persian, month)))
+        .or_else(|| {
+            MONTH_ALIASES
+                .iter()
+                .find(|(alias, _, _)| alias.to_lowercase() == name)
+                .map(|&(_, calendar, month)| (calendar, month))
+        });
+    found.map(|(calendar, month)| (Some(calendar), month))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    fn date(calendar: Calendar, year: i64, month: u32, day: u32) -> CalendarDate {
+        CalendarDate {
+            calendar,
+            year,
+            month,
+            day,
+        }
+    }
+
+    fn check(gregorian: NaiveDate, expected: CalendarDate) {
+        assert_eq!(
+            CalendarDate::from_gregorian(expected.calendar, gregorian),
+            expected
+        );
+        assert_eq!(expected.to_gregorian(), Ok(gregorian));
+    }
+
+    #[test]
+    fn test_julian() {
+        // The day the Gregorian calendar was adopted.
+        check(ymd(1582, 10, 15), date(Calendar::Julian, 1582, 10, 5));
+        check(ymd(2024, 4, 23), date(Calendar::Julian, 2024, 4, 10));
+        check(ymd(1, 1, 1), date(Calendar::Julian, 1, 1, 3));
+        // Julius Caesar's death, 15 March 44 BC.
+        check(ymd(-43, 3, 13), date(Calendar::Julian, -43, 3, 15));
+        // Leap in the Julian calendar but not the Gregorian one.
+        check(ymd(1900, 3, 13), date(Calendar::Julian, 1900, 2, 29));
+    }
+
+    #[test]
+    fn test_hebrew() {
+        check(ymd(2024, 4, 23), date(Calendar::Hebrew, 5784, 1, 15));
+        // Rosh Hashanah.
+        check(ymd(2023, 9, 16), date(Calendar::Hebrew, 5784, 7, 1));
+        check(ymd(2024, 10, 3), date(Calendar::Hebrew, 5785, 7, 1));
+        // Purim in a leap year is in Adar II.
+        check(ymd(2024, 3, 24), date(Calendar::Hebrew, 5784, 13, 14));
+        check(ymd(2023, 3, 7), date(Calendar::Hebrew, 5783, 12, 14));
+        assert!(date(Calendar::Hebrew, 5783, 13, 1).to_gregorian().is_err());
+    }
+
+    #[test]
+    fn test_islamic() {
+        check(ymd(622, 7, 19), date(Calendar::Islamic, 1, 1, 1));
+        check(ymd(2024, 4, 10), date(Calendar::Islamic, 1445, 10, 1));
+        check(ymd(2023, 7, 19), date(Calendar::Islamic, 1445, 1, 1));
+    }
+
+    #[test]
+    fn test_persian() {
+        // Nowruz.
+        check(ymd(2024, 3, 20), date(Calendar::Persian, 1403, 1, 1));
+        check(ymd(2025, 3, 21), date(Calendar::Persian, 1404, 1, 1));
+        // 1403 is a leap year.
+        check(ymd(2025, 3, 20), date(Calendar::Persian, 1403, 12, 30));
+        assert!(date(Calendar::Persian, 1404, 12, 30)
+            .to_gregorian()
+            .is_err());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let calendars = [
+            Calendar::Julian,
+            Calendar::Hebrew,
+            Calendar::Islamic,
+            Calendar::Persian,
+        ];
+        let mut day = ymd(1999, 1, 1);
+        while day < ymd(2030, 1, 1) {
+            for &calendar in &calendars {
+                let converted = CalendarDate::from_gregorian(calendar, day);
+                assert_eq!(converted.to_gregorian(), Ok(day), "{:?}", converted);
+            }
+            day = day.succ();
+        }
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(
+            date(Calendar::Hebrew, 5784, 12, 1).to_string(),
+            "1 Adar I 5784 AM"
+        );
+        assert_eq!(
+            date(Calendar::Julian, -43, 3, 15).to_string(),
+            "15 March 44 BC Julian"
+        );
+        assert_eq!(
+            parse_month_name("adar ii"),
+            Some((Some(Calendar::Hebrew), 13))
+        );
+        assert_eq!(
+            parse_month_name("Tishri"),
+            Some((Some(Calendar::Hebrew), 7))
+        );
+        assert_eq!(parse_month_name("sep"), Some((None, 9)));
+        assert_eq!(parse_month_name("Atlantis"), None);
+        assert_eq!(parse_calendar("AH"), Some(Calendar::Islamic));
+    }
+}
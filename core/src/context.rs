@@ -3,11 +3,15 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::ast::{DatePattern, Expr};
+use crate::business::BusinessCalendar;
+use crate::date::{self, LeapSecond};
+use crate::limits::{Budget, Limits};
 use crate::number::{Dimension, Number, Quantity};
 use crate::numeric::Numeric;
-use crate::reply::NotFoundError;
+use crate::reply::{DatePatternError, HolidayError, NotFoundError, TimezoneError};
 use crate::search;
 use crate::substance::Substance;
+use crate::timezone::{TimezoneAlias, TimezoneAliases};
 use chrono::{DateTime, TimeZone, Utc};
 use std::collections::{BTreeMap, BTreeSet};
 
@@ -25,12 +29,38 @@ pub struct Context {
     pub categories: BTreeMap<String, String>,
     pub category_names: BTreeMap<String, String>,
     pub datepatterns: Vec<Vec<DatePattern>>,
+    /// Date patterns that couldn't be loaded, from `!datepattern`
+    /// directives and from `date::parse_datefile` when the frontend
+    /// adds them.
+    pub datepattern_errors: Vec<DatePatternError>,
+    /// Used to convert between UTC and TAI, see `leapseconds.txt`.
+    pub leap_seconds: Vec<LeapSecond>,
+    /// Named calendars that can be picked with `<name> businessdays`,
+    /// see `holidays.txt`.
+    pub business_calendars: BTreeMap<String, BusinessCalendar>,
+    /// Lines of `holidays.txt` that couldn't be parsed, from
+    /// `business::parse_holidays` when the frontend adds them.
+    pub holiday_errors: Vec<HolidayError>,
+    /// Used for business day arithmetic when no calendar is named.
+    pub business_calendar: BusinessCalendar,
+    /// Abbreviations and place names usable as timezones, see
+    /// `timezones.txt`.
+    pub timezone_aliases: TimezoneAliases,
+    /// Lines of `timezones.txt` that couldn't be parsed, from
+    /// `timezone::parse_timezones` when the frontend adds them.
+    pub timezone_errors: Vec<TimezoneError>,
     pub substances: BTreeMap<String, Substance>,
     pub substance_symbols: BTreeMap<String, String>,
     pub temporaries: BTreeMap<String, Number>,
     pub now: DateTime<Utc>,
     pub short_output: bool,
     pub use_humanize: bool,
+    /// How many units relative dates are shown with, so 2 gives
+    /// `2 years, 4 months ago` where 1 gives `2 years ago`.
+    pub humanize_precision: usize,
+    /// Resource limits applied to each query, see `limits`.
+    pub limits: Limits,
+    pub(crate) budget: Budget,
 }
 
 impl Context {
@@ -39,12 +69,22 @@ impl Context {
         Context {
             short_output: false,
             use_humanize: true,
+            humanize_precision: 1,
+            limits: Limits::default(),
+            budget: Budget::default(),
 
             now: Utc.ymd(2000, 1, 1).and_hms(0, 0, 0),
 
             dimensions: BTreeSet::new(),
             prefixes: vec![],
             datepatterns: vec![],
+            datepattern_errors: vec![],
+            leap_seconds: vec![],
+            business_calendars: BTreeMap::new(),
+            holiday_errors: vec![],
+            business_calendar: BusinessCalendar::default(),
+            timezone_aliases: TimezoneAliases::default(),
+            timezone_errors: vec![],
             canonicalizations: BTreeMap::new(),
             units: BTreeMap::new(),
             quantities: BTreeMap::new(),
@@ -71,6 +111,38 @@ impl Context {
         self.datepatterns.append(&mut dates)
     }
 
+    /// Parses a date pattern in the syntax of `datepatterns.txt` and
+    /// adds it after the ones already loaded, which are tried first.
+    /// There is no query for this, so frontends that want to add
+    /// patterns from a prompt have to call it themselves.
+    pub fn add_datepattern(&mut self, pattern: &str) -> Result<(), DatePatternError> {
+        let pat = date::parse_pattern(pattern).map_err(|message| DatePatternError {
+            line: None,
+            pattern: pattern.to_owned(),
+            message,
+        })?;
+        self.datepatterns.push(pat);
+        Ok(())
+    }
+
+    pub fn load_leap_seconds(&mut self, mut leaps: Vec<LeapSecond>) {
+        self.leap_seconds.append(&mut leaps);
+        self.leap_seconds.sort_by_key(|leap| leap.utc);
+    }
+
+    pub fn load_timezones(&mut self, aliases: Vec<TimezoneAlias>) {
+        for alias in aliases {
+            self.timezone_aliases.insert(alias);
+        }
+    }
+
+    pub fn load_business_calendars(&mut self, calendars: Vec<BusinessCalendar>) {
+        for calendar in calendars {
+            self.business_calendars
+                .insert(calendar.name.clone(), calendar);
+        }
+    }
+
     /// Given a unit name, returns its value if it exists. Supports SI
     /// prefixes, plurals, bare dimensions like length, and quantities.
     pub fn lookup(&self, name: &str) -> Option<Number> {
@@ -184,9 +256,7 @@ impl Context {
     /// (e.g. you should prefix "1.0 / " or replace "multiply" with
     /// "divide" when rendering it).
     pub fn describe_unit(&self, value: &Number) -> (bool, String) {
-        use std::io::Write;
-
-        let mut buf = vec![];
+        let mut buf = String::new();
         let mut recip = false;
         let square = Number {
             value: Numeric::one(),
@@ -201,28 +271,28 @@ impl Context {
             })
             .unwrap();
         if let Some(name) = self.quantities.get(&value.unit) {
-            write!(buf, "{}", name).unwrap();
+            buf.push_str(name);
         } else if let Some(name) = square.and_then(|square| self.quantities.get(&square.unit)) {
-            write!(buf, "{}^2", name).unwrap();
+            buf.push_str(&format!("{}^2", name));
         } else if let Some(name) = self.quantities.get(&inverse.unit) {
             recip = true;
-            write!(buf, "{}", name).unwrap();
+            buf.push_str(name);
         } else {
-            let helper = |dim: &Dimension, pow: i64, buf: &mut Vec<u8>| {
+            let helper = |dim: &Dimension, pow: i64, buf: &mut String| {
                 let mut map = Quantity::new();
                 map.insert(dim.clone(), pow);
                 if let Some(name) = self.quantities.get(&map) {
-                    write!(buf, " {}", name).unwrap();
+                    buf.push_str(&format!(" {}", name));
                 } else {
                     let mut map = Quantity::new();
                     map.insert(dim.clone(), 1);
                     if let Some(name) = self.quantities.get(&map) {
-                        write!(buf, " {}", name).unwrap();
+                        buf.push_str(&format!(" {}", name));
                     } else {
-                        write!(buf, " '{}'", dim).unwrap();
+                        buf.push_str(&format!(" '{}'", dim));
                     }
                     if pow != 1 {
-                        write!(buf, "^{}", pow).unwrap();
+                        buf.push_str(&format!("^{}", pow));
                     }
                 }
             };
@@ -241,22 +311,21 @@ impl Context {
                 if !found {
                     recip = true;
                 } else {
-                    write!(buf, " /").unwrap();
+                    buf.push_str(" /");
                 }
                 for (dim, pow) in frac {
                     let mut map = Quantity::new();
                     map.insert(dim.clone(), pow);
                     if let Some(name) = self.quantities.get(&map) {
-                        write!(buf, " {}", name).unwrap();
+                        buf.push_str(&format!(" {}", name));
                     } else {
                         helper(dim, pow, &mut buf);
                     }
                 }
             }
-            buf.remove(0);
         }
 
-        (recip, String::from_utf8(buf).unwrap())
+        (recip, buf.trim_start().to_owned())
     }
 
     pub fn typo_dym<'a>(&'a self, what: &str) -> Option<&'a str> {
@@ -2,14 +2,21 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::ast::{DatePattern, DateToken};
+use crate::ast::{Calendar, DateFormat, DatePattern, DateToken};
 use crate::bigint::BigInt;
 use crate::bigrat::BigRat;
+use crate::calendar::{self, CalendarDate};
 use crate::context::Context;
+use crate::humanize;
 use crate::number::{Dimension, Number};
 use crate::numeric::Numeric;
+use crate::reply::DatePatternError;
+use crate::timezone::{TimezoneAliases, Zone};
 use chrono::format::Parsed;
-use chrono::{DateTime, Duration, FixedOffset, TimeZone, Utc, Weekday};
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone,
+    Timelike, Utc, Weekday,
+};
 use chrono_tz::Tz;
 use std::iter::Peekable;
 use std::str::FromStr;
@@ -17,6 +24,8 @@ use std::str::FromStr;
 pub fn parse_date<I>(
     out: &mut Parsed,
     out_tz: &mut Option<Tz>,
+    out_cal: &mut Option<Calendar>,
+    aliases: &TimezoneAliases,
     date: &mut Peekable<I>,
     pat: &[DatePattern],
 ) -> Result<(), String>
@@ -27,6 +36,15 @@ where
 
     let tok = date.peek().cloned();
 
+    fn number<T>(name: &str, s: &str) -> Result<T, String>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        s.parse()
+            .map_err(|e| format!("Invalid {} {}: {}", name, s, e))
+    }
+
     fn ts<T>(x: Option<T>) -> String
     where
         T: Borrow<DateToken>,
@@ -41,7 +59,7 @@ where
         ($name:expr, $digits:expr, $field:ident) => {
             match tok {
                 Some(DateToken::Number(ref s, None)) if $digits == 0 || s.len() == $digits => {
-                    let value = i32::from_str_radix(&**s, 10).unwrap();
+                    let value: i32 = number($name, s)?;
                     out.$field = Some(value as _);
                     Ok(())
                 }
@@ -96,6 +114,23 @@ where
             "ordinal" => numeric_match!("ordinal", 3, ordinal),
             "isoyear" => numeric_match!("isoyear", 4, isoyear),
             "isoweek" => numeric_match!("isoweek", 2, isoweek),
+            "isoweekday" => match tok {
+                Some(DateToken::Number(ref s, None)) if s.len() == 1 => {
+                    let value: u32 = number("isoweekday", s)?;
+                    out.weekday = Some(match value {
+                        1 => Weekday::Mon,
+                        2 => Weekday::Tue,
+                        3 => Weekday::Wed,
+                        4 => Weekday::Thu,
+                        5 => Weekday::Fri,
+                        6 => Weekday::Sat,
+                        7 => Weekday::Sun,
+                        _ => return Err(format!("Expected isoweekday from 1 to 7, got {}", s)),
+                    });
+                    Ok(())
+                }
+                x => Err(format!("Expected 1-digit isoweekday, got {}", ts(x))),
+            },
             "unix" => numeric_match!("unix", 0, timestamp),
             "year" => {
                 advance = false;
@@ -110,7 +145,7 @@ where
                     Some(x) => x,
                     None => take!(DateToken::Number(x, None), x),
                 };
-                let value = i32::from_str_radix(&*num, 10).unwrap();
+                let value: i32 = number("year", &num)?;
                 out.year = Some(value * sign);
                 Ok(())
             }
@@ -130,7 +165,7 @@ where
             },
             "hour12" => match tok {
                 Some(DateToken::Number(ref s, None)) if s.len() == 2 => {
-                    let value = u32::from_str_radix(&**s, 10).unwrap();
+                    let value: u32 = number("hour12", s)?;
                     out.hour_mod_12 = Some(value % 12);
                     Ok(())
                 }
@@ -138,7 +173,7 @@ where
             },
             "hour24" => match tok {
                 Some(DateToken::Number(ref s, None)) if s.len() == 2 => {
-                    let value = u32::from_str_radix(&**s, 10).unwrap();
+                    let value: u32 = number("hour24", s)?;
                     out.hour_div_12 = Some(value / 12);
                     out.hour_mod_12 = Some(value % 12);
                     Ok(())
@@ -158,14 +193,15 @@ where
             },
             "sec" => match tok {
                 Some(DateToken::Number(ref s, None)) if s.len() == 2 => {
-                    let value = u32::from_str_radix(&**s, 10).unwrap();
+                    let value: u32 = number("sec", s)?;
                     out.second = Some(value);
                     Ok(())
                 }
                 Some(DateToken::Number(ref s, Some(ref f))) if s.len() == 2 => {
-                    let secs = u32::from_str_radix(&**s, 10).unwrap();
-                    let nsecs =
-                        u32::from_str_radix(&**f, 10).unwrap() * 10u32.pow(9 - f.len() as u32);
+                    let secs: u32 = number("sec", s)?;
+                    // Digits beyond nanoseconds are dropped.
+                    let f = &f[..f.len().min(9)];
+                    let nsecs = number::<u32>("sec", f)? * 10u32.pow(9 - f.len() as u32);
                     out.second = Some(secs);
                     out.nanosecond = Some(nsecs);
                     Ok(())
@@ -176,11 +212,31 @@ where
                 advance = false;
                 if let Some(DateToken::Literal(ref s)) = date.peek().cloned() {
                     date.next();
-                    if let Ok(tz) = Tz::from_str(s) {
-                        *out_tz = Some(tz);
-                        Ok(())
-                    } else {
-                        Err(format!("Invalid timezone {}", s))
+                    // Place names can be several words, like New York.
+                    let mut name = s.clone();
+                    loop {
+                        let mut copy = date.clone();
+                        match (copy.next(), copy.next()) {
+                            (Some(DateToken::Space), Some(DateToken::Literal(ref word)))
+                                if aliases.contains(&format!("{} {}", name, word)) =>
+                            {
+                                name = format!("{} {}", name, word);
+                                *date = copy;
+                            }
+                            _ => break,
+                        }
+                    }
+                    match aliases.resolve(&name) {
+                        Some(Ok(Zone::Named(tz))) => {
+                            *out_tz = Some(tz);
+                            Ok(())
+                        }
+                        Some(Ok(Zone::Offset(offset))) => {
+                            out.offset = Some(offset.local_minus_utc());
+                            Ok(())
+                        }
+                        Some(Err(e)) => Err(e),
+                        None => Err(format!("Invalid timezone {}", name)),
                     }
                 } else {
                     let s = match take!(DateToken::Plus | DateToken::Dash) {
@@ -190,16 +246,21 @@ where
                     };
                     let h = take!(DateToken::Number(s, None), s);
                     if h.len() == 4 {
-                        let h = i32::from_str_radix(&*h, 10).unwrap();
+                        let h: i32 = number("offset", &h)?;
                         let m = h % 100;
                         let h = h / 100;
                         out.offset = Some(s * (h * 3600 + m * 60));
-                    } else {
-                        let h = i32::from_str_radix(&*h, 10).unwrap();
+                    } else if h.len() <= 2 {
+                        let h: i32 = number("offset", &h)?;
                         take!(DateToken::Colon);
                         let m = take!(DateToken::Number(s, None), s);
-                        let m = i32::from_str_radix(&*m, 10).unwrap();
+                        if m.len() != 2 {
+                            return Err(format!("Expected 2-digit offset minutes, got {}", m));
+                        }
+                        let m: i32 = number("offset", &m)?;
                         out.offset = Some(s * (h * 3600 + m * 60));
+                    } else {
+                        return Err(format!("Expected offset as hhmm or hh:mm, got {}", h));
                     }
                     Ok(())
                 }
@@ -226,6 +287,55 @@ where
                 }
                 x => Err(format!("Expected month name, got {}", ts(x))),
             },
+            "calmonth" => {
+                advance = false;
+                if let Some(DateToken::Literal(ref s)) = date.peek().cloned() {
+                    date.next();
+                    // Some month names are several words, like Adar II.
+                    let mut name = s.clone();
+                    loop {
+                        let mut copy = date.clone();
+                        match (copy.next(), copy.next()) {
+                            (Some(DateToken::Space), Some(DateToken::Literal(ref word))) => {
+                                let longer = format!("{} {}", name, word);
+                                if calendar::parse_month_name(&longer).is_none() {
+                                    break;
+                                }
+                                name = longer;
+                                *date = copy;
+                            }
+                            _ => break,
+                        }
+                    }
+                    match calendar::parse_month_name(&name) {
+                        Some((cal, month)) => {
+                            out.month = Some(month);
+                            if cal.is_some() {
+                                *out_cal = cal;
+                            }
+                            Ok(())
+                        }
+                        None => Err(format!("Unknown month name: {}", name)),
+                    }
+                } else {
+                    Err(format!("Expected month name, got {}", ts(tok)))
+                }
+            }
+            "calendar" => match tok {
+                Some(DateToken::Literal(ref s)) => match calendar::parse_calendar(s) {
+                    Some(cal) if out_cal.is_none() || *out_cal == Some(cal) => {
+                        *out_cal = Some(cal);
+                        Ok(())
+                    }
+                    Some(cal) => Err(format!(
+                        "The month is from the {} calendar, not {}",
+                        out_cal.unwrap(),
+                        cal
+                    )),
+                    None => Err(format!("Unknown calendar: {}", s)),
+                },
+                x => Err(format!("Expected calendar, got {}", ts(x))),
+            },
             "weekday" => match tok {
                 Some(DateToken::Literal(ref s)) => {
                     let res = match &*s.to_lowercase() {
@@ -248,7 +358,7 @@ where
         Some(&DatePattern::Optional(ref pats)) => {
             advance = false;
             let mut iter = date.clone();
-            if let Ok(()) = parse_date(out, out_tz, &mut iter, &pats[..]) {
+            if let Ok(()) = parse_date(out, out_tz, out_cal, aliases, &mut iter, &pats[..]) {
                 *date = iter
             }
             Ok(())
@@ -269,7 +379,7 @@ where
     if advance {
         date.next();
     }
-    res.and_then(|_| parse_date(out, out_tz, date, &pat[1..]))
+    res.and_then(|_| parse_date(out, out_tz, out_cal, aliases, date, &pat[1..]))
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -285,17 +395,107 @@ impl GenericDateTime {
             GenericDateTime::Timezone(ref d) => d.with_timezone(tz),
         }
     }
+
+    pub fn naive_local(&self) -> NaiveDateTime {
+        match *self {
+            GenericDateTime::Fixed(ref d) => d.naive_local(),
+            GenericDateTime::Timezone(ref d) => d.naive_local(),
+        }
+    }
+}
+
+/// The span between two dates, written `#start# .. #end#`. Both ends
+/// are included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub start: GenericDateTime,
+    pub end: GenericDateTime,
+}
+
+impl Interval {
+    pub fn new(start: GenericDateTime, end: GenericDateTime) -> Result<Interval, String> {
+        if end.with_timezone(&Utc) < start.with_timezone(&Utc) {
+            Err("Interval ends before it starts".to_string())
+        } else {
+            Ok(Interval { start, end })
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.end.with_timezone(&Utc) - self.start.with_timezone(&Utc)
+    }
+
+    pub fn contains(&self, date: &GenericDateTime) -> bool {
+        let date = date.with_timezone(&Utc);
+        self.start.with_timezone(&Utc) <= date && date <= self.end.with_timezone(&Utc)
+    }
+
+    pub fn contains_interval(&self, other: &Interval) -> bool {
+        self.contains(&other.start) && self.contains(&other.end)
+    }
+
+    /// The part of the two intervals that they have in common, if any.
+    pub fn overlap(&self, other: &Interval) -> Option<Interval> {
+        let start = if self.contains(&other.start) {
+            other.start
+        } else {
+            self.start
+        };
+        let end = if self.contains(&other.end) {
+            other.end
+        } else {
+            self.end
+        };
+        Interval::new(start, end)
+            .ok()
+            .filter(|overlap| self.contains_interval(overlap) && other.contains_interval(overlap))
+    }
+
+    /// The dates `step(0)`, `step(1)`, ... that fall within the
+    /// interval, stopping at the first one past the end. Fails if there
+    /// would be more than `limit` of them.
+    pub fn recurrence<F>(&self, limit: usize, step: F) -> Result<Vec<GenericDateTime>, String>
+    where
+        F: Fn(i32) -> Result<GenericDateTime, String>,
+    {
+        let mut dates = vec![];
+        for count in 0.. {
+            let date = step(count)?;
+            if !self.contains(&date) {
+                break;
+            }
+            if dates.len() == limit {
+                return Err(format!("Recurrence has more than {} dates", limit));
+            }
+            dates.push(date);
+        }
+        Ok(dates)
+    }
+}
+
+pub fn add_duration(date: &GenericDateTime, duration: Duration) -> Result<GenericDateTime, String> {
+    match *date {
+        GenericDateTime::Fixed(date) => date
+            .checked_add_signed(duration)
+            .map(GenericDateTime::Fixed),
+        GenericDateTime::Timezone(date) => date
+            .checked_add_signed(duration)
+            .map(GenericDateTime::Timezone),
+    }
+    .ok_or_else(|| "Date is out of range".to_string())
 }
 
 fn attempt(
     now: DateTime<Utc>,
+    aliases: &TimezoneAliases,
     date: &[DateToken],
     pat: &[DatePattern],
 ) -> Result<GenericDateTime, (String, usize)> {
     let mut parsed = Parsed::new();
     let mut tz = None;
+    let mut cal = None;
     let mut iter = date.iter().cloned().peekable();
-    let res = parse_date(&mut parsed, &mut tz, &mut iter, pat);
+    let res = parse_date(&mut parsed, &mut tz, &mut cal, aliases, &mut iter, pat);
     let count = iter.count();
     let res = if count > 0 && res.is_ok() {
         Err(format!(
@@ -310,6 +510,40 @@ fn attempt(
         res
     };
     res.map_err(|e| (e, count))?;
+    if let Some(calendar) = cal {
+        let converted = match (parsed.year, parsed.month, parsed.day) {
+            (Some(year), Some(month), Some(day)) => CalendarDate {
+                calendar,
+                year: i64::from(year),
+                month,
+                day,
+            }
+            .to_gregorian()
+            .map_err(|e| (e, count))?,
+            _ => {
+                return Err((
+                    format!(
+                        "Expected a year, month and day in the {} calendar",
+                        calendar
+                    ),
+                    count,
+                ))
+            }
+        };
+        parsed.year = Some(converted.year());
+        parsed.month = Some(converted.month());
+        parsed.day = Some(converted.day());
+    }
+    // Week dates are numbered within the ISO year, and a week date
+    // without a day means the Monday.
+    if parsed.isoweek.is_some() {
+        if parsed.isoyear.is_none() {
+            parsed.isoyear = parsed.year.take();
+        }
+        if parsed.weekday.is_none() {
+            parsed.weekday = Some(Weekday::Mon);
+        }
+    }
     let time = parsed.to_naive_time();
     let date = parsed.to_naive_date();
     if let Some(tz) = tz {
@@ -324,7 +558,16 @@ fn attempt(
                     )
                 })
                 .map(GenericDateTime::Timezone),
-            (Ok(time), Err(_)) => Ok(now.with_timezone(&tz).date().and_time(time).unwrap())
+            (Ok(time), Err(_)) => now
+                .with_timezone(&tz)
+                .date()
+                .and_time(time)
+                .ok_or_else(|| {
+                    (
+                        "Datetime does not represent a valid moment in time".to_string(),
+                        count,
+                    )
+                })
                 .map(GenericDateTime::Timezone),
             (Err(_), Ok(date)) => tz
                 .from_local_date(&date)
@@ -354,9 +597,17 @@ fn attempt(
                     )
                 })
                 .map(GenericDateTime::Fixed),
-            (Ok(time), Err(_)) => Ok(GenericDateTime::Fixed(
-                now.with_timezone(&offset).date().and_time(time).unwrap(),
-            )),
+            (Ok(time), Err(_)) => now
+                .with_timezone(&offset)
+                .date()
+                .and_time(time)
+                .ok_or_else(|| {
+                    (
+                        "Datetime does not represent a valid moment in time".to_string(),
+                        count,
+                    )
+                })
+                .map(GenericDateTime::Fixed),
             (Err(_), Ok(date)) => offset
                 .from_local_date(&date)
                 .earliest()
@@ -373,10 +624,51 @@ fn attempt(
     }
 }
 
+/// Dates that are read without going through the date patterns, like
+/// `tomorrow`, `@1700000000` or `2451545 JD`.
+pub fn builtin_date(
+    date: &[DateToken],
+    context: &Context,
+) -> Option<Result<GenericDateTime, String>> {
+    relative_date(date, context.now)
+        .or_else(|| numeric_date(date))
+        .or_else(|| time_scale_date(date, context))
+}
+
+/// Tries a date literal against each loaded pattern in order, for the
+/// `datepatterns` query.
+pub fn try_patterns<'a>(
+    date: &[DateToken],
+    context: &'a Context,
+) -> Vec<(&'a [DatePattern], Result<GenericDateTime, String>)> {
+    context
+        .datepatterns
+        .iter()
+        .map(|pat| {
+            (
+                &pat[..],
+                attempt(context.now, &context.timezone_aliases, date, pat).map_err(|(e, _)| e),
+            )
+        })
+        .collect()
+}
+
 pub fn try_decode(date: &[DateToken], context: &Context) -> Result<GenericDateTime, String> {
+    if let Some(date) = builtin_date(date, context) {
+        return date;
+    }
+    // Offsets are optional in most patterns, so an ambiguous timezone
+    // would otherwise be reported as unexpected trailing input.
+    for token in date {
+        if let DateToken::Literal(ref name) = *token {
+            if let Some(Err(e)) = context.timezone_aliases.resolve(name) {
+                return Err(e);
+            }
+        }
+    }
     let mut best = None;
     for pat in &context.datepatterns {
-        match attempt(context.now, date, pat) {
+        match attempt(context.now, &context.timezone_aliases, date, pat) {
             Ok(datetime) => return Ok(datetime),
             Err((e, c)) => {
                 //println!("{}", e);
@@ -402,6 +694,452 @@ pub fn try_decode(date: &[DateToken], context: &Context) -> Result<GenericDateTi
     }
 }
 
+/// A calendar period used by relative dates, such as the `month` in
+/// `end of month` or `3 months ago`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Period {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl Period {
+    fn from_name(name: &str) -> Option<Period> {
+        let name = if name.len() > 1 {
+            name.strip_suffix('s').unwrap_or(name)
+        } else {
+            name
+        };
+        Some(match name {
+            "second" | "sec" => Period::Second,
+            "minute" | "min" => Period::Minute,
+            "hour" | "hr" => Period::Hour,
+            "day" => Period::Day,
+            "week" => Period::Week,
+            "month" => Period::Month,
+            "quarter" => Period::Quarter,
+            "year" => Period::Year,
+            _ => return None,
+        })
+    }
+
+    /// Moves `date` by `count` of this period, using calendar
+    /// arithmetic for months and longer.
+    fn add(self, date: NaiveDateTime, count: i64) -> Option<NaiveDateTime> {
+        let seconds =
+            |unit: i64| date.checked_add_signed(Duration::seconds(count.checked_mul(unit)?));
+        match self {
+            Period::Second => seconds(1),
+            Period::Minute => seconds(60),
+            Period::Hour => seconds(3600),
+            Period::Day => seconds(86400),
+            Period::Week => seconds(7 * 86400),
+            Period::Month => add_months_naive(date, count),
+            Period::Quarter => add_months_naive(date, count.checked_mul(3)?),
+            Period::Year => add_months_naive(date, count.checked_mul(12)?),
+        }
+    }
+
+    /// The first instant of the period containing `date`. Weeks start
+    /// on Monday.
+    fn start(self, date: NaiveDateTime) -> Option<NaiveDateTime> {
+        let day = date.date();
+        let start = match self {
+            Period::Second => return date.with_nanosecond(0),
+            Period::Minute => return date.with_nanosecond(0)?.with_second(0),
+            Period::Hour => return date.with_nanosecond(0)?.with_second(0)?.with_minute(0),
+            Period::Day => day,
+            Period::Week => day - Duration::days(day.weekday().num_days_from_monday() as i64),
+            Period::Month => day.with_day(1)?,
+            Period::Quarter => day.with_day(1)?.with_month(day.month0() / 3 * 3 + 1)?,
+            Period::Year => day.with_day(1)?.with_month(1)?,
+        };
+        Some(start.and_hms(0, 0, 0))
+    }
+
+    /// The last whole second of the period containing `date`.
+    fn end(self, date: NaiveDateTime) -> Option<NaiveDateTime> {
+        self.add(self.start(date)?, 1)?
+            .checked_sub_signed(Duration::seconds(1))
+    }
+}
+
+/// Returns the dates for `today`, `tomorrow` and `yesterday` relative
+/// to `now`, at midnight UTC.
+pub fn named_day(name: &str, now: DateTime<Utc>) -> Option<GenericDateTime> {
+    let offset = match name {
+        "today" => 0,
+        "tomorrow" => 1,
+        "yesterday" => -1,
+        _ => return None,
+    };
+    let today = now.naive_utc().date().and_hms(0, 0, 0);
+    Period::Day.add(today, offset).map(utc_date)
+}
+
+fn utc_date(date: NaiveDateTime) -> GenericDateTime {
+    GenericDateTime::Fixed(FixedOffset::east(0).from_utc_datetime(&date))
+}
+
+/// Resolves natural language dates relative to `now`:
+///
+/// - `now`, `today`, `tomorrow`, `yesterday`
+/// - `next friday`, `last monday`, `next month`, `last year`
+/// - `3 weeks ago`, `in 2 days`, `5 hours from now`
+/// - `start of quarter`, `end of next month`, `beginning of last week`
+///
+/// Dates without a time of day are at midnight UTC, and ends of periods
+/// are their last second. Returns None when the tokens aren't a relative
+/// date, so that the date patterns can be tried instead.
+pub fn relative_date(
+    date: &[DateToken],
+    now: DateTime<Utc>,
+) -> Option<Result<GenericDateTime, String>> {
+    let mut words = vec![];
+    for token in date {
+        match *token {
+            DateToken::Space => (),
+            DateToken::Literal(ref word) => words.push(word.to_lowercase()),
+            DateToken::Number(ref number, None) => words.push(number.clone()),
+            _ => return None,
+        }
+    }
+    let words = words.iter().map(|word| &**word).collect::<Vec<_>>();
+
+    let now = now.naive_utc();
+    let today = now.date().and_hms(0, 0, 0);
+    let count = |number: &str| number.parse::<i64>().ok();
+    let out_of_range = || "Date is out of range".to_string();
+    // `this`, `next` or `last`, as an offset from the current period.
+    let direction = |word: &str| match word {
+        "this" => Some(0),
+        "next" => Some(1),
+        "last" => Some(-1),
+        _ => None,
+    };
+
+    let result = match *words.as_slice() {
+        ["now"] => Some(now),
+        [name] => return named_day(name, Utc.from_utc_datetime(&now)).map(Ok),
+        [dir, weekday] if direction(dir).is_some() && Weekday::from_str(weekday).is_ok() => {
+            let weekday = Weekday::from_str(weekday).unwrap();
+            let current = today.weekday().num_days_from_monday() as i64;
+            let target = weekday.num_days_from_monday() as i64;
+            let days = match direction(dir).unwrap() {
+                0 => target - current,
+                1 => (target - current - 1).rem_euclid(7) + 1,
+                _ => -((current - target - 1).rem_euclid(7) + 1),
+            };
+            Period::Day.add(today, days)
+        }
+        [dir, period] if direction(dir).is_some() && Period::from_name(period).is_some() => {
+            Period::from_name(period)
+                .unwrap()
+                .add(today, direction(dir).unwrap())
+        }
+        [number, period, "ago"] => {
+            let (number, period) = (count(number)?, Period::from_name(period)?);
+            period.add(now, number.checked_neg()?)
+        }
+        ["in", number, period] | [number, period, "from", "now"] => {
+            let (number, period) = (count(number)?, Period::from_name(period)?);
+            period.add(now, number)
+        }
+        [edge @ "start", "of", ref rest @ ..]
+        | [edge @ "beginning", "of", ref rest @ ..]
+        | [edge @ "end", "of", ref rest @ ..] => {
+            let (offset, period) = match *rest {
+                [period] => (0, period),
+                [dir, period] => (direction(dir)?, period),
+                _ => return None,
+            };
+            let period = Period::from_name(period)?;
+            let anchor = period.add(today, offset);
+            if edge == "end" {
+                anchor.and_then(|date| period.end(date))
+            } else {
+                anchor.and_then(|date| period.start(date))
+            }
+        }
+        _ => return None,
+    };
+    Some(result.map(utc_date).ok_or_else(out_of_range))
+}
+
+/// A change in the offset between TAI and UTC, as listed in
+/// `leapseconds.txt`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeapSecond {
+    /// Unix time from which the offset applies.
+    pub utc: i64,
+    /// TAI - UTC in seconds.
+    pub tai_minus_utc: i64,
+}
+
+pub fn parse_leap_seconds(file: &str) -> Vec<LeapSecond> {
+    let mut leaps = vec![];
+    for (num, line) in file.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let date = words
+            .next()
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok());
+        let offset = words.next().and_then(|offset| offset.parse::<i64>().ok());
+        match (date, offset, words.next()) {
+            (Some(date), Some(tai_minus_utc), None) => leaps.push(LeapSecond {
+                utc: date.and_hms(0, 0, 0).timestamp(),
+                tai_minus_utc,
+            }),
+            _ => println!(
+                "Line {}: Expected a date and TAI - UTC offset: {}",
+                num, line
+            ),
+        }
+    }
+    leaps
+}
+
+/// Time scales that dates can be read in and converted to, besides
+/// UTC. They differ from UTC by leap seconds plus a fixed offset. UT1
+/// isn't one of them, since it drifts from UTC by a measured amount
+/// rather than a fixed one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeScale {
+    Tai,
+    Tt,
+    Gps,
+}
+
+impl TimeScale {
+    fn from_name(name: &str) -> Option<TimeScale> {
+        match &*name.to_lowercase() {
+            "tai" => Some(TimeScale::Tai),
+            "tt" => Some(TimeScale::Tt),
+            "gps" => Some(TimeScale::Gps),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            TimeScale::Tai => "TAI",
+            TimeScale::Tt => "TT",
+            TimeScale::Gps => "GPS",
+        }
+    }
+
+    /// The scale's reading minus TAI's.
+    fn offset_from_tai(self) -> Duration {
+        match self {
+            TimeScale::Tai => Duration::zero(),
+            TimeScale::Tt => Duration::milliseconds(32_184),
+            TimeScale::Gps => Duration::seconds(-19),
+        }
+    }
+}
+
+/// TAI - UTC at `seconds`, which is a Unix time when `in_tai` is false
+/// and the equivalent reading of a TAI clock otherwise.
+fn tai_minus_utc(leaps: &[LeapSecond], seconds: i64, in_tai: bool) -> Result<i64, String> {
+    leaps
+        .iter()
+        .rev()
+        .find(|leap| leap.utc + if in_tai { leap.tai_minus_utc } else { 0 } <= seconds)
+        .map(|leap| leap.tai_minus_utc)
+        .ok_or_else(|| match leaps.first() {
+            Some(first) => format!(
+                "Leap seconds are only known from {}",
+                NaiveDateTime::from_timestamp(first.utc, 0).format("%Y-%m-%d")
+            ),
+            None => "No leap second table is loaded".to_string(),
+        })
+}
+
+/// Reads a UTC instant on the clock of another time scale.
+pub fn to_time_scale(
+    leaps: &[LeapSecond],
+    date: DateTime<Utc>,
+    scale: TimeScale,
+) -> Result<NaiveDateTime, String> {
+    let offset = tai_minus_utc(leaps, date.timestamp(), false)?;
+    date.naive_utc()
+        .checked_add_signed(Duration::seconds(offset) + scale.offset_from_tai())
+        .ok_or_else(|| "Date is out of range".to_string())
+}
+
+/// Converts a reading of a time scale's clock to UTC.
+pub fn from_time_scale(
+    leaps: &[LeapSecond],
+    reading: NaiveDateTime,
+    scale: TimeScale,
+) -> Result<DateTime<Utc>, String> {
+    let out_of_range = || "Date is out of range".to_string();
+    let tai = reading
+        .checked_sub_signed(scale.offset_from_tai())
+        .ok_or_else(out_of_range)?;
+    let offset = tai_minus_utc(leaps, tai.timestamp(), true)?;
+    tai.checked_sub_signed(Duration::seconds(offset))
+        .map(|utc| Utc.from_utc_datetime(&utc))
+        .ok_or_else(out_of_range)
+}
+
+/// Decodes dates ending in a time scale, like `2020-01-01 12:00 TAI`.
+/// The rest of the date is read as if it were UTC.
+fn time_scale_date(
+    date: &[DateToken],
+    context: &Context,
+) -> Option<Result<GenericDateTime, String>> {
+    let (scale, rest) = match date.split_last() {
+        Some((DateToken::Literal(name), rest)) => (TimeScale::from_name(name)?, rest),
+        _ => return None,
+    };
+    let rest = match rest.split_last() {
+        Some((DateToken::Space, rest)) => rest,
+        _ => return None,
+    };
+    Some(try_decode(rest, context).and_then(|reading| {
+        let reading = reading.with_timezone(&Utc).naive_utc();
+        from_time_scale(&context.leap_seconds, reading, scale)
+            .map(|date| GenericDateTime::Fixed(date.with_timezone(&FixedOffset::east(0))))
+    }))
+}
+
+/// Decodes dates given as a number, like `jd 2451545`, `mjd 58849.5`
+/// or `unix 1577880000`.
+fn numeric_date(date: &[DateToken]) -> Option<Result<GenericDateTime, String>> {
+    const NANOS_PER_DAY: i128 = 86_400_000_000_000;
+    let (kind, rest) = match date {
+        [DateToken::Literal(ref kind), DateToken::Space, rest @ ..] => (kind.to_lowercase(), rest),
+        _ => return None,
+    };
+    // Scale of the number in nanoseconds, and the Unix epoch in that scale.
+    let (scale, epoch) = match &*kind {
+        "unix" => (1_000_000_000, 0),
+        "jd" => (NANOS_PER_DAY, 2_440_587 * NANOS_PER_DAY + NANOS_PER_DAY / 2),
+        "mjd" => (NANOS_PER_DAY, 40_587 * NANOS_PER_DAY),
+        _ => return None,
+    };
+    let (sign, integer, frac) = match rest {
+        [DateToken::Number(ref integer, ref frac)] => (1, integer, frac),
+        [DateToken::Dash, DateToken::Number(ref integer, ref frac)] => (-1, integer, frac),
+        _ => return None,
+    };
+    let out_of_range = || "Date is out of range".to_string();
+    let number = || -> Option<i128> {
+        // Digits past a nanosecond are dropped.
+        let frac = frac
+            .as_ref()
+            .map(|frac| &frac[..frac.len().min(15)])
+            .unwrap_or("");
+        let numer = format!("{}{}", integer, frac).parse::<i128>().ok()?;
+        let denom = 10i128.pow(frac.len() as u32);
+        let nanos = numer.checked_mul(scale)?.div_euclid(denom);
+        (sign * nanos).checked_sub(epoch)
+    };
+    let nanos = match number() {
+        Some(nanos) => nanos,
+        None => return Some(Err(out_of_range())),
+    };
+    let seconds = nanos.div_euclid(1_000_000_000);
+    if seconds < i64::MIN as i128 || seconds > i64::MAX as i128 {
+        return Some(Err(out_of_range()));
+    }
+    let nanos = nanos.rem_euclid(1_000_000_000) as u32;
+    Some(
+        NaiveDateTime::from_timestamp_opt(seconds as i64, nanos)
+            .map(utc_date)
+            .ok_or_else(out_of_range),
+    )
+}
+
+/// The Julian Day of a date in millionths of a day, rounded to the
+/// nearest.
+fn julian_day_micros<Tz: TimeZone>(date: &DateTime<Tz>) -> i128 {
+    const NANOS_PER_MICRO_DAY: i128 = 86_400_000;
+    // 1970-01-01T00:00:00Z
+    const UNIX_EPOCH_MICROS: i128 = 2_440_587_500_000;
+    let nanos = date.timestamp() as i128 * 1_000_000_000 + date.timestamp_subsec_nanos() as i128;
+    (nanos + NANOS_PER_MICRO_DAY / 2).div_euclid(NANOS_PER_MICRO_DAY) + UNIX_EPOCH_MICROS
+}
+
+fn format_micros(micros: i128) -> String {
+    let sign = if micros < 0 { "-" } else { "" };
+    let micros = micros.abs();
+    let frac = format!("{:06}", micros % 1_000_000);
+    let frac = frac.trim_end_matches('0');
+    if frac.is_empty() {
+        format!("{}{}", sign, micros / 1_000_000)
+    } else {
+        format!("{}{}.{}", sign, micros / 1_000_000, frac)
+    }
+}
+
+/// Renders a date in one of the formats available as conversion
+/// targets. Fails for invalid strftime patterns, and for time scales
+/// when the date isn't covered by the leap second table.
+pub fn format_date<Tz>(
+    context: &Context,
+    date: &DateTime<Tz>,
+    format: &DateFormat,
+) -> Result<String, String>
+where
+    Tz: TimeZone,
+    Tz::Offset: std::fmt::Display,
+{
+    Ok(match *format {
+        DateFormat::Unix => date.timestamp().to_string(),
+        DateFormat::UnixMillis => date.timestamp_millis().to_string(),
+        DateFormat::Iso8601 => date.to_rfc3339(),
+        DateFormat::IsoWeek => date.format("%G-W%V-%u").to_string(),
+        DateFormat::IsoOrdinal => date.format("%Y-%j").to_string(),
+        DateFormat::DayOfYear => date.ordinal().to_string(),
+        DateFormat::Rfc2822 if (0..=9999).contains(&date.year()) => date.to_rfc2822(),
+        DateFormat::Rfc2822 => {
+            return Err(format!(
+                "RFC 2822 can't represent the year {}, only 0 to 9999",
+                date.year()
+            ))
+        }
+        DateFormat::JulianDay => format_micros(julian_day_micros(date)),
+        DateFormat::ModifiedJulianDay => format_micros(julian_day_micros(date) - 2_400_000_500_000),
+        DateFormat::Tai | DateFormat::Tt | DateFormat::Gps => {
+            let scale = match *format {
+                DateFormat::Tai => TimeScale::Tai,
+                DateFormat::Tt => TimeScale::Tt,
+                _ => TimeScale::Gps,
+            };
+            let reading = to_time_scale(&context.leap_seconds, date.with_timezone(&Utc), scale)?;
+            format!(
+                "{} {}",
+                reading.format("%Y-%m-%d %H:%M:%S%.f"),
+                scale.name()
+            )
+        }
+        DateFormat::Strftime(ref pattern) => {
+            use chrono::format::{Item, StrftimeItems};
+            use std::fmt::Write;
+
+            let items = StrftimeItems::new(pattern).collect::<Vec<_>>();
+            if items.contains(&Item::Error) {
+                return Err(format!("Invalid strftime pattern: {}", pattern));
+            }
+            let mut out = String::new();
+            write!(out, "{}", date.format_with_items(items.into_iter()))
+                .map_err(|_| format!("Invalid strftime pattern: {}", pattern))?;
+            out
+        }
+    })
+}
+
 pub fn to_duration(num: &Number) -> Result<Duration, String> {
     if num.unit.len() != 1 || num.unit.get("s") != Some(&1) {
         return Err("Expected seconds".to_string());
@@ -416,7 +1154,109 @@ pub fn to_duration(num: &Number) -> Result<Duration, String> {
     let ms = &num.value * &Numeric::from(1000);
     let (ms, rem) = ms.div_rem(&Numeric::from(1));
     let ns = &rem * &Numeric::from(1_000_000_000);
-    Ok(Duration::milliseconds(ms.to_int().unwrap()) + Duration::nanoseconds(ns.to_int().unwrap()))
+    match (ms.to_int(), ns.to_int()) {
+        (Some(ms), Some(ns)) => Ok(Duration::milliseconds(ms) + Duration::nanoseconds(ns)),
+        _ => Err("Duration must be a finite number".to_string()),
+    }
+}
+
+pub(crate) fn days_in_month(year: i32, month: u32) -> Option<u32> {
+    let (next_year, next_month) = if month == 12 {
+        (year.checked_add(1)?, 1)
+    } else {
+        (year, month + 1)
+    };
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let next = NaiveDate::from_ymd_opt(next_year, next_month, 1)?;
+    Some(next.signed_duration_since(first).num_days() as u32)
+}
+
+fn add_months_naive(date: NaiveDateTime, months: i64) -> Option<NaiveDateTime> {
+    let total = (date.year() as i64 * 12 + date.month0() as i64).checked_add(months)?;
+    let year = total.div_euclid(12);
+    if year < i32::MIN as i64 || year > i32::MAX as i64 {
+        return None;
+    }
+    let year = year as i32;
+    let month = total.rem_euclid(12) as u32 + 1;
+    let day = date.day().min(days_in_month(year, month)?);
+    NaiveDate::from_ymd_opt(year, month, day).map(|d| d.and_time(date.time()))
+}
+
+/// Adds whole calendar months to a date, keeping the time of day. When
+/// the day doesn't exist in the resulting month it is clamped to the
+/// last day of the month, so Jan 31 + 1 month is Feb 28 or Feb 29.
+pub fn add_months(date: &GenericDateTime, months: i64) -> Result<GenericDateTime, String> {
+    map_local(date, |naive| add_months_naive(naive, months))
+}
+
+/// Replaces the local date and time of `date` with the result of `f`,
+/// keeping its timezone. Fails if the new time is out of range or falls
+/// in a gap in the timezone.
+pub(crate) fn map_local<F>(date: &GenericDateTime, f: F) -> Result<GenericDateTime, String>
+where
+    F: FnOnce(NaiveDateTime) -> Option<NaiveDateTime>,
+{
+    let out_of_range = || "Date is out of range".to_string();
+    match *date {
+        GenericDateTime::Fixed(ref d) => {
+            let naive = f(d.naive_local()).ok_or_else(out_of_range)?;
+            d.offset()
+                .from_local_datetime(&naive)
+                .single()
+                .map(GenericDateTime::Fixed)
+                .ok_or_else(out_of_range)
+        }
+        GenericDateTime::Timezone(ref d) => {
+            let naive = f(d.naive_local()).ok_or_else(out_of_range)?;
+            d.timezone()
+                .from_local_datetime(&naive)
+                .earliest()
+                .map(GenericDateTime::Timezone)
+                .ok_or_else(|| format!("{} does not exist in {}", naive, d.timezone().name()))
+        }
+    }
+}
+
+/// Local times of both dates, in the timezone of `left`.
+pub(crate) fn local_pair(
+    left: &GenericDateTime,
+    right: &GenericDateTime,
+) -> (NaiveDateTime, NaiveDateTime) {
+    match *left {
+        GenericDateTime::Fixed(ref l) => (
+            l.naive_local(),
+            right.with_timezone(l.offset()).naive_local(),
+        ),
+        GenericDateTime::Timezone(ref l) => (
+            l.naive_local(),
+            right.with_timezone(&l.timezone()).naive_local(),
+        ),
+    }
+}
+
+/// Splits the time from `right` to `left` into whole calendar months
+/// and the remainder, using the calendar of `left`'s timezone. Both are
+/// negative when `left` is before `right`.
+pub fn calendar_difference(left: &GenericDateTime, right: &GenericDateTime) -> (i64, Duration) {
+    let (left, right) = local_pair(left, right);
+    fn forward(later: NaiveDateTime, earlier: NaiveDateTime) -> (i64, Duration) {
+        let mut months = (later.year() as i64 - earlier.year() as i64) * 12 + later.month() as i64
+            - earlier.month() as i64;
+        loop {
+            match add_months_naive(earlier, months) {
+                Some(date) if date <= later => return (months, later - date),
+                _ if months > 0 => months -= 1,
+                _ => return (0, later - earlier),
+            }
+        }
+    }
+    if left >= right {
+        forward(left, right)
+    } else {
+        let (months, rest) = forward(right, left);
+        (-months, -rest)
+    }
 }
 
 pub fn from_duration(duration: &Duration) -> Result<Number, String> {
@@ -464,6 +1304,9 @@ where
                         buf.push(c);
                     }
                 }
+                if iter.peek().is_none() {
+                    return Err("Expected '".to_string());
+                }
                 DatePattern::Literal(buf)
             }
             x if x.is_whitespace() => {
@@ -494,38 +1337,99 @@ where
     Ok(out)
 }
 
-pub fn parse_datefile(file: &str) -> Vec<Vec<DatePattern>> {
+/// The names that can appear in a pattern, see `parse_date`.
+const MATCHERS: &[&str] = &[
+    "fullyear",
+    "shortyear",
+    "century",
+    "monthnum",
+    "day",
+    "fullday",
+    "min",
+    "ordinal",
+    "isoyear",
+    "isoweek",
+    "isoweekday",
+    "unix",
+    "year",
+    "adbc",
+    "hour12",
+    "hour24",
+    "meridiem",
+    "sec",
+    "offset",
+    "monthname",
+    "calmonth",
+    "calendar",
+    "weekday",
+];
+
+fn check_matchers(pat: &[DatePattern]) -> Result<(), String> {
+    for part in pat {
+        match *part {
+            DatePattern::Match(ref name) if !MATCHERS.contains(&&**name) => {
+                return Err(format!("Unknown matcher {}", name))
+            }
+            DatePattern::Optional(ref pats) => check_matchers(pats)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Parses a single date pattern, such as the argument of a
+/// `!datepattern` directive. Unlike `parse_datepattern`, this rejects
+/// trailing input and unknown matchers up front instead of letting
+/// every date literal fail on them later.
+pub fn parse_pattern(pattern: &str) -> Result<Vec<DatePattern>, String> {
+    let mut iter = pattern.trim().chars().peekable();
+    let pat = parse_datepattern(&mut iter)?;
+    if let Some(c) = iter.next() {
+        return Err(format!("Unexpected {}", c));
+    }
+    if pat.is_empty() {
+        return Err("Pattern is empty".to_owned());
+    }
+    check_matchers(&pat)?;
+    Ok(pat)
+}
+
+/// Parses the format of `datepatterns.txt`, one pattern per line.
+/// Lines that fail to parse are skipped and returned as diagnostics.
+pub fn parse_datefile(file: &str) -> (Vec<Vec<DatePattern>>, Vec<DatePatternError>) {
     let mut defs = vec![];
+    let mut errors = vec![];
     for (num, line) in file.lines().enumerate() {
         let line = line.split('#').next().unwrap();
         let line = line.trim();
         if line.is_empty() {
             continue;
         }
-        let res = parse_datepattern(&mut line.chars().peekable());
-        match res {
+        match parse_pattern(line) {
             Ok(res) => defs.push(res),
-            Err(e) => println!("Line {}: {}: {}", num, e, line),
+            Err(message) => errors.push(DatePatternError {
+                line: Some(num + 1),
+                pattern: line.to_owned(),
+                message,
+            }),
         }
     }
-    defs
+    (defs, errors)
 }
 
 impl Context {
-    #[cfg(feature = "chrono-humanize")]
+    /// Describes a date relative to `self.now`, such as `in 3 days`,
+    /// using `self.humanize_precision` units. Returns None when
+    /// `self.use_humanize` is off.
     pub fn humanize<Tz: TimeZone>(&self, date: DateTime<Tz>) -> Option<String> {
         if self.use_humanize {
-            use chrono_humanize::HumanTime;
-            Some(HumanTime::from(date).to_string())
+            let date = GenericDateTime::Fixed(date.with_timezone(&date.offset().fix()));
+            let now = GenericDateTime::Fixed(self.now.with_timezone(&FixedOffset::east(0)));
+            Some(humanize::humanize(&date, &now, self.humanize_precision))
         } else {
             None
         }
     }
-
-    #[cfg(not(feature = "chrono-humanize"))]
-    pub fn humanize<Tz: TimeZone>(&self, _date: DateTime<Tz>) -> Option<String> {
-        None
-    }
 }
 
 #[cfg(test)]
@@ -540,7 +1444,14 @@ mod tests {
         let mut parsed = Parsed::new();
         let mut tz = None;
         let pat = pattern(pat);
-        let res = parse_date(&mut parsed, &mut tz, &mut date.into_iter().peekable(), &pat);
+        let res = parse_date(
+            &mut parsed,
+            &mut tz,
+            &mut None,
+            &TimezoneAliases::default(),
+            &mut date.into_iter().peekable(),
+            &pat,
+        );
 
         (res, parsed, tz)
     }
@@ -753,6 +1664,47 @@ mod tests {
         assert!(parse("*").is_err());
     }
 
+    #[test]
+    fn test_parse_pattern() {
+        assert!(parse_pattern("year-monthnum-fullday").is_ok());
+        assert_eq!(
+            parse_pattern("year-foo"),
+            Err("Unknown matcher foo".to_owned())
+        );
+        assert_eq!(
+            parse_pattern("year[ hour24 bar]"),
+            Err("Unknown matcher bar".to_owned())
+        );
+        assert_eq!(parse_pattern("year]-day"), Err("Unexpected ]".to_owned()));
+        assert_eq!(parse_pattern("year'T"), Err("Expected '".to_owned()));
+        assert_eq!(parse_pattern("  "), Err("Pattern is empty".to_owned()));
+    }
+
+    #[test]
+    fn test_parse_datefile() {
+        let (pats, errors) = parse_datefile(
+            "# comment\n\
+             year-monthnum-fullday\n\
+             \n\
+             year-foo # comment\n",
+        );
+        assert_eq!(pats.len(), 1);
+        assert_eq!(
+            errors,
+            vec![DatePatternError {
+                line: Some(4),
+                pattern: "year-foo".to_owned(),
+                message: "Unknown matcher foo".to_owned(),
+            }]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "Line 4: Unknown matcher foo: year-foo"
+        );
+        let (_, errors) = parse_datefile(crate::DATES_FILE);
+        assert_eq!(errors, vec![]);
+    }
+
     #[test]
     fn test_attempt() {
         use self::DateToken::*;
@@ -765,7 +1717,7 @@ mod tests {
         macro_rules! check_attempt {
             ($date:expr, $pat:expr) => {{
                 let pat = parse_datepattern(&mut $pat.chars().peekable()).unwrap();
-                attempt(now, $date, pat.as_ref())
+                attempt(now, &TimezoneAliases::default(), $date, pat.as_ref())
             }};
         }
 
@@ -786,4 +1738,183 @@ mod tests {
         let res = check_attempt!(date, "year monthnum day offset");
         assert!(res.is_ok(), "{:?}", res);
     }
+
+    fn utc(y: i32, m: u32, d: u32) -> GenericDateTime {
+        GenericDateTime::Fixed(FixedOffset::east(0).ymd(y, m, d).and_hms(12, 0, 0))
+    }
+
+    #[test]
+    fn test_add_months_clamps() {
+        assert_eq!(add_months(&utc(2020, 1, 31), 1), Ok(utc(2020, 2, 29)));
+        assert_eq!(add_months(&utc(2019, 1, 31), 1), Ok(utc(2019, 2, 28)));
+        assert_eq!(add_months(&utc(2020, 3, 31), -1), Ok(utc(2020, 2, 29)));
+        assert_eq!(add_months(&utc(2020, 2, 29), 12), Ok(utc(2021, 2, 28)));
+        assert_eq!(add_months(&utc(2020, 12, 15), 1), Ok(utc(2021, 1, 15)));
+        assert_eq!(add_months(&utc(2021, 1, 15), -1), Ok(utc(2020, 12, 15)));
+        assert!(add_months(&utc(2020, 1, 1), i64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_calendar_difference() {
+        let diff = |a, b| calendar_difference(&a, &b);
+        assert_eq!(
+            diff(utc(2021, 3, 15), utc(2020, 1, 31)),
+            (13, Duration::days(15))
+        );
+        assert_eq!(
+            diff(utc(2020, 1, 31), utc(2021, 3, 15)),
+            (-13, Duration::days(-15))
+        );
+        assert_eq!(
+            diff(utc(2020, 2, 29), utc(2020, 1, 31)),
+            (1, Duration::zero())
+        );
+        assert_eq!(
+            diff(utc(2020, 2, 28), utc(2020, 1, 31)),
+            (0, Duration::days(28))
+        );
+    }
+
+    fn relative(input: &str) -> Option<Result<String, String>> {
+        use crate::text_query::{Token, TokenIterator};
+        // Wednesday.
+        let now = Utc.ymd(2020, 5, 13).and_hms(15, 30, 0);
+        let tokens = match TokenIterator::new(&format!("#{}#", input)).next() {
+            Some(Token::Date(tokens)) => tokens,
+            x => panic!("{:?}", x),
+        };
+        relative_date(&tokens, now).map(|res| {
+            res.map(|date| {
+                date.with_timezone(&Utc)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+        })
+    }
+
+    #[test]
+    fn test_relative_days() {
+        let ok = |date: &str| Some(Ok(date.to_owned()));
+        assert_eq!(relative("now"), ok("2020-05-13 15:30:00"));
+        assert_eq!(relative("today"), ok("2020-05-13 00:00:00"));
+        assert_eq!(relative("Tomorrow"), ok("2020-05-14 00:00:00"));
+        assert_eq!(relative("yesterday"), ok("2020-05-12 00:00:00"));
+        assert_eq!(relative("next friday"), ok("2020-05-15 00:00:00"));
+        assert_eq!(relative("next wednesday"), ok("2020-05-20 00:00:00"));
+        assert_eq!(relative("last monday"), ok("2020-05-11 00:00:00"));
+        assert_eq!(relative("last wed"), ok("2020-05-06 00:00:00"));
+        assert_eq!(relative("this sunday"), ok("2020-05-17 00:00:00"));
+        assert_eq!(relative("next month"), ok("2020-06-13 00:00:00"));
+        assert_eq!(relative("last year"), ok("2019-05-13 00:00:00"));
+    }
+
+    #[test]
+    fn test_relative_offsets() {
+        let ok = |date: &str| Some(Ok(date.to_owned()));
+        assert_eq!(relative("3 weeks ago"), ok("2020-04-22 15:30:00"));
+        assert_eq!(relative("1 hour ago"), ok("2020-05-13 14:30:00"));
+        assert_eq!(relative("in 2 days"), ok("2020-05-15 15:30:00"));
+        assert_eq!(relative("18 months from now"), ok("2021-11-13 15:30:00"));
+        assert_eq!(
+            relative("99999999999999999 years ago"),
+            Some(Err("Date is out of range".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_relative_periods() {
+        let ok = |date: &str| Some(Ok(date.to_owned()));
+        assert_eq!(relative("start of month"), ok("2020-05-01 00:00:00"));
+        assert_eq!(relative("end of month"), ok("2020-05-31 23:59:59"));
+        assert_eq!(relative("end of next month"), ok("2020-06-30 23:59:59"));
+        assert_eq!(relative("start of quarter"), ok("2020-04-01 00:00:00"));
+        assert_eq!(relative("end of quarter"), ok("2020-06-30 23:59:59"));
+        assert_eq!(
+            relative("beginning of last week"),
+            ok("2020-05-04 00:00:00")
+        );
+        assert_eq!(relative("end of this week"), ok("2020-05-17 23:59:59"));
+        assert_eq!(relative("end of year"), ok("2020-12-31 23:59:59"));
+        assert_eq!(relative("end of day"), ok("2020-05-13 23:59:59"));
+    }
+
+    #[test]
+    fn test_not_relative() {
+        assert_eq!(relative("2020-01-01"), None);
+        assert_eq!(relative("friday"), None);
+        assert_eq!(relative("next fortnight"), None);
+        assert_eq!(relative("2.5 days ago"), None);
+    }
+
+    #[test]
+    fn test_parse_leap_seconds() {
+        let leaps = parse_leap_seconds("# comment\n1972-01-01 10\n\n2017-01-01 37 # last\nbad\n");
+        assert_eq!(
+            leaps,
+            vec![
+                LeapSecond {
+                    utc: 63072000,
+                    tai_minus_utc: 10
+                },
+                LeapSecond {
+                    utc: 1483228800,
+                    tai_minus_utc: 37
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_time_scales() {
+        let leaps = parse_leap_seconds(crate::LEAP_SECONDS_FILE);
+        let utc = Utc.ymd(2016, 12, 31).and_hms(23, 59, 59);
+        let tai = to_time_scale(&leaps, utc, TimeScale::Tai).unwrap();
+        assert_eq!(tai, NaiveDate::from_ymd(2017, 1, 1).and_hms(0, 0, 35));
+        assert_eq!(from_time_scale(&leaps, tai, TimeScale::Tai), Ok(utc));
+
+        let utc = Utc.ymd(2017, 1, 1).and_hms(0, 0, 0);
+        let gps = to_time_scale(&leaps, utc, TimeScale::Gps).unwrap();
+        assert_eq!(gps, NaiveDate::from_ymd(2017, 1, 1).and_hms(0, 0, 18));
+        assert_eq!(from_time_scale(&leaps, gps, TimeScale::Gps), Ok(utc));
+
+        let tt = to_time_scale(&leaps, utc, TimeScale::Tt).unwrap();
+        assert_eq!(
+            tt,
+            NaiveDate::from_ymd(2017, 1, 1).and_hms_milli(0, 1, 9, 184)
+        );
+
+        let utc = Utc.ymd(1971, 12, 31).and_hms(0, 0, 0);
+        assert_eq!(
+            to_time_scale(&leaps, utc, TimeScale::Tai),
+            Err("Leap seconds are only known from 1972-01-01".to_owned())
+        );
+        assert_eq!(
+            to_time_scale(&[], utc, TimeScale::Tai),
+            Err("No leap second table is loaded".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_interval() {
+        let day =
+            |d: u32| GenericDateTime::Fixed(FixedOffset::east(0).ymd(2024, 1, d).and_hms(0, 0, 0));
+        let span = |a, b| Interval::new(day(a), day(b)).unwrap();
+        assert!(Interval::new(day(2), day(1)).is_err());
+        assert!(span(1, 10).contains(&day(10)));
+        assert!(!span(1, 10).contains(&day(11)));
+        assert_eq!(span(1, 10).overlap(&span(5, 20)), Some(span(5, 10)));
+        assert_eq!(span(5, 20).overlap(&span(1, 10)), Some(span(5, 10)));
+        assert_eq!(span(1, 20).overlap(&span(5, 10)), Some(span(5, 10)));
+        assert_eq!(span(1, 10).overlap(&span(10, 20)), Some(span(10, 10)));
+        assert_eq!(span(1, 10).overlap(&span(11, 20)), None);
+        // Compared as moments, so this is before the end in UTC.
+        let tokyo = GenericDateTime::Timezone(Tz::Asia__Tokyo.ymd(2024, 1, 10).and_hms(8, 0, 0));
+        assert!(span(1, 10).contains(&tokyo));
+
+        let weekly =
+            span(1, 20).recurrence(10, |n| add_duration(&day(1), Duration::weeks(n.into())));
+        assert_eq!(weekly, Ok(vec![day(1), day(8), day(15)]));
+        let daily = span(1, 20).recurrence(10, |n| add_duration(&day(1), Duration::days(n.into())));
+        assert_eq!(daily, Err("Recurrence has more than 10 dates".to_owned()));
+    }
 }
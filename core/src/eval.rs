@@ -2,52 +2,96 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::ast::{BinOpExpr, BinOpType, Conversion, Expr, Function, Query, UnaryOpType};
+use crate::ast::{
+    BinOpExpr, BinOpType, Conversion, DatePattern, Expr, Function, MixtureBasis, Query,
+    UnaryOpExpr, UnaryOpType,
+};
 use crate::bigint::BigInt;
+use crate::bigrat::BigRat;
+use crate::business::BusinessCalendar;
+use crate::calendar::CalendarDate;
 use crate::context::Context;
 use crate::date;
 use crate::factorize::{factorize, Factors};
-use crate::formula::substance_from_formula;
+use crate::formula::{self, substance_from_formula};
 use crate::number::{pow, Dimension, Number, NumberParts};
 use crate::numeric::{Digits, Numeric};
+use crate::reaction;
 use crate::reply::{
-    ConformanceError, ConversionReply, DateReply, DefReply, DurationReply, ExprReply,
-    FactorizeReply, QueryError, QueryReply, SearchReply, UnitListReply, UnitsForReply,
-    UnitsInCategory,
+    BalanceReply, BooleanReply, CalendarDateReply, ConformanceError, ConversionReply, DateError,
+    DatePatternResult, DatePatternsReply, DateReply, DefReply, DilutionReply, DurationReply,
+    ElementReply, ExprReply, FactorizeReply, IntervalReply, OperationError, PropertiesReply,
+    PropertyInfoReply, QueryError, QueryReply, RecurrenceReply, SearchReply, SpeciesReply,
+    SubstanceListReply, UnitListReply, UnitsForReply, UnitsInCategory,
 };
 use crate::search;
-use crate::substance::SubstanceGetError;
-use crate::value::{Show, Value};
-use chrono::FixedOffset;
-use std::collections::BTreeMap;
+use crate::substance::{Properties, Property, Substance, SubstanceGetError};
+use crate::text_query::split_leading_number;
+use crate::timezone::Zone;
+use crate::value::{operation_error, Show, Value};
+use chrono::{Duration, FixedOffset, Utc};
+use std::collections::{BTreeMap, BTreeSet};
 use std::rc::Rc;
+use std::sync::Arc;
+
+/// The most dates that `every <step> from <start> until <end>` lists.
+const MAX_RECURRENCE: usize = 1000;
 
 impl Context {
     /// Evaluates an expression to compute its value, *excluding* `->`
     /// conversions.
     pub fn eval(&self, expr: &Expr) -> Result<Value, QueryError> {
-        use std::ops::*;
+        self.step()?;
+        let value = self.eval_expr(expr)?;
+        self.check_value(&value)?;
+        Ok(value)
+    }
 
+    fn eval_expr(&self, expr: &Expr) -> Result<Value, QueryError> {
         match *expr {
             Expr::Unit { ref name } if name == "now" => Ok(Value::DateTime(
                 date::GenericDateTime::Fixed(self.now.with_timezone(&FixedOffset::east(0))),
             )),
-            Expr::Unit { ref name } => self
-                .lookup(name)
-                .map(Value::Number)
-                .or_else(|| self.substances.get(name).cloned().map(Value::Substance))
-                .or_else(|| {
-                    substance_from_formula(name, &self.substance_symbols, &self.substances)
-                        .map(Value::Substance)
-                })
-                .ok_or_else(|| QueryError::NotFound(self.unknown_unit_err(name))),
+            Expr::Unit { ref name } => match date::named_day(name, self.now) {
+                Some(day) => Ok(Value::DateTime(day)),
+                None => match self
+                    .lookup(name)
+                    .map(Value::Number)
+                    .or_else(|| self.substances.get(name).cloned().map(Value::Substance))
+                    .or_else(|| {
+                        substance_from_formula(name, &self.substance_symbols, &self.substances)
+                            .map(Value::Substance)
+                    }) {
+                    Some(value) => Ok(value),
+                    // The tokenizer keeps `3_kg` whole, since names like
+                    // `6061_aluminum` start with digits, so it's only
+                    // split when it isn't defined.
+                    None => match split_leading_number(name) {
+                        Some((number, unit)) => self.eval(&Expr::new_mul(vec![
+                            Expr::new_const(number),
+                            Expr::new_unit(unit.to_owned()),
+                        ])),
+                        None => match formula::misread_count(name, &self.substance_symbols) {
+                            Some((count, rest)) => Err(QueryError::generic(format!(
+                                "No such unit {}. Digits at the start of a formula are a mass \
+                                 number, and there is no such isotope. For a count, write `{} {}`",
+                                name, count, rest
+                            ))),
+                            None => Err(QueryError::NotFound(self.unknown_unit_err(name))),
+                        },
+                    },
+                },
+            },
             Expr::Quote { ref string } => {
                 Ok(Value::Number(Number::one_unit(Dimension::new(string))))
             }
             Expr::Const { ref value } => Ok(Value::Number(Number::new(value.clone()))),
             Expr::Date { ref tokens } => match date::try_decode(tokens, self) {
                 Ok(date) => Ok(Value::DateTime(date)),
-                Err(e) => Err(QueryError::generic(e)),
+                Err(message) => Err(QueryError::InvalidDate(DateError {
+                    input: tokens.iter().map(ToString::to_string).collect(),
+                    message,
+                })),
             },
 
             Expr::BinOp(BinOpExpr {
@@ -69,31 +113,19 @@ impl Context {
             }
 
             Expr::BinOp(ref binop) => {
+                if let Some(value) = self.eval_business_days(binop)? {
+                    return Ok(value);
+                }
                 let left = self.eval(&binop.left)?;
                 let right = self.eval(&binop.right)?;
-                let result = match binop.op {
-                    BinOpType::Add => left.add(&right),
-                    BinOpType::Sub => left.sub(&right),
-                    BinOpType::Frac => left.div(&right),
-                    BinOpType::Pow => left.pow(&right),
-                    BinOpType::Equals => panic!("Should be unreachable"),
-                };
-                result.map_err(|e| {
-                    QueryError::generic(format!(
-                        "{}: <{}> {} <{}>",
-                        e,
-                        left.show(self),
-                        binop.op.symbol().trim(),
-                        right.show(self)
-                    ))
-                })
+                self.eval_binop(binop, &left, &right)
             }
 
             Expr::UnaryOp(ref unaryop) => match unaryop.op {
                 UnaryOpType::Positive => self.eval(&unaryop.expr),
-                UnaryOpType::Negative => self.eval(&unaryop.expr).and_then(|v| {
-                    (-&v).map_err(|e| QueryError::generic(format!("{}: - <{}>", e, v.show(self))))
-                }),
+                UnaryOpType::Negative => self
+                    .eval(&unaryop.expr)
+                    .and_then(|v| (-&v).map_err(|e| self.with_operands(e, &[&v]))),
                 UnaryOpType::Degree(ref suffix) => {
                     let (name, base, scale) = suffix.name_base_scale();
 
@@ -114,18 +146,17 @@ impl Context {
                             expr.show(self)
                         )))
                     } else {
-                        let expr = (&expr
-                            * &self
-                                .lookup(scale)
-                                .expect(&*format!("Missing {} unit", scale)))
-                            .unwrap();
-                        Ok(Value::Number(
-                            (&expr
-                                + &self
-                                    .lookup(base)
-                                    .expect(&*format!("Missing {} constant", base)))
-                                .unwrap(),
-                        ))
+                        let scale = self.lookup_required(scale)?;
+                        let base = self.lookup_required(base)?;
+                        (&expr * &scale)
+                            .and_then(|expr| &expr + &base)
+                            .map(Value::Number)
+                            .ok_or_else(|| {
+                                QueryError::generic(format!(
+                                    "Definitions of °{} have mismatched units",
+                                    name
+                                ))
+                            })
                     }
                 }
             },
@@ -134,14 +165,7 @@ impl Context {
                 exprs.iter().fold(Ok(Value::Number(Number::one())), |a, b| {
                     a.and_then(|a| {
                         let b = self.eval(b)?;
-                        (&a * &b).map_err(|e| {
-                            QueryError::generic(format!(
-                                "{}: <{}> * <{}>",
-                                e,
-                                a.show(self),
-                                b.show(self)
-                            ))
-                        })
+                        (&a * &b).map_err(|e| self.with_operands(e, &[&a, &b]))
                     })
                 })
             }
@@ -149,9 +173,34 @@ impl Context {
                 ref property,
                 ref expr,
             } => {
-                let expr = self.eval(expr)?;
+                let expr = match **expr {
+                    // Only the table of the property asked for has to
+                    // cover the state.
+                    Expr::At {
+                        ref expr,
+                        ref states,
+                    } => self.eval_at(expr, states, Some(property))?,
+                    ref expr => self.eval(expr)?,
+                };
                 let expr = match expr {
                     Value::Substance(sub) => sub,
+                    Value::Interval(ref interval) => match &**property {
+                        "start" => return Ok(Value::DateTime(interval.start)),
+                        "end" => return Ok(Value::DateTime(interval.end)),
+                        "duration" | "length" => {
+                            return date::from_duration(&interval.duration())
+                                .map(Value::Number)
+                                .map_err(QueryError::generic)
+                        }
+                        _ => {
+                            return Err(QueryError::generic(format!(
+                                "Not defined: {} of <{}>, intervals have a start, end and \
+                                 duration",
+                                property,
+                                expr.show(self)
+                            )))
+                        }
+                    },
                     x => {
                         return Err(QueryError::generic(format!(
                             "Not defined: {} of <{}>",
@@ -160,13 +209,50 @@ impl Context {
                         )))
                     }
                 };
-                expr.get(property).map(Value::Number).map_err(|e| match e {
+                // `1 L of 2 M NaCl` is an amount of a substance rather
+                // than a property, when the unit measures out moles of a
+                // concentration like molar or molal.
+                let mole = Number::one_unit(Dimension::new("mol"));
+                let measures_moles = |unit: &Number| match &expr.amount * unit {
+                    Some(amount) => amount.unit == mole.unit,
+                    None => false,
+                };
+                match (expr.get(property), self.lookup(property)) {
+                    (Err(SubstanceGetError::Generic(_)), Some(unit)) if measures_moles(&unit) => {
+                        return (&expr * &unit)
+                            .map(Value::Substance)
+                            .map_err(QueryError::generic)
+                    }
+                    (res, _) => res,
+                }
+                .map(Value::Number)
+                .map_err(|e| match e {
                     SubstanceGetError::Generic(s) => QueryError::generic(s),
                     SubstanceGetError::Conformance(l, r) => {
                         QueryError::Conformance(Box::new(self.conformance_err(&l, &r)))
                     }
                 })
             }
+            Expr::Yields {
+                ref expr,
+                ref product,
+            } => {
+                let (reagent, moles) = self.formula_moles(expr)?;
+                let (numer, denom) =
+                    reaction::yield_ratio(&reagent, product).map_err(QueryError::generic)?;
+                let ratio = Number::new(Numeric::Rational(BigRat::ratio(
+                    &BigInt::from(numer),
+                    &BigInt::from(denom),
+                )));
+                let moles = self.multiply(&moles, &ratio)?;
+                self.formula_amount(product, moles).map(Value::Substance)
+            }
+            Expr::Mixture { ref expr, by } => self.eval_mixture(expr, by),
+            Expr::At {
+                ref expr,
+                ref states,
+            } => self.eval_at(expr, states, None),
+            Expr::After { ref expr, ref time } => self.eval_after(expr, time),
             Expr::Call { ref func, ref args } => {
                 let args = args
                     .iter()
@@ -330,6 +416,13 @@ impl Context {
                             }))
                         }
                     ),
+                    Function::Overlap => func!(
+                        fn overlap(a: Interval, b: Interval) {
+                            a.overlap(b)
+                                .map(Value::Interval)
+                                .ok_or_else(|| "Intervals do not overlap".to_string())
+                        }
+                    ),
                     Function::Atan2 => func!(
                         fn atan2(x: Number, y: Number) {
                             if x.unit != y.unit {
@@ -393,7 +486,7 @@ impl Context {
                     ),
                 }
             }
-            Expr::Error { ref message } => Err(QueryError::generic(message.clone())),
+            Expr::Error { ref message } => Err(QueryError::parse(message.clone())),
         }
     }
 
@@ -401,10 +494,16 @@ impl Context {
         &self,
         expr: &Expr,
     ) -> Result<(BTreeMap<String, isize>, Numeric), QueryError> {
+        self.step()?;
         match *expr {
             Expr::Call { .. } => Err(QueryError::generic(
                 "Calls are not allowed in the right hand side of conversions".to_string(),
             )),
+            Expr::Yields { .. } | Expr::Mixture { .. } | Expr::At { .. } | Expr::After { .. } => {
+                Err(QueryError::generic(
+                    "Substances are not allowed in the right hand side of conversions".to_string(),
+                ))
+            }
             Expr::Unit { ref name } | Expr::Quote { string: ref name } => {
                 let mut map = BTreeMap::new();
                 map.insert(
@@ -426,6 +525,9 @@ impl Context {
                         x
                     ))),
                 },
+                BinOpType::Range => Err(QueryError::generic(
+                    "Intervals are not allowed in the right hand side of conversions".to_string(),
+                )),
                 BinOpType::Add | BinOpType::Sub => {
                     let (left_unit, left) = self.eval_unit_name(&binop.left)?;
                     let (right_unit, _right) = self.eval_unit_name(&binop.right)?;
@@ -474,7 +576,14 @@ impl Context {
                         ));
                     }
                     let right = right.value.to_f64();
+                    if right.abs() >= (1u64 << 31) as f64 {
+                        return Err(QueryError::generic("Exponent is too large".to_string()));
+                    }
                     let (left_unit, left_value) = self.eval_unit_name(&binop.left)?;
+                    if right < 0.0 && left_value == Numeric::zero() {
+                        return Err(QueryError::generic("Division by zero".to_string()));
+                    }
+                    self.check_pow(&left_value, &Numeric::from(right as i32 as i64))?;
                     Ok((
                         left_unit
                             .into_iter()
@@ -549,10 +658,40 @@ impl Context {
             Expr::Date { .. } => Err(QueryError::generic(
                 "Dates are not allowed in the right hand side of conversions".to_string(),
             )),
-            Expr::Error { ref message } => Err(QueryError::generic(message.clone())),
+            Expr::Error { ref message } => Err(QueryError::parse(message.clone())),
         }
     }
 
+    /// Looks up a unit which a builtin feature like the temperature
+    /// scales depends on. Custom unit files don't have to define these.
+    fn lookup_required(&self, name: &str) -> Result<Number, QueryError> {
+        self.lookup(name)
+            .ok_or_else(|| QueryError::NotFound(self.unknown_unit_err(name)))
+    }
+
+    /// Multiplies numbers for builtin features like substances, where
+    /// a failure is reported rather than assumed impossible.
+    fn multiply(&self, left: &Number, right: &Number) -> Result<Number, QueryError> {
+        (left * right).ok_or_else(|| {
+            QueryError::generic(format!(
+                "Multiplication failed: <{}> * <{}>",
+                left.show(self),
+                right.show(self)
+            ))
+        })
+    }
+
+    /// Divides numbers for builtin features like substances.
+    fn divide(&self, left: &Number, right: &Number) -> Result<Number, QueryError> {
+        (left / right).ok_or_else(|| {
+            QueryError::generic(format!(
+                "Division by zero: <{}> / <{}>",
+                left.show(self),
+                right.show(self)
+            ))
+        })
+    }
+
     fn conformance_err(&self, top: &Number, bottom: &Number) -> ConformanceError {
         fn multiply_or_divide(recip: bool) -> &'static str {
             if recip {
@@ -601,7 +740,9 @@ impl Context {
             .into_iter()
             .map(|(a, b)| (Dimension::new(&*a), b as i64))
             .collect();
-        let (num, den) = bottom_const.to_rational();
+        let (num, den) = bottom_const
+            .to_rational()
+            .unwrap_or_else(|| (BigInt::one(), BigInt::one()));
         ConversionReply {
             value: NumberParts {
                 exact_value: exact,
@@ -623,11 +764,874 @@ impl Context {
         }
     }
 
+    /// Applies a binary operator to already evaluated operands.
+    fn eval_binop(
+        &self,
+        binop: &BinOpExpr,
+        left: &Value,
+        right: &Value,
+    ) -> Result<Value, QueryError> {
+        if let (BinOpType::Pow, Value::Number(ref base), Value::Number(ref exp)) =
+            (binop.op, left, right)
+        {
+            self.check_pow(&base.value, &exp.value)?;
+        }
+        let calendar = match (binop.op, left, right) {
+            (BinOpType::Add, Value::DateTime(ref date), _) => self
+                .calendar_months(&binop.right)?
+                .map(|months| (date, months)),
+            (BinOpType::Add, _, Value::DateTime(ref date)) => self
+                .calendar_months(&binop.left)?
+                .map(|months| (date, months)),
+            (BinOpType::Sub, Value::DateTime(ref date), _) => self
+                .calendar_months(&binop.right)?
+                .and_then(|months| months.checked_neg())
+                .map(|months| (date, months)),
+            _ => None,
+        };
+        let result = match (calendar, binop.op) {
+            (Some((date, months)), _) => date::add_months(date, months)
+                .map(Value::DateTime)
+                .map_err(|message| operation_error(binop.op.symbol().trim(), message)),
+            (None, BinOpType::Add) => left + right,
+            (None, BinOpType::Sub) => left - right,
+            (None, BinOpType::Frac) => left / right,
+            (None, BinOpType::Pow) => left.pow(right),
+            (None, BinOpType::Range) => left.range(right),
+            (None, BinOpType::Equals) => Err(operation_error(
+                "=",
+                "= is currently only used for inline unit definitions".to_owned(),
+            )),
+        };
+        result.map_err(|e| self.with_operands(e, &[left, right]))
+    }
+
+    /// Fills in the operands of an error from an operator on `Value`s,
+    /// which can't show them itself.
+    fn with_operands(&self, error: QueryError, operands: &[&Value]) -> QueryError {
+        match error {
+            QueryError::Operation(mut error) if error.operands.is_empty() => {
+                error.operands = operands.iter().map(|value| value.show(self)).collect();
+                QueryError::Operation(error)
+            }
+            error => error,
+        }
+    }
+
+    /// Recognizes offsets like `3 months`, `year` or `1 month * 2`,
+    /// which are added to dates using calendar arithmetic instead of as
+    /// a fixed number of seconds. Returns the offset in months, or None
+    /// if the expression doesn't use calendar units. Offsets that use
+    /// them in other ways, like `1 month + 1 day` or `1.5 months`, are
+    /// an error rather than an average month length.
+    fn calendar_months(&self, expr: &Expr) -> Result<Option<i64>, QueryError> {
+        let months = match self.calendar_count(expr)? {
+            Some(months) => months,
+            None if self.mentions_calendar_unit(expr) => {
+                return Err(QueryError::generic(format!(
+                    "Can't offset a date by `{}`: calendar months and years can only be \
+                     multiplied or divided by numbers",
+                    expr
+                )))
+            }
+            None => return Ok(None),
+        };
+        if months.div_rem(&Numeric::one()).1 != Numeric::zero() {
+            return Err(QueryError::generic(format!(
+                "Can't offset a date by `{}`: it isn't a whole number of months. Use a \
+                 fixed length like `gregorianyear / 12` for an average month",
+                expr
+            )));
+        }
+        months
+            .to_int()
+            .map(Some)
+            .ok_or_else(|| QueryError::generic(format!("Date offset `{}` is too large", expr)))
+    }
+
+    /// The number of months in a product of one calendar unit and
+    /// dimensionless factors.
+    fn calendar_count(&self, expr: &Expr) -> Result<Option<Numeric>, QueryError> {
+        let dimensionless = |expr: &Expr| -> Result<Option<Numeric>, QueryError> {
+            match self.eval(expr)? {
+                Value::Number(ref number) if number.unit.is_empty() => {
+                    Ok(Some(number.value.clone()))
+                }
+                _ => Ok(None),
+            }
+        };
+        match *expr {
+            Expr::Unit { ref name } => Ok(self.calendar_unit(name).map(Numeric::from)),
+            Expr::UnaryOp(UnaryOpExpr {
+                op: UnaryOpType::Negative,
+                ref expr,
+            }) => Ok(self.calendar_count(expr)?.map(|months| -&months)),
+            Expr::Mul { ref exprs } => {
+                let mut months = None;
+                let mut factors = vec![];
+                for expr in exprs {
+                    match self.calendar_count(expr)? {
+                        Some(_) if months.is_some() => return Ok(None),
+                        Some(count) => months = Some(count),
+                        None => factors.push(expr.clone()),
+                    }
+                }
+                let months = match months {
+                    Some(months) => months,
+                    None => return Ok(None),
+                };
+                if factors.is_empty() {
+                    return Ok(Some(months));
+                }
+                Ok(dimensionless(&Expr::new_mul(factors))?.map(|factor| &months * &factor))
+            }
+            Expr::BinOp(BinOpExpr {
+                op: BinOpType::Frac,
+                ref left,
+                ref right,
+            }) => {
+                let months = match self.calendar_count(left)? {
+                    Some(months) => months,
+                    None => return Ok(None),
+                };
+                match dimensionless(right)? {
+                    Some(divisor) if divisor != Numeric::zero() => Ok(Some(&months / &divisor)),
+                    _ => Ok(None),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn mentions_calendar_unit(&self, expr: &Expr) -> bool {
+        match *expr {
+            Expr::Unit { ref name } => self.calendar_unit(name).is_some(),
+            Expr::UnaryOp(UnaryOpExpr { ref expr, .. }) => self.mentions_calendar_unit(expr),
+            Expr::Mul { ref exprs } => exprs.iter().any(|expr| self.mentions_calendar_unit(expr)),
+            Expr::BinOp(BinOpExpr {
+                ref left,
+                ref right,
+                ..
+            }) => self.mentions_calendar_unit(left) || self.mentions_calendar_unit(right),
+            _ => false,
+        }
+    }
+
+    /// Number of months in a calendar unit, following aliases like `yr`
+    /// and plurals.
+    fn calendar_unit(&self, name: &str) -> Option<i64> {
+        const CALENDAR_UNITS: &[(&str, i64)] = &[
+            ("month", 1),
+            ("year", 12),
+            ("decade", 120),
+            ("century", 1200),
+            ("millennium", 12000),
+        ];
+        fn resolve<'a>(ctx: &'a Context, mut name: &'a str) -> Option<i64> {
+            // Bounded in case of alias cycles.
+            for _ in 0..8 {
+                if let Some(&(_, months)) = CALENDAR_UNITS.iter().find(|&&(unit, _)| unit == name) {
+                    return Some(months);
+                }
+                match ctx.definitions.get(name) {
+                    Some(Expr::Unit { name: alias }) => name = alias,
+                    _ => return None,
+                }
+            }
+            None
+        }
+        resolve(self, name).or_else(|| resolve(self, name.strip_suffix('s')?))
+    }
+
+    /// Recognizes timezone abbreviations and place names after `->`,
+    /// like `PDT` or `New York`, which aren't IANA names and so are
+    /// parsed as units.
+    fn timezone_alias(&self, expr: &Expr) -> Option<Result<Zone, String>> {
+        let name = match *expr {
+            Expr::Unit { ref name } => name.clone(),
+            Expr::Mul { ref exprs } => exprs
+                .iter()
+                .map(|expr| match *expr {
+                    Expr::Unit { ref name } => Some(&**name),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()?
+                .join(" "),
+            _ => return None,
+        };
+        if self.timezone_aliases.contains(&name) {
+            self.timezone_aliases.resolve(&name)
+        } else {
+            None
+        }
+    }
+
+    fn formula_molar_mass(&self, name: &str) -> Result<Number, QueryError> {
+        substance_from_formula(name, &self.substance_symbols, &self.substances)
+            .and_then(|sub| sub.get("molar_mass").ok())
+            .ok_or_else(|| QueryError::generic(format!("{} is not a chemical formula", name)))
+    }
+
+    /// Splits an amount of a chemical formula like `10 g C3H8` into the
+    /// formula and how many moles of it there are. A formula on its own
+    /// is one mole.
+    fn formula_moles(&self, expr: &Expr) -> Result<(String, Number), QueryError> {
+        let mole = Number::one_unit(Dimension::new("mol"));
+        let expected = || {
+            QueryError::generic(format!(
+                "Expected an amount followed by a chemical formula, like `10 g C3H8`, got {}",
+                expr
+            ))
+        };
+        let (amount, name) = match *expr {
+            Expr::Unit { ref name } => (mole.clone(), name),
+            Expr::Mul { ref exprs } => match exprs.split_last() {
+                Some((Expr::Unit { name }, rest)) => {
+                    let amount = match *rest {
+                        [ref amount] => self.eval(amount)?,
+                        _ => self.eval(&Expr::new_mul(rest.to_vec()))?,
+                    };
+                    match amount {
+                        Value::Number(amount) => (amount, name),
+                        amount => {
+                            return Err(QueryError::generic(format!(
+                                "Expected an amount of {}, got <{}>",
+                                name,
+                                amount.show(self)
+                            )))
+                        }
+                    }
+                }
+                _ => return Err(expected()),
+            },
+            _ => return Err(expected()),
+        };
+        let molar_mass = self.formula_molar_mass(name)?;
+        let moles = if amount.unit == mole.unit {
+            amount
+        } else if amount.unit == self.multiply(&molar_mass, &mole)?.unit {
+            self.divide(&amount, &molar_mass)?
+        } else {
+            return Err(QueryError::generic(format!(
+                "Expected an amount of substance or a mass of {}, got <{}>",
+                name,
+                amount.show(self)
+            )));
+        };
+        Ok((name.clone(), moles))
+    }
+
+    /// A number of moles of a chemical formula.
+    fn formula_amount(&self, name: &str, moles: Number) -> Result<Substance, QueryError> {
+        substance_from_formula(name, &self.substance_symbols, &self.substances)
+            .ok_or_else(|| QueryError::generic(format!("{} is not a chemical formula", name)))
+            .and_then(|sub| (&sub * &moles).map_err(QueryError::generic))
+    }
+
+    /// Evaluates `expr at states`, replacing each property that has a
+    /// table for one of the states with the value interpolated from
+    /// it. Properties whose table doesn't cover the state are left out,
+    /// and using one of them later fails with the reason. When
+    /// `property` names the one being asked for, it fails right away.
+    fn eval_at(
+        &self,
+        expr: &Expr,
+        states: &[Expr],
+        property: Option<&str>,
+    ) -> Result<Value, QueryError> {
+        let substance = match self.eval(expr)? {
+            Value::Substance(substance) => substance,
+            x => {
+                return Err(QueryError::generic(format!(
+                    "Expected a substance before at, got <{}>",
+                    x.show(self)
+                )))
+            }
+        };
+        let values = states
+            .iter()
+            .map(|state| match self.eval(state)? {
+                Value::Number(state) => Ok(state),
+                x => Err(QueryError::generic(format!(
+                    "Expected a temperature or pressure after at, got <{}>",
+                    x.show(self)
+                ))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let name = &substance.properties.name;
+        let mut properties = substance.properties.properties.clone();
+        let mut left_out = substance.properties.left_out.clone();
+        let mut used = vec![false; values.len()];
+        let mut outside = vec![None; values.len()];
+        for (prop_name, tables) in &substance.properties.tables {
+            let found = tables.iter().find_map(|table| {
+                let (first, _) = table.points.first()?;
+                let i = values.iter().position(|state| state.unit == first.unit)?;
+                Some((i, table))
+            });
+            let (i, table) = match found {
+                Some(found) => found,
+                None => continue,
+            };
+            let value = match table.interpolate(&values[i]) {
+                Some(value) => value,
+                None => {
+                    let show = |state: &Number| state.to_parts(self).format("n u");
+                    let message = format!(
+                        "<{}> is outside the {} table of {}, which goes from <{}> to <{}>",
+                        show(&values[i]),
+                        prop_name,
+                        name,
+                        show(&table.points[0].0),
+                        show(&table.points[table.points.len() - 1].0),
+                    );
+                    if property == Some(&**prop_name) {
+                        return Err(QueryError::generic(message));
+                    }
+                    outside[i].get_or_insert_with(|| message.clone());
+                    if let Some(prop) = properties.remove(prop_name) {
+                        left_out.insert(prop_name.clone(), (prop, message));
+                    }
+                    continue;
+                }
+            };
+            let prop = match properties.get_mut(prop_name) {
+                Some(prop) => prop,
+                None => continue,
+            };
+            used[i] = true;
+            prop.output = self.multiply(&value, &prop.input)?;
+            prop.doc = table.doc.clone();
+        }
+        // Substances without a density of their own, like formulas and
+        // the gaseous elements, are treated as gases.
+        let quantity = |i: usize| self.quantities.get(&values[i].unit).map(|name| &**name);
+        let temperature = (0..values.len()).find(|&i| quantity(i) == Some("temperature"));
+        let pressure = (0..values.len()).find(|&i| quantity(i) == Some("pressure"));
+        let has_density = properties.contains_key("density") || left_out.contains_key("density");
+        if !has_density && (temperature.is_some() || pressure.is_some()) {
+            if let (Some(t), Some(p)) = (temperature, pressure) {
+                self.gas_properties(name, &mut properties, &values[t], &values[p])?;
+                used[t] = true;
+                used[p] = true;
+            } else if let Some(i) = temperature.or(pressure).filter(|&i| !used[i]) {
+                return Err(QueryError::generic(format!(
+                    "Treating {} as a gas needs both a temperature and a pressure, got only <{}>",
+                    name,
+                    values[i].show(self)
+                )));
+            }
+        }
+        if let Some(i) = used.iter().position(|&used| !used) {
+            if let Some(message) = outside[i].take() {
+                return Err(QueryError::generic(message));
+            }
+            let quantity = self
+                .quantities
+                .get(&values[i].unit)
+                .map(|quantity| &**quantity)
+                .unwrap_or("the state");
+            return Err(QueryError::generic(format!(
+                "{} has no properties that depend on {}",
+                name, quantity
+            )));
+        }
+
+        Ok(Value::Substance(Substance {
+            amount: substance.amount,
+            properties: Arc::new(Properties {
+                name: Expr::new_at(expr.clone(), states.to_vec()).to_string(),
+                properties,
+                tables: BTreeMap::new(),
+                left_out,
+            }),
+        }))
+    }
+
+    /// Evaluates `<substance> after <time>`, scaling the amount by the
+    /// fraction of the substance that hasn't decayed, `2^(-time /
+    /// half_life)`.
+    fn eval_after(&self, expr: &Expr, time: &Expr) -> Result<Value, QueryError> {
+        let substance = match self.eval(expr)? {
+            Value::Substance(substance) => substance,
+            x => {
+                return Err(QueryError::generic(format!(
+                    "Expected a radioactive substance before after, got <{}>",
+                    x.show(self)
+                )))
+            }
+        };
+        let name = &substance.properties.name;
+        let half_life = substance
+            .properties
+            .properties
+            .get("half_life")
+            .and_then(|prop| &prop.output / &prop.input)
+            .ok_or_else(|| {
+                QueryError::generic(format!("{} has no half_life, so it doesn't decay", name))
+            })?;
+        let elapsed = match self.eval(time)? {
+            Value::Number(ref elapsed) if elapsed.unit == half_life.unit => elapsed.clone(),
+            x => {
+                return Err(QueryError::generic(format!(
+                    "Expected a time after after, got <{}>",
+                    x.show(self)
+                )))
+            }
+        };
+        let half_lives = (&elapsed / &half_life)
+            .ok_or_else(|| QueryError::generic(format!("{} has a half_life of zero", name)))?;
+        let remaining = Number::new(Numeric::Float((-half_lives.value.to_f64()).exp2()));
+        Ok(Value::Substance(Substance {
+            amount: self.multiply(&substance.amount, &remaining)?,
+            properties: Arc::new(Properties {
+                name: Expr::new_after(expr.clone(), (*time).clone()).to_string(),
+                properties: substance.properties.properties.clone(),
+                tables: substance.properties.tables.clone(),
+                left_out: substance.properties.left_out.clone(),
+            }),
+        }))
+    }
+
+    /// Adds the density and molar volume of a gas at a temperature and
+    /// pressure, from the ideal gas law, or from the van der Waals
+    /// equation when the substance defines `vanderwaals_a` and
+    /// `vanderwaals_b`. Diatomic elements like nitrogen define
+    /// `gas_molar_mass` for the molecule, which the density uses
+    /// instead of `molar_mass`.
+    fn gas_properties(
+        &self,
+        name: &str,
+        properties: &mut BTreeMap<String, Property>,
+        temperature: &Number,
+        pressure: &Number,
+    ) -> Result<(), QueryError> {
+        if temperature.value.to_f64() <= 0.0 {
+            return Err(QueryError::generic(format!(
+                "Treating {} as a gas needs a temperature above absolute zero, got <{}>",
+                name,
+                temperature.show(self)
+            )));
+        }
+        if pressure.value.to_f64() <= 0.0 {
+            return Err(QueryError::generic(format!(
+                "Treating {} as a gas needs a positive pressure, got <{}>",
+                name,
+                pressure.show(self)
+            )));
+        }
+        let molar_mass = match properties
+            .get("gas_molar_mass")
+            .or_else(|| properties.get("molar_mass"))
+            .and_then(|prop| &prop.output / &prop.input)
+        {
+            Some(molar_mass) => molar_mass,
+            None => {
+                return Err(QueryError::generic(format!(
+                    "Treating {} as a gas needs its molar_mass",
+                    name
+                )))
+            }
+        };
+        let gas_constant = self.lookup_required("R")?;
+        let rt = (&gas_constant * temperature).ok_or_else(|| {
+            QueryError::generic(format!("Can't multiply R by <{}>", temperature.show(self)))
+        })?;
+        let ideal =
+            (&rt / pressure).ok_or_else(|| QueryError::generic("Division by zero".to_owned()))?;
+        let constant = |name: &str| {
+            properties
+                .get(name)
+                .and_then(|prop| &prop.output / &prop.input)
+                .map(|value| value.value.to_f64())
+        };
+        let (molar_volume, doc) = match (constant("vanderwaals_a"), constant("vanderwaals_b")) {
+            (Some(a), Some(b)) => {
+                // Newton's method on (P + a/V^2)(V - b) = RT, starting from
+                // the ideal gas, which finds the gas rather than the liquid
+                // root.
+                let rt = rt.value.to_f64();
+                let p = pressure.value.to_f64();
+                let mut v = ideal.value.to_f64() + b;
+                let mut converged = false;
+                for _ in 0..100 {
+                    let f = (p + a / (v * v)) * (v - b) - rt;
+                    let df = p - a / (v * v) + 2.0 * a * b / (v * v * v);
+                    let step = f / df;
+                    v -= step;
+                    if !v.is_finite() || v <= b {
+                        break;
+                    }
+                    if step.abs() <= v.abs() * 1e-12 {
+                        converged = true;
+                        break;
+                    }
+                }
+                if !converged {
+                    return Err(QueryError::generic(format!(
+                        "The van der Waals equation of {} has no gas solution at <{}> and <{}>",
+                        name,
+                        temperature.show(self),
+                        pressure.show(self)
+                    )));
+                }
+                (
+                    Number {
+                        value: Numeric::Float(v),
+                        unit: ideal.unit.clone(),
+                    },
+                    "Van der Waals gas.",
+                )
+            }
+            _ => (ideal, "Ideal gas."),
+        };
+        let mol = Number::one_unit(Dimension::new("mol"));
+        let mass = self.multiply(&molar_mass, &mol)?;
+        let volume = self.multiply(&molar_volume, &mol)?;
+        properties.insert(
+            "density".to_owned(),
+            Property {
+                input: volume.clone(),
+                input_name: "volume".to_owned(),
+                output: mass,
+                output_name: "mass".to_owned(),
+                doc: Some(doc.to_owned()),
+            },
+        );
+        properties.insert(
+            "molar_volume".to_owned(),
+            Property {
+                input: mol,
+                input_name: "amount".to_owned(),
+                output: volume,
+                output_name: "volume".to_owned(),
+                doc: Some(doc.to_owned()),
+            },
+        );
+        Ok(())
+    }
+
+    /// Mixes the substances added together in `expr`, whose amounts
+    /// are either fractions or quantities measured by `basis`. The
+    /// density, molar mass and specific heat of the mixture are worked
+    /// out assuming ideal mixing, where there's no change in volume.
+    fn eval_mixture(&self, expr: &Expr, basis: MixtureBasis) -> Result<Value, QueryError> {
+        fn terms<'a>(expr: &'a Expr, out: &mut Vec<&'a Expr>) {
+            match *expr {
+                Expr::BinOp(BinOpExpr {
+                    op: BinOpType::Add,
+                    ref left,
+                    ref right,
+                }) => {
+                    terms(left, out);
+                    terms(right, out);
+                }
+                ref expr => out.push(expr),
+            }
+        }
+        let mut exprs = vec![];
+        terms(expr, &mut exprs);
+        let components = exprs
+            .into_iter()
+            .map(|expr| match self.eval(expr)? {
+                Value::Substance(sub) => Ok(sub),
+                x => Err(QueryError::generic(format!(
+                    "Expected a substance in mixture, got <{}>",
+                    x.show(self)
+                ))),
+            })
+            .collect::<Result<Vec<_>, QueryError>>()?;
+
+        let mut unit = BTreeMap::new();
+        match basis {
+            MixtureBasis::Mass => unit.insert(Dimension::new("kg"), 1),
+            MixtureBasis::Volume => unit.insert(Dimension::new("m"), 3),
+            MixtureBasis::Mole => unit.insert(Dimension::new("mol"), 1),
+        };
+        let dimless = components[0].amount.dimless();
+        let mut total = None::<Number>;
+        for sub in &components {
+            if !(sub.amount.dimless() && dimless || sub.amount.unit == unit && !dimless) {
+                return Err(QueryError::generic(format!(
+                    "Expected fractions or amounts by {} in mixture, got <{}> of {}",
+                    basis,
+                    sub.amount.show(self),
+                    sub.properties.name
+                )));
+            }
+            total = Some(match total {
+                Some(total) => (&total + &sub.amount)
+                    .ok_or_else(|| QueryError::generic("Mismatched units in mixture".to_owned()))?,
+                None => sub.amount.clone(),
+            });
+        }
+        let total = total.unwrap_or_else(Number::one);
+
+        // The property as a ratio like kg/m^3, regardless of amount.
+        let ratio = |sub: &Substance, name: &str| {
+            let prop = sub.properties.properties.get(name)?;
+            &prop.output / &prop.input
+        };
+        let div = |a: &Number, b: &Number| {
+            (a / b).ok_or_else(|| QueryError::generic("Division by zero in mixture".to_owned()))
+        };
+        let sum = |values: Vec<Number>| -> Result<Number, QueryError> {
+            let mut values = values.into_iter();
+            let first = values.next().unwrap_or_else(Number::one);
+            values.try_fold(first, |acc, value| {
+                (&acc + &value)
+                    .ok_or_else(|| QueryError::generic("Mismatched units in mixture".to_owned()))
+            })
+        };
+
+        // Everything is mixed by mass fraction, so other bases are
+        // weighed first.
+        let masses = components
+            .iter()
+            .map(|sub| {
+                let fraction = div(&sub.amount, &total)?;
+                let per_unit = match basis {
+                    MixtureBasis::Mass => return Ok(fraction),
+                    MixtureBasis::Volume => "density",
+                    MixtureBasis::Mole => "molar_mass",
+                };
+                let per_unit = ratio(sub, per_unit).ok_or_else(|| {
+                    QueryError::generic(format!(
+                        "{} has no {}, which is needed to mix by {}",
+                        sub.properties.name, per_unit, basis
+                    ))
+                })?;
+                self.multiply(&fraction, &per_unit)
+            })
+            .collect::<Result<Vec<_>, QueryError>>()?;
+        let total_mass = sum(masses.clone())?;
+        let weights = masses
+            .iter()
+            .map(|mass| div(mass, &total_mass))
+            .collect::<Result<Vec<_>, QueryError>>()?;
+
+        let mut properties = BTreeMap::new();
+        for &(name, harmonic) in &[
+            ("density", true),
+            ("molar_mass", true),
+            ("specific_heat", false),
+        ] {
+            let template = match components[0].properties.properties.get(name) {
+                Some(template) => template,
+                None => continue,
+            };
+            let values = match components
+                .iter()
+                .map(|sub| ratio(sub, name))
+                .collect::<Option<Vec<_>>>()
+            {
+                Some(values) => values,
+                None => continue,
+            };
+            let terms = values
+                .iter()
+                .zip(&weights)
+                .map(|(value, weight)| {
+                    if harmonic {
+                        div(weight, value)
+                    } else {
+                        self.multiply(weight, value)
+                    }
+                })
+                .collect::<Result<Vec<_>, QueryError>>()?;
+            let mixed = sum(terms)?;
+            let mixed = if harmonic {
+                div(&Number::one(), &mixed)?
+            } else {
+                mixed
+            };
+            properties.insert(
+                name.to_owned(),
+                Property {
+                    output: self.multiply(&mixed, &template.input)?,
+                    output_name: template.output_name.clone(),
+                    input: template.input.clone(),
+                    input_name: template.input_name.clone(),
+                    doc: None,
+                },
+            );
+        }
+
+        Ok(Value::Substance(Substance {
+            amount: if dimless { Number::one() } else { total },
+            properties: Arc::new(Properties {
+                name: Expr::new_mixture(expr.clone(), basis).to_string(),
+                properties,
+                tables: BTreeMap::new(),
+                left_out: BTreeMap::new(),
+            }),
+        }))
+    }
+
+    /// Evaluates `date + N businessdays` and `date - N businessdays`,
+    /// which have to be recognized before evaluating since business
+    /// days aren't a unit.
+    fn eval_business_days(&self, binop: &BinOpExpr) -> Result<Option<Value>, QueryError> {
+        let (date, offset, (days, calendar)) = match binop.op {
+            BinOpType::Add => match (
+                self.business_days(&binop.left)?,
+                self.business_days(&binop.right)?,
+            ) {
+                (None, Some(offset)) => (&binop.left, &binop.right, offset),
+                (Some(offset), None) => (&binop.right, &binop.left, offset),
+                _ => return Ok(None),
+            },
+            BinOpType::Sub => match self.business_days(&binop.right)? {
+                Some((days, calendar)) => (&binop.left, &binop.right, (-days, calendar)),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let calendar = self.business_calendar(calendar.as_deref())?;
+        let date = self.eval(date)?;
+        date.add_business_days(days, calendar, &|| self.step())
+            .map(Some)
+            .map_err(|err| match err {
+                QueryError::Generic { message } => QueryError::Operation(OperationError {
+                    message,
+                    operator: binop.op.symbol().trim().to_owned(),
+                    operands: vec![date.show(self), offset.to_string()],
+                }),
+                err => err,
+            })
+    }
+
+    /// Recognizes offsets like `10 businessdays` or `us workday`.
+    /// Returns the number of days and the calendar name, if any.
+    fn business_days(&self, expr: &Expr) -> Result<Option<(i64, Option<String>)>, QueryError> {
+        fn is_unit(expr: &Expr) -> bool {
+            match *expr {
+                Expr::Unit { ref name } => matches!(
+                    &**name,
+                    "businessday" | "businessdays" | "workday" | "workdays"
+                ),
+                _ => false,
+            }
+        }
+        match *expr {
+            ref unit if is_unit(unit) => Ok(Some((1, None))),
+            Expr::UnaryOp(UnaryOpExpr {
+                op: UnaryOpType::Negative,
+                ref expr,
+            }) => Ok(self
+                .business_days(expr)?
+                .map(|(days, calendar)| (-days, calendar))),
+            Expr::Mul { ref exprs } => {
+                let count = match exprs.split_last() {
+                    Some((unit, count)) if is_unit(unit) => count,
+                    _ => return Ok(None),
+                };
+                let (count, calendar) = match count.split_last() {
+                    Some((Expr::Unit { name }, count))
+                        if self.business_calendars.contains_key(name) =>
+                    {
+                        (count, Some(name.clone()))
+                    }
+                    _ => (count, None),
+                };
+                if count.is_empty() {
+                    return Ok(Some((1, calendar)));
+                }
+                let count = match self.eval(&Expr::Mul {
+                    exprs: count.to_vec(),
+                })? {
+                    Value::Number(ref count) if count.unit.is_empty() => count.value.clone(),
+                    _ => {
+                        return Err(QueryError::generic(
+                            "Expected a number of business days".to_owned(),
+                        ))
+                    }
+                };
+                if count.div_rem(&Numeric::one()).1 != Numeric::zero() {
+                    return Err(QueryError::generic(
+                        "Business days must be a whole number".to_owned(),
+                    ));
+                }
+                count
+                    .to_int()
+                    .filter(|days| days.checked_neg().is_some())
+                    .map(|days| Some((days, calendar)))
+                    .ok_or_else(|| QueryError::generic("Too many business days".to_owned()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Looks up a calendar from `holidays.txt`, or the context's
+    /// default calendar when no name is given.
+    fn business_calendar(&self, name: Option<&str>) -> Result<&BusinessCalendar, QueryError> {
+        match name {
+            None => Ok(&self.business_calendar),
+            Some(name) => self.business_calendars.get(name).ok_or_else(|| {
+                QueryError::generic(format!(
+                    "No such business calendar: {}. Known calendars are: {}",
+                    name,
+                    self.business_calendars
+                        .keys()
+                        .map(|name| &**name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            }),
+        }
+    }
+
+    /// Breaks the time between two dates down into calendar years,
+    /// months and days.
+    fn calendar_duration(
+        &self,
+        left: &date::GenericDateTime,
+        right: &date::GenericDateTime,
+    ) -> Result<DurationReply, QueryError> {
+        let (months, rest) = date::calendar_difference(left, right);
+        let rest = date::from_duration(&rest).map_err(QueryError::generic)?;
+        let raw = date::from_duration(&(left.with_timezone(&Utc) - right.with_timezone(&Utc)))
+            .map_err(QueryError::generic)?;
+        let whole = |value: i64, name: &str| {
+            let mut raw = BTreeMap::new();
+            raw.insert(Dimension::new(name), 1);
+            NumberParts {
+                exact_value: Some(value.to_string()),
+                unit: Some(name.to_owned()),
+                raw_unit: Some(raw),
+                ..Default::default()
+            }
+        };
+        let list = self.to_list(&rest, &["day", "hour", "minute", "second"])?;
+        let mut list = list.into_iter();
+        Ok(DurationReply {
+            raw: raw.to_parts(self),
+            years: whole(months / 12, "year"),
+            months: whole(months % 12, "month"),
+            weeks: whole(0, "week"),
+            days: list.next().expect("Unexpected end of iterator"),
+            hours: list.next().expect("Unexpected end of iterator"),
+            minutes: list.next().expect("Unexpected end of iterator"),
+            seconds: list.next().expect("Unexpected end of iterator"),
+        })
+    }
+
     fn to_list(&self, top: &Number, list: &[&str]) -> Result<Vec<NumberParts>, QueryError> {
         let units = list
             .iter()
             .map(|x| self.lookup(x).ok_or_else(|| self.unknown_unit_err(x)))
             .collect::<Result<Vec<Number>, _>>()?;
+        if let Some((name, _)) = list
+            .iter()
+            .zip(&units)
+            .find(|(_, unit)| unit.value == Numeric::zero())
+        {
+            return Err(QueryError::generic(format!(
+                "Unit {} in unit list is zero",
+                name
+            )));
+        }
         {
             let first = units
                 .first()
@@ -698,6 +1702,10 @@ impl Context {
 
     /// Evaluates an expression, include `->` conversions.
     pub fn eval_outer(&self, expr: &Query) -> Result<QueryReply, QueryError> {
+        self.begin_query();
+        if let Query::Convert(_, _, _, Digits::Digits(digits)) = *expr {
+            self.limits.check_output_len(digits as usize)?;
+        }
         match *expr {
             Query::Expr(Expr::Unit { ref name })
                 if {
@@ -790,6 +1798,7 @@ impl Context {
                         )))
                     }
                 };
+                self.check_digits(&top.value, base, digits)?;
                 let (exact, approx) = top.numeric_value(base, digits);
                 let parts = NumberParts {
                     exact_value: exact,
@@ -817,6 +1826,7 @@ impl Context {
                         )))
                     }
                 };
+                self.check_digits(&top.value, base.unwrap_or(10), digits)?;
                 let (exact, approx) = top.numeric_value(base.unwrap_or(10), digits);
                 let parts = NumberParts {
                     exact_value: exact,
@@ -827,68 +1837,130 @@ impl Context {
                     value: parts,
                 })))
             }
-            Query::Convert(ref top, Conversion::Expr(ref bottom), base, digits) => match (
-                self.eval(top)?,
-                self.eval(bottom)?,
-                self.eval_unit_name(bottom)?,
-            ) {
-                (Value::Number(top), Value::Number(bottom), (bottom_name, bottom_const)) => {
-                    if top.unit == bottom.unit {
-                        let raw = match &top / &bottom {
-                            Some(raw) => raw,
-                            None => {
-                                return Err(QueryError::generic(format!(
-                                    "Division by zero: {} / {}",
-                                    top.show(self),
-                                    bottom.show(self)
-                                )))
-                            }
-                        };
-                        Ok(QueryReply::Conversion(Box::new(self.show(
-                            &raw,
-                            &bottom,
+            Query::Convert(ref top, Conversion::Expr(ref bottom), base, digits) => {
+                let top = self.eval(top)?;
+                if let (Value::DateTime(ref date), Some(zone)) = (&top, self.timezone_alias(bottom))
+                {
+                    let date = match zone.map_err(QueryError::generic)? {
+                        Zone::Named(tz) => DateReply::new(self, date.with_timezone(&tz)),
+                        Zone::Offset(offset) => DateReply::new(self, date.with_timezone(&offset)),
+                    };
+                    return Ok(QueryReply::Date(date));
+                }
+                let bottom_value = self.eval(bottom)?;
+                if let Value::Interval(ref interval) = bottom_value {
+                    let value = match top {
+                        Value::DateTime(ref date) => interval.contains(date),
+                        Value::Interval(ref other) => interval.contains_interval(other),
+                        ref x => {
+                            return Err(self.with_operands(
+                                operation_error("->", "Operation is not defined".to_owned()),
+                                &[x, &bottom_value],
+                            ))
+                        }
+                    };
+                    return Ok(QueryReply::Boolean(BooleanReply { value }));
+                }
+                // `-> g NaCl` converts an amount of NaCl like `-> g`, as long
+                // as the substances match.
+                if let (Value::Substance(ref sub), Value::Substance(ref unit)) =
+                    (&top, &bottom_value)
+                {
+                    let unit_expr = match *bottom {
+                        Expr::Mul { ref exprs } if sub.properties.name == unit.properties.name => {
+                            Expr::new_mul(exprs[..exprs.len() - 1].to_vec())
+                        }
+                        _ => {
+                            return Err(self.with_operands(
+                                operation_error("->", "Operation is not defined".to_owned()),
+                                &[&top, &bottom_value],
+                            ))
+                        }
+                    };
+                    let (bottom_name, bottom_const) = self.eval_unit_name(&unit_expr)?;
+                    return sub
+                        .get_in_unit(
+                            unit.amount.clone(),
+                            self,
                             bottom_name,
                             bottom_const,
                             base.unwrap_or(10),
                             digits,
-                        ))))
-                    } else {
-                        Err(QueryError::Conformance(Box::new(
-                            self.conformance_err(&top, &bottom),
-                        )))
-                    }
+                        )
+                        .map_err(QueryError::generic)
+                        .map(QueryReply::Substance);
                 }
-                (Value::Substance(sub), Value::Number(bottom), (bottom_name, bottom_const)) => sub
-                    .get_in_unit(
-                        bottom,
-                        self,
-                        bottom_name,
-                        bottom_const,
-                        base.unwrap_or(10),
-                        digits,
-                    )
-                    .map_err(QueryError::generic)
-                    .map(QueryReply::Substance),
-                (Value::Number(top), Value::Substance(mut sub), (bottom_name, bottom_const)) => {
-                    let unit = sub.amount.clone();
-                    sub.amount = top;
-                    sub.get_in_unit(
-                        unit,
-                        self,
-                        bottom_name,
-                        bottom_const,
-                        base.unwrap_or(10),
-                        digits,
-                    )
-                    .map_err(QueryError::generic)
-                    .map(QueryReply::Substance)
+                // Intervals convert like their duration, as in `.. -> days`.
+                let top = match top {
+                    Value::Interval(ref interval) => Value::Number(
+                        date::from_duration(&interval.duration()).map_err(QueryError::generic)?,
+                    ),
+                    top => top,
+                };
+                match (top, bottom_value, self.eval_unit_name(bottom)?) {
+                    (Value::Number(top), Value::Number(bottom), (bottom_name, bottom_const)) => {
+                        if top.unit == bottom.unit {
+                            let raw = match &top / &bottom {
+                                Some(raw) => raw,
+                                None => {
+                                    return Err(QueryError::generic(format!(
+                                        "Division by zero: {} / {}",
+                                        top.show(self),
+                                        bottom.show(self)
+                                    )))
+                                }
+                            };
+                            self.check_digits(&raw.value, base.unwrap_or(10), digits)?;
+                            Ok(QueryReply::Conversion(Box::new(self.show(
+                                &raw,
+                                &bottom,
+                                bottom_name,
+                                bottom_const,
+                                base.unwrap_or(10),
+                                digits,
+                            ))))
+                        } else {
+                            Err(QueryError::Conformance(Box::new(
+                                self.conformance_err(&top, &bottom),
+                            )))
+                        }
+                    }
+                    (Value::Substance(sub), Value::Number(bottom), (bottom_name, bottom_const)) => {
+                        sub.get_in_unit(
+                            bottom,
+                            self,
+                            bottom_name,
+                            bottom_const,
+                            base.unwrap_or(10),
+                            digits,
+                        )
+                        .map_err(QueryError::generic)
+                        .map(QueryReply::Substance)
+                    }
+                    (
+                        Value::Number(top),
+                        Value::Substance(mut sub),
+                        (bottom_name, bottom_const),
+                    ) => {
+                        let unit = sub.amount.clone();
+                        sub.amount = top;
+                        sub.get_in_unit(
+                            unit,
+                            self,
+                            bottom_name,
+                            bottom_const,
+                            base.unwrap_or(10),
+                            digits,
+                        )
+                        .map_err(QueryError::generic)
+                        .map(QueryReply::Substance)
+                    }
+                    (x, y, _) => Err(self.with_operands(
+                        operation_error("->", "Operation is not defined".to_owned()),
+                        &[&x, &y],
+                    )),
                 }
-                (x, y, _) => Err(QueryError::generic(format!(
-                    "Operation is not defined: <{}> -> <{}>",
-                    x.show(self),
-                    y.show(self)
-                ))),
-            },
+            }
             Query::Convert(ref top, Conversion::List(ref list), None, Digits::Default) => {
                 let top = self.eval(top)?;
                 let top = match top {
@@ -924,7 +1996,10 @@ impl Context {
                         )))
                     }
                 };
-                let top = top.with_timezone(&FixedOffset::east(off as i32));
+                let offset = FixedOffset::east_opt(off as i32).ok_or_else(|| {
+                    QueryError::generic(format!("Timezone offset {:+} is out of range", off))
+                })?;
+                let top = top.with_timezone(&offset);
                 Ok(QueryReply::Date(DateReply::new(self, top)))
             }
             Query::Convert(ref top, Conversion::Timezone(tz), None, Digits::Default) => {
@@ -942,6 +2017,56 @@ impl Context {
                 let top = top.with_timezone(&tz);
                 Ok(QueryReply::Date(DateReply::new(self, top)))
             }
+            Query::Convert(ref top, Conversion::DateFormat(ref format), None, Digits::Default) => {
+                let top = self.eval(top)?;
+                let (mut reply, string) = match top {
+                    Value::DateTime(date::GenericDateTime::Fixed(date)) => {
+                        let mut reply = DateReply::new(self, date);
+                        reply.add_formats(self, &date);
+                        (reply, date::format_date(self, &date, format))
+                    }
+                    Value::DateTime(date::GenericDateTime::Timezone(date)) => {
+                        let mut reply = DateReply::new(self, date);
+                        reply.add_formats(self, &date);
+                        (reply, date::format_date(self, &date, format))
+                    }
+                    _ => {
+                        return Err(QueryError::generic(format!(
+                            "Cannot convert <{}> to {}",
+                            top.show(self),
+                            format
+                        )))
+                    }
+                };
+                reply.string = string.map_err(QueryError::generic)?;
+                // The output is meant to be machine readable.
+                reply.human = None;
+                Ok(QueryReply::Date(reply))
+            }
+            Query::Convert(ref top, Conversion::Calendar(calendar), None, Digits::Default) => {
+                let date = match self.eval(top)? {
+                    Value::DateTime(date) => date,
+                    top => {
+                        return Err(QueryError::generic(format!(
+                            "Cannot convert <{}> to {}",
+                            top.show(self),
+                            calendar
+                        )))
+                    }
+                };
+                // The calendar date where the date is, not in UTC.
+                let converted = CalendarDate::from_gregorian(calendar, date.naive_local().date());
+                let mut reply = DateReply::from_generic(self, &date);
+                reply.string = converted.to_string();
+                reply.calendar = Some(CalendarDateReply {
+                    calendar: calendar.to_string(),
+                    year: converted.year,
+                    month: converted.month,
+                    month_name: converted.month_name().to_owned(),
+                    day: converted.day,
+                });
+                Ok(QueryReply::Date(reply))
+            }
             Query::Convert(ref top, Conversion::Degree(ref deg), None, digits) => {
                 let (name, base, scale) = deg.name_base_scale();
 
@@ -956,20 +2081,22 @@ impl Context {
                         )))
                     }
                 };
-                let bottom = self
-                    .lookup(scale)
-                    .expect(&*format!("Unit {} missing", scale));
+                let bottom = self.lookup_required(scale)?;
                 if top.unit != bottom.unit {
                     Err(QueryError::Conformance(Box::new(
                         self.conformance_err(&top, &bottom),
                     )))
                 } else {
-                    let res = (top
-                        - &self
-                            .lookup(base)
-                            .expect(&*format!("Constant {} missing", base)))
-                        .unwrap();
-                    let res = (&res / &bottom).unwrap();
+                    let mismatched = || {
+                        QueryError::generic(format!(
+                            "Definitions of °{} have mismatched units",
+                            name
+                        ))
+                    };
+                    let res = (top - &self.lookup_required(base)?).ok_or_else(mismatched)?;
+                    let res = (&res / &bottom)
+                        .ok_or_else(|| QueryError::generic(format!("{} is zero", scale)))?;
+                    self.check_digits(&res.value, 10, digits)?;
                     let mut name = BTreeMap::new();
                     name.insert(deg.to_string(), 1);
                     Ok(QueryReply::Conversion(Box::new(self.show(
@@ -1022,12 +2149,19 @@ impl Context {
                     }
                     Some(val) => val,
                 };
+                // The search recurses once for each unit power removed.
+                if val.complexity_score() > 100 {
+                    return Err(QueryError::generic(format!(
+                        "Cannot factorize <{}>: the unit is too complex",
+                        val.show(self)
+                    )));
+                }
                 let quantities = self
                     .quantities
                     .iter()
                     .map(|(a, b)| (a.clone(), Rc::new(b.clone())))
                     .collect::<BTreeMap<_, _>>();
-                let results = factorize(&val, &quantities);
+                let results = factorize(&val, &quantities, &|| self.step())?;
                 let mut results = results.into_sorted_vec();
                 results.dedup();
                 let results = results
@@ -1130,35 +2264,50 @@ impl Context {
             Query::Search(ref string) => Ok(QueryReply::Search(SearchReply {
                 results: search::search(self, &**string, 5)
                     .into_iter()
-                    .map(|x| {
-                        let parts = self
-                            .lookup(x)
-                            .map(|x| x.to_parts(self))
-                            .or_else(|| {
-                                if self.substances.get(x).is_some() {
-                                    Some(NumberParts {
-                                        quantity: Some("substance".to_owned()),
-                                        ..Default::default()
-                                    })
-                                } else {
-                                    None
-                                }
-                            })
-                            .expect("Search returned non-existent result");
+                    // Names with broken definitions can't be shown.
+                    .filter_map(|x| {
+                        let parts = self.lookup(x).map(|x| x.to_parts(self)).or_else(|| {
+                            if self.substances.get(x).is_some() {
+                                Some(NumberParts {
+                                    quantity: Some("substance".to_owned()),
+                                    ..Default::default()
+                                })
+                            } else {
+                                None
+                            }
+                        })?;
                         let mut raw = BTreeMap::new();
                         raw.insert(Dimension::new(x), 1);
-                        NumberParts {
+                        Some(NumberParts {
                             unit: Some(x.to_owned()),
                             raw_unit: Some(raw),
                             quantity: parts.quantity,
                             ..Default::default()
-                        }
+                        })
                     })
                     .collect(),
             })),
             Query::Expr(ref expr)
             | Query::Convert(ref expr, Conversion::None, None, Digits::Default) => {
-                let val = self.eval(expr)?;
+                let val = match *expr {
+                    Expr::BinOp(ref binop) if binop.op == BinOpType::Sub => {
+                        if let Some(value) = self.eval_business_days(binop)? {
+                            value
+                        } else {
+                            let left = self.eval(&binop.left)?;
+                            let right = self.eval(&binop.right)?;
+                            if let (Value::DateTime(ref left), Value::DateTime(ref right)) =
+                                (&left, &right)
+                            {
+                                return Ok(QueryReply::Duration(Box::new(
+                                    self.calendar_duration(left, right)?,
+                                )));
+                            }
+                            self.eval_binop(binop, &left, &right)?
+                        }
+                    }
+                    _ => self.eval(expr)?,
+                };
                 match val {
                     Value::Number(ref n)
                         if n.unit == Number::one_unit(Dimension::new("s")).unit =>
@@ -1196,12 +2345,333 @@ impl Context {
                             Ok(QueryReply::Date(DateReply::new(self, d)))
                         }
                     },
+                    Value::Interval(interval) => {
+                        Ok(QueryReply::Interval(Box::new(IntervalReply {
+                            start: DateReply::from_generic(self, &interval.start),
+                            end: DateReply::from_generic(self, &interval.end),
+                            duration: self.calendar_duration(&interval.end, &interval.start)?,
+                        })))
+                    }
                     Value::Substance(s) => Ok(QueryReply::Substance(
                         s.to_reply(self).map_err(QueryError::generic)?,
                     )),
                 }
             }
-            Query::Error(ref e) => Err(QueryError::generic(e.clone())),
+            Query::BusinessDays(ref start, ref end, ref calendar) => {
+                let calendar = self.business_calendar(calendar.as_deref())?;
+                let start = self.eval(start)?;
+                let end = self.eval(end)?;
+                let days = start.business_days_between(&end, calendar, &|| self.step())?;
+                Ok(QueryReply::Number(days.to_parts(self)))
+            }
+            Query::Recurrence(ref step, ref start, ref end) => {
+                let (start, end) = match (self.eval(start)?, self.eval(end)?) {
+                    (Value::DateTime(start), Value::DateTime(end)) => (start, end),
+                    (start, end) => {
+                        return Err(QueryError::generic(format!(
+                            "Expected dates for every <step> from <start> until <end>, \
+                             got <{}> and <{}>",
+                            start.show(self),
+                            end.show(self)
+                        )))
+                    }
+                };
+                let interval = date::Interval::new(start, end).map_err(QueryError::generic)?;
+                let positive =
+                    || QueryError::generic("Recurrence step must be positive".to_owned());
+                let dates = if let Some(months) = self.calendar_months(step)? {
+                    if months <= 0 {
+                        return Err(positive());
+                    }
+                    // Each date is counted from the start rather than the
+                    // previous date, so monthly from Jan 31 stays on the 31st
+                    // where the month has one.
+                    interval.recurrence(MAX_RECURRENCE, |count| {
+                        date::add_months(&start, months * i64::from(count))
+                    })
+                } else {
+                    let step = match self.eval(step)? {
+                        Value::Number(ref step) => date::to_duration(step).ok(),
+                        _ => None,
+                    }
+                    .ok_or_else(|| {
+                        QueryError::generic("Recurrence step must be a duration".to_owned())
+                    })?;
+                    if step <= Duration::zero() {
+                        return Err(positive());
+                    }
+                    interval.recurrence(MAX_RECURRENCE, |count| {
+                        date::add_duration(&start, step * count)
+                    })
+                }
+                .map_err(QueryError::generic)?;
+                Ok(QueryReply::Recurrence(RecurrenceReply {
+                    dates: dates
+                        .iter()
+                        .map(|date| DateReply::from_generic(self, date))
+                        .collect(),
+                }))
+            }
+            Query::DatePatterns(ref tokens) => {
+                let builtin = match date::builtin_date(tokens, self) {
+                    Some(Ok(date)) => Some(DateReply::from_generic(self, &date)),
+                    Some(Err(e)) => {
+                        return Err(QueryError::InvalidDate(DateError {
+                            input: tokens.iter().map(ToString::to_string).collect(),
+                            message: e,
+                        }))
+                    }
+                    None => None,
+                };
+                let patterns = date::try_patterns(tokens, self)
+                    .into_iter()
+                    .map(|(pat, res)| {
+                        let pattern = DatePattern::show(pat);
+                        match res {
+                            Ok(date) => DatePatternResult {
+                                pattern,
+                                date: Some(DateReply::from_generic(self, &date)),
+                                error: None,
+                            },
+                            Err(e) => DatePatternResult {
+                                pattern,
+                                date: None,
+                                error: Some(e),
+                            },
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                Ok(QueryReply::DatePatterns(DatePatternsReply {
+                    input: tokens.iter().map(ToString::to_string).collect(),
+                    builtin,
+                    matched: patterns.iter().position(|pat| pat.date.is_some()),
+                    patterns,
+                }))
+            }
+            Query::Composition(ref expr) => {
+                let (name, moles) = self.formula_moles(expr)?;
+                let substance = self.formula_amount(&name, moles.clone())?;
+                let compound_mass = substance
+                    .get("mass")
+                    .map_err(|_| QueryError::generic(format!("{} has no mass", name)))?;
+                let formula = formula::parse_formula(&name).map_err(QueryError::generic)?;
+                let components =
+                    formula::components(&formula, &self.substance_symbols, &self.substances)
+                        .ok_or_else(|| {
+                            QueryError::generic(format!("{} is not a chemical formula", name))
+                        })?;
+                let hundred = Number::new(Numeric::from(100));
+                let composition = components
+                    .into_iter()
+                    .map(|component| {
+                        let count = Number::new(Numeric::from(BigInt::from(component.count)));
+                        let amount = self.multiply(&moles, &count)?;
+                        let mass = self.multiply(&amount, &component.molar_mass)?;
+                        let percent = (&self.multiply(&mass, &hundred)? / &compound_mass)
+                            .ok_or_else(|| QueryError::generic(format!("{} has no mass", name)))?;
+                        Ok(ElementReply {
+                            symbol: component.symbol,
+                            name: component.name,
+                            count: component.count,
+                            amount: amount.to_parts(self),
+                            mass: mass.to_parts(self),
+                            mass_percent: percent.to_parts(self),
+                        })
+                    })
+                    .collect::<Result<Vec<_>, QueryError>>()?;
+                let mut reply = substance.to_reply(self).map_err(QueryError::generic)?;
+                reply.composition = composition;
+                Ok(QueryReply::Substance(reply))
+            }
+            Query::Properties(ref expr) => {
+                let substance = match self.eval(expr)? {
+                    Value::Substance(substance) => substance,
+                    _ => return Err(QueryError::generic(format!("{} is not a substance", expr))),
+                };
+                let name = substance.properties.name.clone();
+                let properties = substance
+                    .properties
+                    .properties
+                    .iter()
+                    .map(|(prop_name, prop)| {
+                        let constant = prop.input == Number::one();
+                        PropertyInfoReply {
+                            name: prop_name.clone(),
+                            output_name: prop.output_name.clone(),
+                            output: prop.output.prettify(self).to_parts(self),
+                            input_name: if constant {
+                                None
+                            } else {
+                                Some(prop.input_name.clone())
+                            },
+                            input: if constant {
+                                None
+                            } else {
+                                Some(prop.input.prettify(self).to_parts(self))
+                            },
+                            doc: prop.doc.clone(),
+                        }
+                    })
+                    .collect();
+                Ok(QueryReply::Properties(PropertiesReply {
+                    doc: self.docs.get(&name).cloned(),
+                    name,
+                    properties,
+                }))
+            }
+            Query::SubstancesWith(ref property) => {
+                // Aliases like `H2O` share the properties of the substance
+                // they name, so each substance is listed once, under the
+                // name it was defined with.
+                let substances = self
+                    .substances
+                    .values()
+                    .filter(|substance| substance.properties.properties.contains_key(property))
+                    .map(|substance| substance.properties.name.clone())
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect::<Vec<_>>();
+                if substances.is_empty() {
+                    return Err(QueryError::generic(format!(
+                        "No substances have a property named {}",
+                        property
+                    )));
+                }
+                Ok(QueryReply::SubstanceList(SubstanceListReply {
+                    property: property.clone(),
+                    substances,
+                }))
+            }
+            Query::Dilution(ref solution, ref target) => {
+                let expected = || {
+                    QueryError::generic(
+                        "Expected `dilute <volume> of <concentration> to <concentration>`"
+                            .to_owned(),
+                    )
+                };
+                let (unit, volume, start) = match *solution {
+                    Expr::Of {
+                        ref property,
+                        ref expr,
+                    } => (property, Expr::new_unit(property.clone()), expr),
+                    Expr::Mul { ref exprs } => match exprs.split_last() {
+                        Some((Expr::Of { property, expr }, rest)) => {
+                            let mut volume = rest.to_vec();
+                            volume.push(Expr::new_unit(property.clone()));
+                            (property, Expr::new_mul(volume), expr)
+                        }
+                        _ => return Err(expected()),
+                    },
+                    _ => return Err(expected()),
+                };
+                // Concentrations can be given with or without the solute.
+                let concentration = |expr: &Expr| match self.eval(expr)? {
+                    Value::Number(num) => Ok(num),
+                    Value::Substance(sub) => Ok(sub.amount),
+                    x => Err(QueryError::generic(format!(
+                        "Expected a concentration, got <{}>",
+                        x.show(self)
+                    ))),
+                };
+                let volume = match self.eval(&volume)? {
+                    Value::Number(num) => num,
+                    x => {
+                        return Err(QueryError::generic(format!(
+                            "Expected a volume, got <{}>",
+                            x.show(self)
+                        )))
+                    }
+                };
+                let (from, to) = (concentration(start)?, concentration(target)?);
+                if from.unit != to.unit {
+                    return Err(QueryError::Conformance(Box::new(
+                        self.conformance_err(&from, &to),
+                    )));
+                }
+                let ratio = (&from / &to)
+                    .ok_or_else(|| QueryError::generic("Division by zero".to_owned()))?;
+                if ratio.value < Numeric::one() {
+                    return Err(QueryError::generic(format!(
+                        "Can't dilute <{}> to the higher concentration <{}>",
+                        from.show(self),
+                        to.show(self)
+                    )));
+                }
+                let diluted = self.multiply(&volume, &ratio)?;
+                let solvent = (&diluted - &volume).ok_or_else(|| {
+                    QueryError::generic("Mismatched units in dilution".to_owned())
+                })?;
+                // Shown in the unit the volume was given in.
+                let unit_expr = Expr::new_unit(unit.clone());
+                let (bottom_name, bottom_const) = self.eval_unit_name(&unit_expr)?;
+                let unit = self.lookup(unit).ok_or_else(expected)?;
+                let show = |num: &Number| {
+                    self.show(
+                        num,
+                        &unit,
+                        bottom_name.clone(),
+                        bottom_const.clone(),
+                        10,
+                        Digits::Default,
+                    )
+                    .value
+                };
+                Ok(QueryReply::Dilution(DilutionReply {
+                    volume: show(&self.divide(&diluted, &unit)?),
+                    solvent: show(&self.divide(&solvent, &unit)?),
+                }))
+            }
+            Query::Balance(ref reactants, ref products, ref amount) => {
+                let coefficients =
+                    reaction::balance(reactants, products).map_err(QueryError::generic)?;
+                let names = reactants.iter().chain(products);
+                // How many times the reaction happens, in moles.
+                let extent = match *amount {
+                    Some(ref amount) => {
+                        let (name, moles) = self.formula_moles(amount)?;
+                        let coefficient = names
+                            .clone()
+                            .zip(&coefficients)
+                            .find(|&(species, _)| *species == name)
+                            .map(|(_, &coefficient)| coefficient)
+                            .ok_or_else(|| {
+                                QueryError::generic(format!("{} is not part of the reaction", name))
+                            })?;
+                        let coefficient = Number::new(Numeric::from(BigInt::from(coefficient)));
+                        Some(self.divide(&moles, &coefficient)?)
+                    }
+                    None => None,
+                };
+                let mut species = names
+                    .zip(coefficients)
+                    .map(|(name, coefficient)| {
+                        let substance = match extent {
+                            Some(ref extent) => {
+                                let coefficient =
+                                    Number::new(Numeric::from(BigInt::from(coefficient)));
+                                let moles = self.multiply(extent, &coefficient)?;
+                                Some(
+                                    self.formula_amount(name, moles)?
+                                        .to_reply(self)
+                                        .map_err(QueryError::generic)?,
+                                )
+                            }
+                            None => None,
+                        };
+                        Ok(SpeciesReply {
+                            coefficient,
+                            formula: name.clone(),
+                            substance,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, QueryError>>()?;
+                let products = species.split_off(reactants.len());
+                Ok(QueryReply::Balance(BalanceReply {
+                    reactants: species,
+                    products,
+                }))
+            }
+            Query::Error(ref e) => Err(QueryError::parse(e.clone())),
         }
     }
 }
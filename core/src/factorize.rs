@@ -2,6 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::limits::LimitExceeded;
 use crate::number::{Dimension, Number, Quantity};
 use crate::numeric::Numeric;
 use std::cmp;
@@ -64,18 +65,23 @@ pub fn fast_decompose(value: &Number, quantities: &BTreeMap<Quantity, String>) -
     value.unit.clone()
 }
 
+/// Finds combinations of quantities which multiply together to form
+/// `value`'s unit. `step` is called for every candidate considered so
+/// that the search can be aborted by `Context::limits`.
 pub fn factorize(
     value: &Number,
     quantities: &BTreeMap<Quantity, Rc<String>>,
-) -> BinaryHeap<Factors> {
+    step: &dyn Fn() -> Result<(), LimitExceeded>,
+) -> Result<BinaryHeap<Factors>, LimitExceeded> {
     if value.dimless() {
         let mut map = BinaryHeap::new();
         map.push(Factors(0, vec![]));
-        return map;
+        return Ok(map);
     }
     let mut candidates: BinaryHeap<Factors> = BinaryHeap::new();
     let value_score = value.complexity_score();
     for (unit, name) in quantities.iter().rev() {
+        step()?;
         let num = Number {
             value: Numeric::one(),
             unit: unit.clone(),
@@ -87,7 +93,7 @@ pub fn factorize(
         if score >= value_score {
             continue;
         }
-        let res = factorize(&res, quantities);
+        let res = factorize(&res, quantities, step)?;
         for Factors(score, mut vec) in res {
             vec.push(name.clone());
             vec.sort();
@@ -98,5 +104,5 @@ pub fn factorize(
         candidates = next.into_iter().take(10).collect();
     }
     assert!(candidates.len() <= 10);
-    candidates
+    Ok(candidates)
 }
@@ -2,55 +2,341 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+//! Chemical formulas like `H2O`, `Ca(OH)2`, `CuSO4·5H2O`,
+//! `[Fe(CN)6]3-`, `SO4^2-` and `13CO2`.
+//!
+//! Digits at the start of a formula or group are a mass number, so
+//! `13C` and `[18O]2` name isotopes, and superscript digits like `¹³C`
+//! are too wherever they appear. `2H` and `3H` are read as `D` and `T`.
+//! Each part after a hydrate dot can start with a multiplier instead.
+//! A charge goes at the end, written as `+`, `2-`, `^2-` or `²⁻`, or
+//! with repeated signs like `++`.
+//!
+//! In queries, formulas with brackets or a charge have to be quoted,
+//! like `"Ca(OH)2"`, since those characters are operators otherwise.
+//! The same goes for a leading mass number: `13CO2` unquoted is 13
+//! times CO2, just as `5H2O` is 5 times H2O.
+
+use crate::bigint::BigInt;
+use crate::bigrat::BigRat;
 use crate::number::{Dimension, Number};
 use crate::numeric::Numeric;
 use crate::substance::{Properties, Property, Substance};
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::iter::Peekable;
-use std::str::{Chars, FromStr};
+use std::str::Chars;
 use std::sync::Arc;
 
-enum Token {
-    Symbol(String),
-    Count(u32),
-    Error,
+/// Groups nested deeper than this are rejected, rather than risking
+/// the stack on inputs like `((((...))))`.
+const MAX_DEPTH: usize = 32;
+
+/// Molar mass of the electron in kg/mol, matching the electron mass of
+/// 5.48579909070e-4 u in `definitions.units`. Ions are this much
+/// lighter or heavier per unit of charge.
+fn electron_molar_mass() -> Numeric {
+    Numeric::Rational(BigRat::ratio(
+        &BigInt::from(548_579_909_070u64),
+        &BigInt::from(1_000_000_000_000_000_000u64),
+    ))
+}
+
+/// A parsed chemical formula.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Formula {
+    /// Atom counts keyed by element symbol, or by mass number and
+    /// symbol like `13C` for isotopes.
+    pub atoms: BTreeMap<String, u64>,
+    /// Net charge in units of the elementary charge.
+    pub charge: i64,
 }
 
-#[derive(Clone)]
-struct TokenIterator<'a>(Peekable<Chars<'a>>);
+fn superscript_digit(c: char) -> Option<u32> {
+    match c {
+        '⁰' => Some(0),
+        '¹' => Some(1),
+        '²' => Some(2),
+        '³' => Some(3),
+        '⁴'..='⁹' => Some(c as u32 - '⁴' as u32 + 4),
+        _ => None,
+    }
+}
+
+fn subscript_digit(c: char) -> Option<u32> {
+    match c {
+        '₀'..='₉' => Some(c as u32 - '₀' as u32),
+        _ => None,
+    }
+}
+
+fn is_hydrate_dot(c: char) -> bool {
+    matches!(c, '·' | '•' | '∙' | '.' | '*')
+}
 
-impl<'a> TokenIterator<'a> {
-    pub fn new(input: &'a str) -> TokenIterator<'a> {
-        TokenIterator(input.chars().peekable())
+fn charge_sign(c: char) -> Option<i64> {
+    match c {
+        '+' | '⁺' => Some(1),
+        '-' | '⁻' | '−' => Some(-1),
+        _ => None,
     }
 }
 
-impl<'a> Iterator for TokenIterator<'a> {
-    type Item = Token;
-
-    fn next(&mut self) -> Option<Token> {
-        self.0.peek()?;
-        let res = match self.0.next().unwrap() {
-            letter @ 'A'..='Z' => {
-                let mut symbol = String::new();
-                symbol.push(letter);
-                if let Some('a'..='z') = self.0.peek().cloned() {
-                    symbol.push(self.0.next().unwrap())
+fn add_atoms(
+    into: &mut BTreeMap<String, u64>,
+    from: BTreeMap<String, u64>,
+    times: u64,
+) -> Result<(), String> {
+    let overflow = || "Atom count is too large".to_owned();
+    for (symbol, count) in from {
+        let count = count.checked_mul(times).ok_or_else(overflow)?;
+        let total = into.entry(symbol).or_insert(0);
+        *total = total.checked_add(count).ok_or_else(overflow)?;
+    }
+    Ok(())
+}
+
+struct Parser<'a> {
+    iter: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    /// Reads a run of digits, using `digit` to recognize them.
+    fn number(&mut self, digit: fn(char) -> Option<u32>) -> Result<Option<u64>, String> {
+        let mut value: Option<u64> = None;
+        while let Some(d) = self.iter.peek().cloned().and_then(digit) {
+            self.iter.next();
+            value = value
+                .unwrap_or(0)
+                .checked_mul(10)
+                .and_then(|v| v.checked_add(u64::from(d)))
+                .map(Some)
+                .ok_or_else(|| "Number is too large".to_owned())?;
+        }
+        Ok(value)
+    }
+
+    /// Whether the input from here on is only a charge, so that the 3
+    /// in `[Fe(CN)6]3-` is read as part of the charge, not a count.
+    fn at_charge(&self) -> bool {
+        let mut copy = self.iter.clone();
+        if copy.peek() == Some(&'^') {
+            return true;
+        }
+        while copy
+            .peek()
+            .map(|&c| c.is_ascii_digit() || superscript_digit(c).is_some())
+            .unwrap_or(false)
+        {
+            copy.next();
+        }
+        match copy.next() {
+            Some(c) if charge_sign(c).is_some() => copy.all(|c| charge_sign(c).is_some()),
+            _ => false,
+        }
+    }
+
+    fn count(&mut self) -> Result<u64, String> {
+        if self.at_charge() {
+            return Ok(1);
+        }
+        let count = match self.number(|c| c.to_digit(10))? {
+            Some(count) => Some(count),
+            None => self.number(subscript_digit)?,
+        };
+        match count {
+            Some(0) => Err("Count must not be zero".to_owned()),
+            Some(count) => Ok(count),
+            None => Ok(1),
+        }
+    }
+
+    /// A sequence of elements and groups, up to a closing bracket, a
+    /// hydrate dot, a charge or the end of input.
+    fn sequence(&mut self, depth: usize) -> Result<BTreeMap<String, u64>, String> {
+        if depth > MAX_DEPTH {
+            return Err("Groups are nested too deeply".to_owned());
+        }
+        let mut atoms = BTreeMap::new();
+        let mut first = true;
+        loop {
+            let mass = match self.iter.peek().cloned() {
+                Some(c) if first && c.is_ascii_digit() => self.number(|c| c.to_digit(10))?,
+                Some(c) if superscript_digit(c).is_some() && !self.at_charge() => {
+                    self.number(superscript_digit)?
                 }
-                Token::Symbol(symbol)
-            }
-            digit @ '0'..='9' => {
-                let mut integer = String::new();
-                integer.push(digit);
-                while let Some('0'..='9') = self.0.peek().cloned() {
-                    integer.push(self.0.next().unwrap())
+                _ => None,
+            };
+            first = false;
+            let (part, close) = match self.iter.peek().cloned() {
+                Some(letter @ 'A'..='Z') => {
+                    self.iter.next();
+                    let mut symbol = String::new();
+                    if let Some(mass) = mass {
+                        symbol.push_str(&mass.to_string());
+                    }
+                    symbol.push(letter);
+                    while let Some('a'..='z') = self.iter.peek().cloned() {
+                        symbol.push(self.iter.next().unwrap());
+                    }
+                    // Heavy hydrogen has its own symbols.
+                    match &*symbol {
+                        "2H" => symbol = "D".to_owned(),
+                        "3H" => symbol = "T".to_owned(),
+                        _ => (),
+                    }
+                    let mut part = BTreeMap::new();
+                    part.insert(symbol, 1);
+                    (part, None)
+                }
+                Some(open @ '(') | Some(open @ '[') if mass.is_none() => {
+                    self.iter.next();
+                    let part = self.sequence(depth + 1)?;
+                    (part, Some(if open == '(' { ')' } else { ']' }))
+                }
+                _ if mass.is_some() => {
+                    return Err("Expected an element after a mass number".to_owned())
+                }
+                _ if atoms.is_empty() => return Err("Expected an element or group".to_owned()),
+                _ => return Ok(atoms),
+            };
+            if let Some(close) = close {
+                match self.iter.next() {
+                    Some(c) if c == close => (),
+                    _ => return Err(format!("Expected {}", close)),
                 }
-                Token::Count(u32::from_str(&integer).unwrap())
             }
-            _ => Token::Error,
+            let count = self.count()?;
+            add_atoms(&mut atoms, part, count)?;
+        }
+    }
+
+    fn charge(&mut self) -> Result<i64, String> {
+        if self.iter.peek() == Some(&'^') {
+            self.iter.next();
+        }
+        let magnitude = match self.number(|c| c.to_digit(10))? {
+            Some(n) => Some(n),
+            None => self.number(superscript_digit)?,
         };
-        Some(res)
+        let mut signs = vec![];
+        while let Some(sign) = self.iter.peek().cloned().and_then(charge_sign) {
+            self.iter.next();
+            signs.push(sign);
+        }
+        match (magnitude, &signs[..]) {
+            (None, []) => Ok(0),
+            (Some(_), []) => Err("Expected + or - after the charge".to_owned()),
+            (Some(n), &[sign]) => {
+                let n = i64::try_from(n).map_err(|_| "Charge is too large".to_owned())?;
+                Ok(sign * n)
+            }
+            (Some(_), _) => Err("Expected a single sign after the charge".to_owned()),
+            (None, signs) if signs.iter().all(|&s| s == signs[0]) => {
+                Ok(signs[0] * signs.len() as i64)
+            }
+            (None, _) => Err("Mixed signs in charge".to_owned()),
+        }
+    }
+
+    fn formula(&mut self) -> Result<Formula, String> {
+        let mut atoms = self.sequence(0)?;
+        while self
+            .iter
+            .peek()
+            .cloned()
+            .map(is_hydrate_dot)
+            .unwrap_or(false)
+        {
+            self.iter.next();
+            let times = self.number(|c| c.to_digit(10))?.unwrap_or(1);
+            if times == 0 {
+                return Err("Count must not be zero".to_owned());
+            }
+            let part = self.sequence(0)?;
+            add_atoms(&mut atoms, part, times)?;
+        }
+        let charge = self.charge()?;
+        match self.iter.next() {
+            None => Ok(Formula { atoms, charge }),
+            Some(c) => Err(format!("Unexpected {}", c)),
+        }
+    }
+}
+
+/// Parses a chemical formula, see the module docs for the syntax.
+pub fn parse_formula(input: &str) -> Result<Formula, String> {
+    Parser {
+        iter: input.chars().peekable(),
     }
+    .formula()
+}
+
+/// Splits a formula like `5H2O`, whose leading mass number makes an
+/// isotope that isn't known, into the number and the rest. Used to
+/// suggest `5 H2O` when a count was meant.
+pub fn misread_count<'a>(
+    formula: &'a str,
+    symbols: &BTreeMap<String, String>,
+) -> Option<(&'a str, &'a str)> {
+    let split = formula.find(|c: char| !c.is_ascii_digit())?;
+    let (digits, rest) = formula.split_at(split);
+    if digits.is_empty() {
+        return None;
+    }
+    let parsed = parse_formula(formula).ok()?;
+    let isotope = parsed
+        .atoms
+        .keys()
+        .find(|symbol| symbol.starts_with(digits))?;
+    if symbols.contains_key(isotope) {
+        return None;
+    }
+    let known = parse_formula(rest)
+        .ok()?
+        .atoms
+        .keys()
+        .all(|symbol| symbols.contains_key(symbol));
+    if known {
+        Some((digits, rest))
+    } else {
+        None
+    }
+}
+
+/// One element's share of a formula.
+#[derive(Debug, Clone)]
+pub struct Component {
+    /// The symbol as written in the formula, like `C` or `13C`.
+    pub symbol: String,
+    /// The substance the symbol stands for, like `carbon`.
+    pub name: String,
+    pub count: u64,
+    /// The molar mass of one atom of the element.
+    pub molar_mass: Number,
+}
+
+/// Looks up each element of a formula, or returns None if one of
+/// them isn't a known substance with a molar mass.
+pub fn components(
+    formula: &Formula,
+    symbols: &BTreeMap<String, String>,
+    substances: &BTreeMap<String, Substance>,
+) -> Option<Vec<Component>> {
+    formula
+        .atoms
+        .iter()
+        .map(|(sym, &count)| {
+            let name = symbols.get(sym)?;
+            let molar_mass = substances.get(name)?.get("molar_mass").ok()?;
+            Some(Component {
+                symbol: sym.clone(),
+                name: name.clone(),
+                count,
+                molar_mass,
+            })
+        })
+        .collect()
 }
 
 /**
@@ -61,46 +347,40 @@ pub fn substance_from_formula(
     symbols: &BTreeMap<String, String>,
     substances: &BTreeMap<String, Substance>,
 ) -> Option<Substance> {
+    let parsed = parse_formula(formula).ok()?;
+
     let mut molar_mass_unit = BTreeMap::new();
     molar_mass_unit.insert(Dimension::new("kg"), 1);
     molar_mass_unit.insert(Dimension::new("mol"), -1);
     let mut total_molar_mass = Number {
         value: Numeric::from(0),
-        unit: molar_mass_unit,
+        unit: molar_mass_unit.clone(),
     };
 
-    let mut iter = TokenIterator::new(formula).peekable();
-    while let Some(token) = iter.next() {
-        match token {
-            Token::Symbol(ref sym) if symbols.contains_key(sym) => {
-                let count = match iter.peek() {
-                    Some(&Token::Count(n)) => {
-                        iter.next().unwrap();
-                        Number::new(Numeric::from(n as i64))
-                    }
-                    _ => Number::one(),
-                };
-
-                let subst = substances.get(symbols.get(sym).unwrap()).unwrap();
-                match subst.get("molar_mass") {
-                    Ok(subst_molar_mass) => {
-                        let subst_molar_mass = (&subst_molar_mass * &count).unwrap();
-                        total_molar_mass = (&total_molar_mass + &subst_molar_mass).unwrap();
-                    }
-                    Err(_) => return None,
-                }
-            }
-            _ => return None,
-        }
+    for component in components(&parsed, symbols, substances)? {
+        let count = Number::new(Numeric::from(i64::try_from(component.count).ok()?));
+        let molar_mass = (&component.molar_mass * &count)?;
+        total_molar_mass = (&total_molar_mass + &molar_mass)?;
+    }
+
+    if parsed.charge != 0 {
+        let electrons = Number {
+            value: &electron_molar_mass() * &Numeric::from(parsed.charge),
+            unit: molar_mass_unit,
+        };
+        total_molar_mass = (&total_molar_mass - &electrons)?;
     }
 
+    // Stored per mole like the `molar_mass` of the elements, so that
+    // amounts of the formula convert between mass and moles.
+    let mole = Number::one_unit(Dimension::new("mol"));
     let mut props = BTreeMap::new();
     props.insert(
         "molar_mass".to_owned(),
         Property {
-            output: total_molar_mass,
+            output: (&total_molar_mass * &mole)?,
             output_name: "mass".to_owned(),
-            input: Number::one(),
+            input: mole,
             input_name: "amount".to_owned(),
             doc: None,
         },
@@ -110,6 +390,64 @@ pub fn substance_from_formula(
         properties: Arc::new(Properties {
             name: formula.to_owned(),
             properties: props,
+            tables: BTreeMap::new(),
+            left_out: BTreeMap::new(),
         }),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atoms(list: &[(&str, u64)]) -> BTreeMap<String, u64> {
+        list.iter()
+            .map(|&(sym, count)| (sym.to_owned(), count))
+            .collect()
+    }
+
+    fn check(input: &str, list: &[(&str, u64)], charge: i64) {
+        assert_eq!(
+            parse_formula(input),
+            Ok(Formula {
+                atoms: atoms(list),
+                charge
+            }),
+            "{}",
+            input
+        );
+    }
+
+    #[test]
+    fn test_parse_formula() {
+        check("H2O", &[("H", 2), ("O", 1)], 0);
+        check("Ca(OH)2", &[("Ca", 1), ("H", 2), ("O", 2)], 0);
+        check("CuSO4·5H2O", &[("Cu", 1), ("H", 10), ("O", 9), ("S", 1)], 0);
+        check("[Fe(CN)6]3-", &[("C", 6), ("Fe", 1), ("N", 6)], -3);
+        check("SO4^2-", &[("O", 4), ("S", 1)], -2);
+        check("SO₄²⁻", &[("O", 4), ("S", 1)], -2);
+        check("Ca2+", &[("Ca", 1)], 2);
+        check("Na+", &[("Na", 1)], 1);
+        check("Fe++", &[("Fe", 1)], 2);
+        check("D2O", &[("D", 2), ("O", 1)], 0);
+        check("13C", &[("13C", 1)], 0);
+        check("13CO2", &[("13C", 1), ("O", 2)], 0);
+        check("CH3[13C]H3", &[("13C", 1), ("C", 1), ("H", 6)], 0);
+        check("¹³CH4", &[("13C", 1), ("H", 4)], 0);
+        check("[2H]2O", &[("D", 2), ("O", 1)], 0);
+    }
+
+    #[test]
+    fn test_parse_formula_errors() {
+        assert!(parse_formula("").is_err());
+        assert!(parse_formula("h2o").is_err());
+        assert!(parse_formula("Ca(OH2").is_err());
+        assert!(parse_formula("Ca(OH]2").is_err());
+        assert!(parse_formula("H0").is_err());
+        assert!(parse_formula("13").is_err());
+        assert!(parse_formula("Fe+-").is_err());
+        assert!(parse_formula("Fe2+2").is_err());
+        assert!(parse_formula("H99999999999999999999").is_err());
+        assert!(parse_formula(&format!("{}H{}", "(".repeat(100), ")".repeat(100))).is_err());
+    }
+}
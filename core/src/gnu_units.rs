@@ -273,6 +273,7 @@ fn parse_mul(iter: &mut Iter<'_>) -> Expr {
             | Token::Plus
             | Token::Dash
             | Token::RPar
+            | Token::LeftBrace
             | Token::Newline
             | Token::Eof => break,
             Token::Asterisk => {
@@ -319,6 +320,68 @@ pub fn parse_expr(iter: &mut Iter<'_>) -> Expr {
     parse_add(iter)
 }
 
+/// Parses the rest of a property table, after `<property> at`.
+fn parse_table(
+    iter: &mut Iter<'_>,
+    property: String,
+    line: &mut usize,
+) -> Result<PropertyTable, String> {
+    let variable = match iter.next().unwrap() {
+        Token::Ident(name) => name,
+        x => return Err(format!("Expected table quantity, got {:?}", x)),
+    };
+    let unit = match iter.next().unwrap() {
+        Token::Ident(name) => name,
+        x => return Err(format!("Expected table unit, got {:?}", x)),
+    };
+    match iter.next().unwrap() {
+        Token::Ident(ref s) if s == "in" => (),
+        x => return Err(format!("Expected in, got {:?}", x)),
+    }
+    let value_unit = parse_div(iter);
+    match iter.next().unwrap() {
+        Token::LeftBrace => (),
+        x => return Err(format!("Expected {{, got {:?}", x)),
+    }
+    // Temperatures like `20 °C` are offsets from absolute zero.
+    let state = |value: Expr| match Degree::from_name(&unit) {
+        Some(degree) => Expr::new_suffix(degree, value),
+        None => Expr::new_mul(vec![value, Expr::new_unit(unit.clone())]),
+    };
+    let mut points = vec![];
+    loop {
+        match iter.peek().cloned().unwrap() {
+            Token::Newline => {
+                iter.next();
+                *line += 1;
+            }
+            Token::RightBrace => {
+                iter.next();
+                break;
+            }
+            Token::Eof => return Err("Expected }, got Eof".to_owned()),
+            _ => {
+                let at = parse_term(iter);
+                let value = parse_term(iter);
+                match iter.peek().unwrap() {
+                    Token::Newline | Token::RightBrace => (),
+                    x => return Err(format!("Expected end of table row, got {:?}", x)),
+                }
+                points.push(TablePoint {
+                    state: ExprString(state(at)),
+                    value: ExprString(Expr::new_mul(vec![value, value_unit.clone()])),
+                });
+            }
+        }
+    }
+    Ok(PropertyTable {
+        property,
+        variable,
+        points,
+        doc: None,
+    })
+}
+
 pub fn parse(iter: &mut Iter<'_>) -> Defs {
     let mut map = vec![];
     let mut line = 1;
@@ -358,6 +421,23 @@ pub fn parse(iter: &mut Iter<'_>) -> Defs {
                         _ => println!("Malformed symbol directive"),
                     }
                 }
+                Token::Ident(ref s) if s == "datepattern" => {
+                    // A missing pattern is left for `Context::load` to
+                    // report as empty.
+                    let pattern = match iter.peek().cloned().unwrap() {
+                        Token::Ident(pattern) => {
+                            iter.next();
+                            pattern
+                        }
+                        _ => String::new(),
+                    };
+                    map.push(DefEntry {
+                        name: pattern.clone(),
+                        def: Rc::new(Def::DatePattern { pattern }),
+                        doc: doc.take(),
+                        category: None,
+                    })
+                }
                 _ => loop {
                     match iter.peek().cloned().unwrap() {
                         Token::Newline | Token::Eof => break,
@@ -442,6 +522,7 @@ pub fn parse(iter: &mut Iter<'_>) -> Defs {
                         // substance
                         iter.next();
                         let mut props = vec![];
+                        let mut tables = vec![];
                         let mut prop_doc = None;
                         loop {
                             let name = match iter.next().unwrap() {
@@ -467,6 +548,19 @@ pub fn parse(iter: &mut Iter<'_>) -> Defs {
                                 }
                             };
                             let output_name = match iter.next().unwrap() {
+                                Token::Ident(ref s) if s == "at" => {
+                                    match parse_table(iter, name, &mut line) {
+                                        Ok(table) => tables.push(PropertyTable {
+                                            doc: prop_doc.take(),
+                                            ..table
+                                        }),
+                                        Err(e) => {
+                                            println!("Line {}: {}", line, e);
+                                            break;
+                                        }
+                                    }
+                                    continue;
+                                }
                                 Token::Ident(ref s) if s == "const" => {
                                     let input_name = match iter.next().unwrap() {
                                         Token::Ident(name) => name,
@@ -526,6 +620,7 @@ pub fn parse(iter: &mut Iter<'_>) -> Defs {
                             def: Rc::new(Def::Substance {
                                 symbol: None,
                                 properties: props,
+                                tables,
                             }),
                             doc: doc.take(),
                             category: category.clone(),
@@ -652,4 +747,26 @@ mod tests {
     fn test_escaped_quotes() {
         expect!("\"ab\\\"\"", Expr::Unit { ref name }, name, "ab\"")
     }
+
+    #[test]
+    fn test_parse_table() {
+        let parse = |s: &str| {
+            let mut iter = TokenIterator::new(s).peekable();
+            parse_table(&mut iter, "density".to_owned(), &mut 0)
+        };
+        let table = parse("temperature °C in kg/m^3 {\n  0 999.84\n  -10 998.12\n}").unwrap();
+        assert_eq!(table.variable, "temperature");
+        assert_eq!(table.points.len(), 2);
+        assert_eq!(table.points[1].state.to_string(), "-10 °C");
+        assert_eq!(table.points[1].value.to_string(), "998.12 (kg / m^3)");
+
+        assert_eq!(
+            parse("temperature K kg/m^3 {}").map(|_| ()),
+            Err("Expected in, got Ident(\"kg\")".to_owned())
+        );
+        assert_eq!(
+            parse("temperature K in kg/m^3 {\n 300 1 2\n}").map(|_| ()),
+            Err("Expected end of table row, got Number(\"2\", None, None)".to_owned())
+        );
+    }
 }
@@ -0,0 +1,100 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Relative descriptions of dates like `in 3 days` or
+//! `2 years, 4 months ago`, shown next to dates in replies. These are
+//! measured from `Context::now` rather than the system clock, so they
+//! come out the same everywhere, including in WASM.
+
+use crate::date::{self, GenericDateTime};
+use chrono::Duration;
+
+/// Units below a month, which all have a fixed length in seconds.
+const UNITS: &[(&str, i64)] = &[
+    ("week", 7 * 86_400),
+    ("day", 86_400),
+    ("hour", 3_600),
+    ("minute", 60),
+    ("second", 1),
+];
+
+/// Describes `date` relative to `now`. Years and months are counted on
+/// the calendar of `date`'s timezone. At most `precision` units are
+/// used, starting from the largest one that isn't zero, and anything
+/// smaller is truncated.
+pub fn humanize(date: &GenericDateTime, now: &GenericDateTime, precision: usize) -> String {
+    let (months, rest) = date::calendar_difference(date, now);
+    let future = months > 0 || rest > Duration::zero();
+    let months = months.abs();
+    let mut seconds = rest.num_seconds().abs();
+
+    let mut amounts = vec![("year", months / 12), ("month", months % 12)];
+    for &(name, size) in UNITS {
+        amounts.push((name, seconds / size));
+        seconds %= size;
+    }
+    let parts = amounts
+        .into_iter()
+        .skip_while(|&(_, amount)| amount == 0)
+        .take(precision.max(1))
+        .filter(|&(_, amount)| amount != 0)
+        .map(|(name, amount)| {
+            if amount == 1 {
+                format!("1 {}", name)
+            } else {
+                format!("{} {}s", amount, name)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if parts.is_empty() {
+        "now".to_owned()
+    } else if future {
+        format!("in {}", parts.join(", "))
+    } else {
+        format!("{} ago", parts.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, TimeZone};
+
+    fn date(y: i32, m: u32, d: u32, h: u32, min: u32, s: u32) -> GenericDateTime {
+        GenericDateTime::Fixed(FixedOffset::east(0).ymd(y, m, d).and_hms(h, min, s))
+    }
+
+    #[test]
+    fn test_humanize() {
+        let now = date(2024, 3, 1, 12, 0, 0);
+        assert_eq!(humanize(&now, &now, 1), "now");
+        assert_eq!(humanize(&date(2024, 3, 4, 12, 0, 0), &now, 1), "in 3 days");
+        assert_eq!(humanize(&date(2024, 3, 4, 18, 0, 0), &now, 1), "in 3 days");
+        assert_eq!(
+            humanize(&date(2024, 3, 4, 18, 0, 0), &now, 2),
+            "in 3 days, 6 hours"
+        );
+        assert_eq!(
+            humanize(&date(2021, 10, 25, 12, 0, 0), &now, 2),
+            "2 years, 4 months ago"
+        );
+        assert_eq!(
+            humanize(&date(2021, 10, 25, 12, 0, 0), &now, 4),
+            "2 years, 4 months, 5 days ago"
+        );
+        assert_eq!(
+            humanize(&date(2024, 3, 15, 12, 0, 0), &now, 1),
+            "in 2 weeks"
+        );
+        assert_eq!(
+            humanize(&date(2024, 3, 1, 11, 59, 59), &now, 1),
+            "1 second ago"
+        );
+        // Zero units in the middle still count towards the precision.
+        assert_eq!(humanize(&date(2025, 3, 1, 12, 0, 5), &now, 2), "in 1 year");
+        // Precision 0 is treated as 1.
+        assert_eq!(humanize(&date(2025, 3, 1, 12, 0, 5), &now, 0), "in 1 year");
+    }
+}
@@ -11,9 +11,12 @@ Using Rink as a library for uses other than simple unit conversion
 tools is not currently well supported, and if you wish to do so,
 please make issues for any problems you have.
 
-There are currently a number of hardcoded `println!`s and `unwrap()`s
-because most of this code was written in a day without much thought
-towards making it into a library.
+Evaluating a query never panics; all failures are reported through
+`QueryError`, which implements `std::error::Error`. `eval_line`
+returns it directly, while `one_line` renders it as text. When
+evaluating untrusted input, set `Context::limits` to bound the time
+and memory a single query can use. There is a fuzz harness for this in
+`core/fuzz`.
 
 To use the library, check how the CLI tool does it. To get additional
 features like currency and BTC you'll need to fetch those files
@@ -38,23 +41,32 @@ extern crate serde_derive;
 pub mod ast;
 pub mod bigint;
 pub mod bigrat;
+pub mod business;
+pub mod calendar;
 pub mod context;
 pub mod date;
 pub mod eval;
 pub mod factorize;
 pub mod formula;
 pub mod gnu_units;
+pub mod humanize;
+pub mod limits;
 pub mod load;
+pub mod materials;
 pub mod number;
 pub mod numeric;
+pub mod reaction;
 pub mod reply;
+pub mod schema;
 pub mod search;
 pub mod substance;
 pub mod text_query;
+pub mod timezone;
 pub mod value;
 
 pub use crate::context::Context;
 pub use crate::number::Number;
+pub use crate::reply::{QueryError, QueryReply};
 pub use crate::value::Value;
 
 use std::collections::BTreeMap;
@@ -65,36 +77,79 @@ pub static DEFAULT_FILE: Option<&'static str> = Some(include_str!("../definition
 pub static DEFAULT_FILE: Option<&'static str> = None;
 
 pub static DATES_FILE: &str = include_str!("../datepatterns.txt");
+pub static LEAP_SECONDS_FILE: &str = include_str!("../leapseconds.txt");
+pub static HOLIDAYS_FILE: &str = include_str!("../holidays.txt");
+pub static TIMEZONES_FILE: &str = include_str!("../timezones.txt");
 pub static CURRENCY_FILE: &str = include_str!("../currency.units");
-
-/// Evaluates a single line within a context.
-pub fn one_line(ctx: &mut Context, line: &str) -> Result<String, String> {
+/// Energy, macronutrients and density of common foods, per 100 g, from
+/// the USDA's FoodData Central. It is a material table, see the
+/// `materials` module. Amounts are given by mass or volume, like
+/// `energy of 150 g rice`; there are no serving sizes.
+pub static FOODS_FILE: &str = include_str!("../foods.csv");
+
+/// Evaluates a single line within a context. The result is subject to
+/// `ctx.limits`, see the `limits` module.
+///
+/// Errors are returned as a structured `QueryError`, which can be
+/// matched on to tell parse errors apart from unknown units and so on.
+/// Use `one_line` if only the rendered text is needed.
+pub fn eval_line(ctx: &mut Context, line: &str) -> Result<QueryReply, QueryError> {
     ctx.update_time();
     let mut iter = text_query::TokenIterator::new(line.trim()).peekable();
     let expr = text_query::parse_query(&mut iter);
-    let res = ctx.eval_outer(&expr);
-    res.as_ref()
+    let reply = ctx.eval_outer(&expr)?;
+    if ctx.limits.max_output_len.is_some() {
+        ctx.limits
+            .check_output_len(reply.to_string().chars().count())?;
+    }
+    Ok(reply)
+}
+
+/// Evaluates a single line within a context and renders the reply or
+/// error as text. The result is subject to `ctx.limits`, see the
+/// `limits` module.
+pub fn one_line(ctx: &mut Context, line: &str) -> Result<String, String> {
+    eval_line(ctx, line)
+        .as_ref()
         .map(ToString::to_string)
         .map_err(ToString::to_string)
 }
 
 /// Tries to create a context that has core definitions only (contents
-/// of definitions.units), will fail if the GPL feature isn't enabled.
-/// Mainly intended for unit testing.
-pub fn simple_context() -> Result<Context, String> {
+/// of definitions.units and foods.csv), will fail if the GPL feature
+/// isn't enabled. Mainly intended for unit testing.
+pub fn simple_context() -> Result<Context, QueryError> {
     let units = match DEFAULT_FILE {
         Some(units) => units,
-        None => return Err("GPL feature not enabled, cannot create simple context.".to_owned()),
+        None => {
+            return Err(QueryError::load(
+                "GPL feature not enabled, cannot create simple context.".to_owned(),
+            ))
+        }
     };
 
     let mut iter = gnu_units::TokenIterator::new(&*units).peekable();
     let units = gnu_units::parse(&mut iter);
 
-    let dates = date::parse_datefile(DATES_FILE);
+    let (dates, date_errors) = date::parse_datefile(DATES_FILE);
+    let leaps = date::parse_leap_seconds(LEAP_SECONDS_FILE);
+    let (holidays, holiday_errors) = business::parse_holidays(HOLIDAYS_FILE);
+    let (timezones, timezone_errors) = timezone::parse_timezones(TIMEZONES_FILE);
+
+    let foods = materials::parse_csv(FOODS_FILE).map_err(QueryError::load)?;
 
     let mut ctx = Context::new();
-    ctx.load(units);
+    // Before the units, so `!datepattern` directives come after the
+    // shipped patterns.
     ctx.load_dates(dates);
+    ctx.datepattern_errors = date_errors;
+    ctx.load(units);
+    ctx.load_foods(materials::material_defs(foods));
+    ctx.load_leap_seconds(leaps);
+    ctx.load_business_calendars(holidays);
+    ctx.holiday_errors = holiday_errors;
+    ctx.load_timezones(timezones);
+    ctx.timezone_errors = timezone_errors;
     Ok(ctx)
 }
 
@@ -0,0 +1,244 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Resource limits for evaluating untrusted queries.
+//!
+//! Rink's arithmetic is arbitrary precision, so a short query like
+//! `2^2^2^2^2^2` or `pi -> digits 100000000` can take practically
+//! forever or run the process out of memory. Frontends that accept
+//! input from the public should set [`Context::limits`] so that such
+//! queries fail with [`QueryError::LimitExceeded`] instead.
+//!
+//! [`Context::limits`]: crate::Context::limits
+//! [`QueryError::LimitExceeded`]: crate::reply::QueryError::LimitExceeded
+
+use crate::bigint::BigInt;
+use crate::context::Context;
+use crate::numeric::{Digits, Numeric};
+use crate::value::Value;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Bounds on the work a single query is allowed to do. `None` means
+/// unlimited, which is the default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Limits {
+    /// Maximum number of evaluation steps (roughly one per AST node
+    /// visited, plus one per candidate tried by `factorize` and per day
+    /// walked for business days).
+    pub max_steps: Option<u64>,
+    /// Maximum wall-clock time for a query. Not available on
+    /// `wasm32-unknown-unknown`, where there is no clock to read.
+    pub timeout: Option<Duration>,
+    /// Maximum size of the numerator plus denominator of any
+    /// intermediate rational number, in bits.
+    pub max_bigint_bits: Option<u64>,
+    /// Maximum length of the formatted result, in characters.
+    pub max_output_len: Option<usize>,
+}
+
+impl Limits {
+    /// Limits that are suitable for evaluating queries from anyone on
+    /// the internet, like the IRC bot does.
+    pub fn sandbox() -> Limits {
+        Limits {
+            max_steps: Some(100_000),
+            timeout: Some(Duration::from_secs(1)),
+            max_bigint_bits: Some(1 << 17),
+            max_output_len: Some(10_000),
+        }
+    }
+
+    pub(crate) fn check_bits(&self, bits: u64) -> Result<(), LimitExceeded> {
+        match self.max_bigint_bits {
+            Some(max_bits) if bits > max_bits => Err(LimitExceeded::BigIntSize { max_bits, bits }),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_output_len(&self, len: usize) -> Result<(), LimitExceeded> {
+        match self.max_output_len {
+            Some(max) if len > max => Err(LimitExceeded::OutputLength { max, len }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Which limit was exceeded during evaluation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "limit")]
+#[serde(rename_all = "camelCase")]
+pub enum LimitExceeded {
+    Steps { max: u64 },
+    Timeout { millis: u64 },
+    BigIntSize { max_bits: u64, bits: u64 },
+    OutputLength { max: usize, len: usize },
+    Cancelled,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LimitExceeded::Steps { max } => {
+                write!(fmt, "Evaluation exceeded the limit of {} steps", max)
+            }
+            LimitExceeded::Timeout { millis } => {
+                write!(fmt, "Evaluation exceeded the time limit of {}ms", millis)
+            }
+            LimitExceeded::BigIntSize { max_bits, bits } => write!(
+                fmt,
+                "Number is too large: needs {} bits, the limit is {}",
+                bits, max_bits
+            ),
+            LimitExceeded::OutputLength { max, len } => write!(
+                fmt,
+                "Output is too long: {} characters, the limit is {}",
+                len, max
+            ),
+            LimitExceeded::Cancelled => write!(fmt, "Evaluation was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// A handle which can be used to abort a running evaluation from
+/// another thread. Obtained from `Context::cancel_token`.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Requests that the query currently being evaluated stops at the
+    /// next opportunity. Has no effect if no query is running.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Clears the request, returning whether there was one.
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::SeqCst)
+    }
+}
+
+/// Per-query accounting of how much of the `Limits` has been used up.
+#[derive(Debug, Default)]
+pub(crate) struct Budget {
+    steps: AtomicU64,
+    deadline: Mutex<Option<Instant>>,
+    cancel: CancelToken,
+    /// Whether the current query has taken a cancel request.
+    cancelled: AtomicBool,
+}
+
+impl Budget {
+    pub(crate) fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Starts accounting for a new query. A cancel requested before
+    /// the query starts was meant for an earlier one, and is dropped.
+    pub(crate) fn reset(&self, limits: &Limits) {
+        self.cancel.take();
+        self.steps.store(0, Ordering::SeqCst);
+        self.cancelled.store(false, Ordering::SeqCst);
+        let deadline = limits.timeout.map(|timeout| Instant::now() + timeout);
+        *self.deadline.lock().unwrap_or_else(|e| e.into_inner()) = deadline;
+    }
+
+    /// Records one unit of work, failing if any limit has been hit.
+    pub(crate) fn step(&self, limits: &Limits) -> Result<(), LimitExceeded> {
+        if self.cancel.take() {
+            self.cancelled.store(true, Ordering::SeqCst);
+        }
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(LimitExceeded::Cancelled);
+        }
+        let steps = self.steps.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(max) = limits.max_steps {
+            if steps > max {
+                return Err(LimitExceeded::Steps { max });
+            }
+        }
+        if let Some(timeout) = limits.timeout {
+            let deadline = *self.deadline.lock().unwrap_or_else(|e| e.into_inner());
+            if deadline.map(|d| Instant::now() > d).unwrap_or(false) {
+                return Err(LimitExceeded::Timeout {
+                    millis: timeout.as_millis() as u64,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Context {
+    /// Returns a handle that can abort the query currently being
+    /// evaluated in this context, e.g. from a watchdog thread.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.budget.cancel_token()
+    }
+
+    /// Called at the start of every query to reset the step counter
+    /// and start the clock.
+    pub(crate) fn begin_query(&self) {
+        self.budget.reset(&self.limits);
+    }
+
+    pub(crate) fn step(&self) -> Result<(), LimitExceeded> {
+        self.budget.step(&self.limits)
+    }
+
+    pub(crate) fn check_value(&self, value: &Value) -> Result<(), LimitExceeded> {
+        match *value {
+            Value::Number(ref num) => self.limits.check_bits(num.value.size_in_bits()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks `base^exp` before computing it, using a lower bound on
+    /// the size of the result. The exact size is checked afterwards by
+    /// `check_value`.
+    pub(crate) fn check_pow(&self, base: &Numeric, exp: &Numeric) -> Result<(), LimitExceeded> {
+        if self.limits.max_bigint_bits.is_none() {
+            return Ok(());
+        }
+        if let Numeric::Float(_) = *base {
+            return Ok(());
+        }
+        let (num, den) = match exp.to_rational() {
+            Some(rational) => rational,
+            None => return Ok(()),
+        };
+        if den != BigInt::one() {
+            // Roots and fractional powers are computed as floats.
+            return Ok(());
+        }
+        let exp = num.as_int().map(i64::unsigned_abs).unwrap_or(u64::MAX);
+        let (num, den) = match base.to_rational() {
+            Some(rational) => rational,
+            None => return Ok(()),
+        };
+        let bits = num.bits().saturating_sub(1) + den.bits().saturating_sub(1);
+        self.limits.check_bits(bits.saturating_mul(exp))
+    }
+
+    pub(crate) fn check_digits(
+        &self,
+        value: &Numeric,
+        base: u8,
+        digits: Digits,
+    ) -> Result<(), LimitExceeded> {
+        if self.limits.max_output_len.is_none() {
+            return Ok(());
+        }
+        self.limits
+            .check_output_len(value.string_len_estimate(base, digits))
+    }
+}
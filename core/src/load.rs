@@ -2,11 +2,11 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::ast::{BinOpExpr, Def, DefEntry, Defs, Expr};
+use crate::ast::{BinOpExpr, Def, DefEntry, Defs, Expr, PropertyTable, UnaryOpType};
 use crate::number::{Dimension, Number};
 use crate::numeric::Numeric;
-use crate::substance::{Properties, Property, Substance};
-use crate::value::Value;
+use crate::substance::{Properties, Property, StateTable, Substance};
+use crate::value::{Show, Value};
 use crate::Context;
 use std::collections::{BTreeMap, BTreeSet};
 use std::rc::Rc;
@@ -107,8 +107,34 @@ impl Resolver {
                 self.eval(left);
                 self.eval(right);
             }
-            Expr::UnaryOp(ref unaryop) => self.eval(&unaryop.expr),
-            Expr::Of { ref expr, .. } => self.eval(expr),
+            Expr::UnaryOp(ref unaryop) => {
+                // Temperatures like `20 °C` depend on the units of
+                // their scale.
+                if let UnaryOpType::Degree(ref degree) = unaryop.op {
+                    let (_, base, scale) = degree.name_base_scale();
+                    for name in &[base, scale] {
+                        let name = self.intern(&(*name).to_owned());
+                        self.lookup(&name);
+                    }
+                }
+                self.eval(&unaryop.expr)
+            }
+            Expr::Of { ref expr, .. }
+            | Expr::Yields { ref expr, .. }
+            | Expr::Mixture { ref expr, .. } => self.eval(expr),
+            Expr::At {
+                ref expr,
+                ref states,
+            } => {
+                self.eval(expr);
+                for state in states {
+                    self.eval(state);
+                }
+            }
+            Expr::After { ref expr, ref time } => {
+                self.eval(expr);
+                self.eval(time);
+            }
 
             Expr::Mul { ref exprs }
             | Expr::Call {
@@ -138,11 +164,23 @@ impl Resolver {
                     Def::Canonicalization { ref of } => {
                         self.lookup(&Rc::new(of.clone()));
                     }
-                    Def::Substance { ref properties, .. } => {
+                    Def::Substance {
+                        ref properties,
+                        ref tables,
+                        ..
+                    } => {
                         for prop in properties {
                             self.eval(&prop.input);
                             self.eval(&prop.output);
                         }
+                        for table in tables {
+                            let variable = self.intern(&table.variable);
+                            self.lookup(&variable);
+                            for point in &table.points {
+                                self.eval(&point.state);
+                                self.eval(&point.value);
+                            }
+                        }
                     }
                     _ => (),
                 }
@@ -155,6 +193,133 @@ impl Resolver {
 }
 
 impl Context {
+    /// Evaluates the points of a property table, which have to be in
+    /// the same units as `property`.
+    fn eval_table(
+        &self,
+        table: &PropertyTable,
+        property: Option<&Property>,
+    ) -> Result<StateTable, String> {
+        let property = property
+            .ok_or_else(|| format!("Table for {} has no property to go with it", table.property))?;
+        let unit = (&property.output / &property.input)
+            .ok_or_else(|| format!("Property {} has no input", table.property))?
+            .unit;
+        let eval = |expr: &Expr| match self.eval(expr) {
+            Ok(Value::Number(v)) => Ok(v),
+            Ok(x) => Err(format!(
+                "Expected number in the {} table, got {}",
+                table.property,
+                x.show(self)
+            )),
+            Err(e) => Err(format!("Malformed {} table: {}", table.property, e)),
+        };
+        let mut points = table
+            .points
+            .iter()
+            .map(|point| {
+                let state = eval(&point.state)?;
+                let value = eval(&point.value)?;
+                if self.quantities.get(&state.unit) != Some(&table.variable) {
+                    return Err(format!(
+                        "Expected {} for the states of the {} table, got <{}>",
+                        table.variable,
+                        table.property,
+                        state.show(self)
+                    ));
+                }
+                if value.unit != unit {
+                    return Err(format!(
+                        "Expected values like <{}> in the {} table, got <{}>",
+                        Number {
+                            value: Numeric::one(),
+                            unit: unit.clone(),
+                        }
+                        .show(self),
+                        table.property,
+                        value.show(self)
+                    ));
+                }
+                Ok((state, value))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if points.len() < 2 {
+            return Err(format!(
+                "The {} table needs at least two rows",
+                table.property
+            ));
+        }
+        points.sort_by(|a, b| {
+            a.0.value
+                .partial_cmp(&b.0.value)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        if points.windows(2).any(|w| w[0].0.value == w[1].0.value) {
+            return Err(format!(
+                "The {} table has more than one row for the same {}",
+                table.property, table.variable
+            ));
+        }
+        Ok(StateTable {
+            variable: table.variable.clone(),
+            points,
+            doc: table.doc.clone(),
+        })
+    }
+
+    /// Gives each substance with a `half_life` its `decay_constant`
+    /// and, if it has a `molar_mass`, its `specific_activity`. This
+    /// runs after the whole file is loaded, once `avogadro` is.
+    fn add_decay_properties(&mut self) {
+        let avogadro = match self.lookup("avogadro") {
+            Some(avogadro) => avogadro,
+            None => return,
+        };
+        let ln2 = Number::new(Numeric::Float(std::f64::consts::LN_2));
+        for substance in self.substances.values_mut() {
+            let props = &substance.properties.properties;
+            if props.contains_key("decay_constant") {
+                continue;
+            }
+            let half_life = match props
+                .get("half_life")
+                .and_then(|prop| &prop.output / &prop.input)
+            {
+                Some(half_life) => half_life,
+                None => continue,
+            };
+            let decay_constant = match &ln2 / &half_life {
+                Some(decay_constant) => decay_constant,
+                None => continue,
+            };
+            let mut properties = (*substance.properties).clone();
+            if let Some(molar_mass) = props.get("molar_mass") {
+                let atoms = (&molar_mass.input * &avogadro).expect("Multiplying units");
+                properties.properties.insert(
+                    "specific_activity".to_owned(),
+                    Property {
+                        input: molar_mass.output.clone(),
+                        input_name: "mass".to_owned(),
+                        output: (&atoms * &decay_constant).expect("Multiplying units"),
+                        output_name: "activity".to_owned(),
+                        doc: None,
+                    },
+                );
+            }
+            properties.properties.insert(
+                "decay_constant".to_owned(),
+                Property {
+                    input: Number::one(),
+                    input_name: "atoms".to_owned(),
+                    output: decay_constant,
+                    output_name: "activity".to_owned(),
+                    doc: None,
+                },
+            );
+            substance.properties = Arc::new(properties);
+        }
+    }
+
     /// Takes a parsed definitions.units from
     /// `gnu_units::parse()`. Prints if there are errors in the file.
     pub fn load(&mut self, defs: Defs) {
@@ -174,6 +339,14 @@ impl Context {
             category,
         } in defs.defs.into_iter()
         {
+            // Date patterns aren't units, and their order matters, so
+            // they skip the resolver.
+            if let Def::DatePattern { ref pattern } = *def {
+                if let Err(e) = self.add_datepattern(pattern) {
+                    self.datepattern_errors.push(e);
+                }
+                continue;
+            }
             let name = resolver.intern(&name);
             let unit = match *def {
                 Def::Prefix { .. } | Def::SPrefix { .. } => Name::Prefix(name),
@@ -301,6 +474,7 @@ impl Context {
                 Def::Substance {
                     ref properties,
                     ref symbol,
+                    ref tables,
                 } => {
                     let mut prev = BTreeMap::new();
                     let res = properties
@@ -342,7 +516,13 @@ impl Context {
                             unique.insert(&*prop.name);
                             unique.insert(&*prop.input_name);
                             unique.insert(&*prop.output_name);
-                            let unit = (&input / &output).expect("Non-zero property").unit;
+                            // Outputs can be zero, like the protein in
+                            // sugar, so only their units are divided.
+                            let output_unit = Number {
+                                value: Numeric::one(),
+                                unit: output.unit.clone(),
+                            };
+                            let unit = (&input / &output_unit).expect("Non-zero property").unit;
                             let existing = prev.entry(unit).or_insert_with(BTreeSet::new);
                             for conflict in existing.intersection(&unique) {
                                 println!(
@@ -352,10 +532,9 @@ impl Context {
                                 );
                             }
                             existing.append(&mut unique);
-                            self.temporaries.insert(
-                                prop.name.clone(),
-                                (&input / &output).expect("Non-zero property"),
-                            );
+                            if let Some(ratio) = &input / &output {
+                                self.temporaries.insert(prop.name.clone(), ratio);
+                            }
                             if output == Number::one() {
                                 self.temporaries
                                     .insert(prop.input_name.clone(), input.clone());
@@ -377,8 +556,19 @@ impl Context {
                         })
                         .collect::<Result<BTreeMap<_, _>, _>>();
                     self.temporaries.clear();
+                    let res = res.and_then(|res| {
+                        let mut state_tables = BTreeMap::new();
+                        for table in tables {
+                            let state_table = self.eval_table(table, res.get(&table.property))?;
+                            state_tables
+                                .entry(table.property.clone())
+                                .or_insert_with(Vec::new)
+                                .push(state_table);
+                        }
+                        Ok((res, state_tables))
+                    });
                     match res {
-                        Ok(res) => {
+                        Ok((res, tables)) => {
                             self.substances.insert(
                                 name.clone(),
                                 Substance {
@@ -386,6 +576,8 @@ impl Context {
                                     properties: Arc::new(Properties {
                                         name: name.clone(),
                                         properties: res,
+                                        tables,
+                                        left_out: BTreeMap::new(),
                                     }),
                                 },
                             );
@@ -400,9 +592,11 @@ impl Context {
                     self.category_names
                         .insert(name.clone(), display_name.clone());
                 }
+                Def::DatePattern { .. } => unreachable!(),
                 Def::Error { ref message } => println!("Def {}: {}", name, message),
             };
         }
+        self.add_decay_properties();
 
         for (name, val) in resolver.docs {
             let name = name.name();
@@ -418,4 +612,39 @@ impl Context {
             }
         }
     }
+
+    /// Loads the foods table from `materials::material_defs()`. Foods
+    /// that definitions.units already has, like flour, keep the
+    /// properties the table doesn't give them, like `density_sifted`.
+    pub fn load_foods(&mut self, defs: Defs) {
+        let old = defs
+            .defs
+            .iter()
+            .filter_map(|entry| {
+                let old = self.substances.get(&entry.name)?.clone();
+                Some((entry.name.clone(), old))
+            })
+            .collect::<Vec<_>>();
+        self.load(defs);
+        for (name, old) in old {
+            let substance = match self.substances.get_mut(&name) {
+                Some(substance) => substance,
+                None => continue,
+            };
+            let mut properties = (*substance.properties).clone();
+            for (prop_name, prop) in &old.properties.properties {
+                properties
+                    .properties
+                    .entry(prop_name.clone())
+                    .or_insert_with(|| prop.clone());
+            }
+            for (prop_name, table) in &old.properties.tables {
+                properties
+                    .tables
+                    .entry(prop_name.clone())
+                    .or_insert_with(|| table.clone());
+            }
+            substance.properties = Arc::new(properties);
+        }
+    }
 }
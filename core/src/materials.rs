@@ -0,0 +1,358 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Imports tables of material properties as substances, so that a
+//! materials database can be loaded alongside `definitions.units` and
+//! queried with `thermal_conductivity of 6061_aluminum`.
+//!
+//! A table has a `name` column, an optional `doc` column, and one
+//! column per property. Property columns are headed by the property
+//! name and its unit in parentheses:
+//!
+//! ```text
+//! name,doc,density mass/volume (g/cm^3),thermal_conductivity (W/m K),yield_strength (MPa)
+//! 6061_aluminum,Aluminium alloy in the T6 temper.,2.70,167,276
+//! ```
+//!
+//! Naming an `output/input` pair of quantities after the property name
+//! makes it a ratio, like `density mass g / volume cm^3` in
+//! `definitions.units`, so that `1 L 6061_aluminum -> kg` works. Other
+//! properties are constants. Empty cells are left out.
+//!
+//! The same table can be written as a JSON array of objects with the
+//! same keys, which `parse_json` reads.
+
+use crate::ast::{BinOpType, Def, DefEntry, Defs, Expr, ExprString, Property};
+use crate::gnu_units::{parse_expr, Token, TokenIterator};
+use crate::numeric::Numeric;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// One value in a material table. JSON numbers are read as floats, so
+/// values that need to stay exact can be given as strings instead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Cell {
+    Number(f64),
+    Text(String),
+}
+
+/// One row of a material table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Material {
+    pub name: String,
+    #[serde(default)]
+    pub doc: Option<String>,
+    /// Property values, keyed by their column header.
+    #[serde(flatten)]
+    pub properties: BTreeMap<String, Option<Cell>>,
+}
+
+fn parse_whole(input: &str) -> Result<Expr, String> {
+    let mut iter = TokenIterator::new(input).peekable();
+    let expr = parse_expr(&mut iter);
+    match iter.next().unwrap() {
+        Token::Eof | Token::Newline => Ok(expr),
+        x => Err(format!("Unexpected {:?} in {:?}", x, input)),
+    }
+}
+
+/// Finds the `( that matches a trailing `)`, so units like
+/// `(W/(m K))` can have parentheses of their own.
+fn unit_start(header: &str) -> Option<usize> {
+    if !header.ends_with(')') {
+        return None;
+    }
+    let mut depth = 0;
+    for (i, c) in header.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Builds a property from a column header like `density mass/volume
+/// (g/cm^3)` and a value like `2.70`.
+fn parse_property(material: &str, header: &str, value: &str) -> Result<Property, String> {
+    let (head, unit) = match unit_start(header) {
+        Some(start) => (
+            header[..start].trim(),
+            parse_whole(&header[start + 1..header.len() - 1])?,
+        ),
+        _ => (header.trim(), Expr::new_const(Numeric::one())),
+    };
+    let value = parse_whole(value)?;
+    let words = head.split_whitespace().collect::<Vec<_>>();
+    match words[..] {
+        [name] => Ok(Property {
+            name: name.to_owned(),
+            input: ExprString(Expr::new_const(Numeric::one())),
+            input_name: format!("{}_{}", material, name),
+            output: ExprString(Expr::new_mul(vec![value, unit])),
+            output_name: name.to_owned(),
+            doc: None,
+        }),
+        [name, quantities] => {
+            let mut split = quantities.splitn(2, '/');
+            let (output_name, input_name) = match (split.next(), split.next()) {
+                (Some(output), Some(input)) if !output.is_empty() && !input.is_empty() => {
+                    (output, input)
+                }
+                _ => {
+                    return Err(format!(
+                        "Expected output/input quantities after {}, got {}",
+                        name, quantities
+                    ))
+                }
+            };
+            let (numer, denom) = match unit {
+                Expr::BinOp(ref binop) if binop.op == BinOpType::Frac => {
+                    ((*binop.left).clone(), (*binop.right).clone())
+                }
+                _ => {
+                    return Err(format!(
+                        "The unit of {} needs to be a fraction, since it is a ratio of {} to {}",
+                        name, output_name, input_name
+                    ))
+                }
+            };
+            Ok(Property {
+                name: name.to_owned(),
+                input: ExprString(denom),
+                input_name: input_name.to_owned(),
+                output: ExprString(Expr::new_mul(vec![value, numer])),
+                output_name: output_name.to_owned(),
+                doc: None,
+            })
+        }
+        _ => Err(format!(
+            "Expected a property name and unit in column {:?}",
+            header
+        )),
+    }
+}
+
+/// Turns material rows into substance definitions. Rows with bad values
+/// become `Def::Error`, which is reported when the definitions are
+/// loaded.
+pub fn material_defs(materials: Vec<Material>) -> Defs {
+    let defs = materials
+        .into_iter()
+        .map(|material| {
+            let properties = material
+                .properties
+                .iter()
+                .filter_map(|(header, cell)| {
+                    let value = match *cell {
+                        Some(Cell::Number(value)) => value.to_string(),
+                        Some(Cell::Text(ref value)) if !value.trim().is_empty() => value.clone(),
+                        _ => return None,
+                    };
+                    Some(parse_property(&material.name, header, &value))
+                })
+                .collect::<Result<Vec<_>, _>>();
+            let def = match properties {
+                Ok(properties) => Def::Substance {
+                    symbol: None,
+                    properties,
+                    tables: vec![],
+                },
+                Err(message) => Def::Error { message },
+            };
+            DefEntry {
+                name: material.name,
+                def: Rc::new(def),
+                doc: material.doc,
+                category: None,
+            }
+        })
+        .collect();
+    Defs { defs }
+}
+
+/// Splits CSV into records, following RFC 4180: fields can be quoted
+/// to contain commas, newlines and doubled `""` quotes.
+fn parse_records(input: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut chars = input.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        field.push(c)
+                    }
+                    None => return Err(format!("Line {}: Unterminated quoted field", line)),
+                }
+            },
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\n' => {
+                line += 1;
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|field| !field.is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                } else {
+                    record.clear();
+                }
+            }
+            c => field.push(c),
+        }
+    }
+    record.push(field);
+    if record.iter().any(|field| !field.is_empty()) {
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Parses a JSON material table, an array of objects keyed like the
+/// columns of a CSV table.
+pub fn parse_json(input: &str) -> Result<Vec<Material>, String> {
+    serde_json::from_str(input).map_err(|e| e.to_string())
+}
+
+/// Parses a CSV material table, with a header row naming the columns.
+pub fn parse_csv(input: &str) -> Result<Vec<Material>, String> {
+    let mut records = parse_records(input)?.into_iter();
+    let header = records
+        .next()
+        .ok_or_else(|| "Expected a header row".to_owned())?
+        .into_iter()
+        .map(|column| column.trim().to_owned())
+        .collect::<Vec<_>>();
+    let name = header
+        .iter()
+        .position(|column| column == "name")
+        .ok_or_else(|| "Expected a name column".to_owned())?;
+    let doc = header.iter().position(|column| column == "doc");
+
+    records
+        .enumerate()
+        .map(|(row, record)| {
+            if record.len() != header.len() {
+                return Err(format!(
+                    "Row {}: Expected {} fields, got {}",
+                    row + 2,
+                    header.len(),
+                    record.len()
+                ));
+            }
+            let properties = header
+                .iter()
+                .zip(&record)
+                .enumerate()
+                .filter(|&(i, _)| i != name && Some(i) != doc)
+                .map(|(_, (column, value))| {
+                    (column.clone(), Some(Cell::Text(value.trim().to_owned())))
+                })
+                .collect();
+            Ok(Material {
+                name: record[name].trim().to_owned(),
+                doc: doc
+                    .map(|doc| record[doc].trim().to_owned())
+                    .filter(|doc| !doc.is_empty()),
+                properties,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_records() {
+        assert_eq!(
+            parse_records("a,b\r\n\"c, \"\"d\"\"\",\n\n\"e\nf\",g"),
+            Ok(vec![
+                vec!["a".to_owned(), "b".to_owned()],
+                vec!["c, \"d\"".to_owned(), "".to_owned()],
+                vec!["e\nf".to_owned(), "g".to_owned()],
+            ])
+        );
+        assert_eq!(
+            parse_records("a,\"b\n"),
+            Err("Line 2: Unterminated quoted field".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let materials = parse_csv(
+            "name,doc,density mass/volume (g/cm^3),yield_strength (MPa)\n\
+             6061_aluminum,Aluminium alloy.,2.70,276\n\
+             rubber,,1.1,\n",
+        )
+        .unwrap();
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].name, "6061_aluminum");
+        assert_eq!(materials[0].doc, Some("Aluminium alloy.".to_owned()));
+        assert_eq!(materials[1].doc, None);
+        assert_eq!(
+            materials[1].properties.get("yield_strength (MPa)"),
+            Some(&Some(Cell::Text("".to_owned())))
+        );
+
+        assert_eq!(
+            parse_csv("material,density (g/cm^3)\n"),
+            Err("Expected a name column".to_owned())
+        );
+        assert_eq!(
+            parse_csv("name,density (g/cm^3)\nsteel\n"),
+            Err("Row 2: Expected 2 fields, got 1".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_property() {
+        let property = parse_property("steel", "density mass/volume (g/cm^3)", "7.85").unwrap();
+        assert_eq!(property.output_name, "mass");
+        assert_eq!(property.input_name, "volume");
+        assert_eq!(property.output.to_string(), "7.85 g");
+        assert_eq!(property.input.to_string(), "cm^3");
+
+        let property = parse_property("steel", "yield_strength (MPa)", "250").unwrap();
+        assert_eq!(property.output_name, "yield_strength");
+        assert_eq!(property.input_name, "steel_yield_strength");
+        assert_eq!(property.output.to_string(), "250 MPa");
+
+        // The unit is in the parentheses that close the header.
+        let property = parse_property("steel", "k (W/(m K))", "50").unwrap();
+        assert_eq!(property.output_name, "k");
+        assert_eq!(property.output.to_string(), "50 (W / m K)");
+
+        assert_eq!(
+            parse_property("steel", "density mass/volume (g)", "7.85").map(|_| ()),
+            Err(
+                "The unit of density needs to be a fraction, since it is a ratio of mass to volume"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            parse_property("steel", "density mass (g/cm^3)", "7.85").map(|_| ()),
+            Err("Expected output/input quantities after density, got mass".to_owned())
+        );
+    }
+}
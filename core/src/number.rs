@@ -13,6 +13,10 @@ use std::fmt;
 use std::ops::{Add, Div, Mul, Neg, Sub};
 use std::sync::Arc;
 
+/// The largest power of ten that may be written as a number literal,
+/// as in `1e1000000`.
+pub const MAX_LITERAL_EXPONENT: i32 = 1_000_000;
+
 /// Alias for the primary representation of dimensionality.
 pub type Quantity = BTreeMap<Dimension, i64>;
 
@@ -58,9 +62,7 @@ pub fn pow(left: &Numeric, exp: i32) -> Numeric {
             Numeric::Rational(ref left) => left,
             Numeric::Float(f) => return Numeric::Float(f.powi(exp)),
         };
-        let num = left.numer().pow(exp as u32);
-        let den = left.denom().pow(exp as u32);
-        Numeric::Rational(BigRat::ratio(&num, &den))
+        Numeric::Rational(left.pow(exp as u32))
     }
 }
 
@@ -311,31 +313,29 @@ impl Number {
     ) -> Result<Numeric, String> {
         use std::str::FromStr;
 
-        let num = BigInt::from_str_radix(integer, 10).unwrap();
-        let frac = if let Some(ref frac) = frac {
-            let frac_digits = frac.len();
-            let frac = BigInt::from_str_radix(&*frac, 10).unwrap();
-            BigRat::ratio(&frac, &BigInt::from(10u64).pow(frac_digits as u32))
-        } else {
-            BigRat::zero()
-        };
-        let exp = if let Some(ref exp) = exp {
-            let exp: i32 = match FromStr::from_str(&*exp) {
+        let digits = format!("{}{}", integer, frac.unwrap_or(""));
+        let mantissa = BigInt::from_str_radix(&digits, 10).unwrap();
+        let exp: i64 = if let Some(ref exp) = exp {
+            match FromStr::from_str(&*exp) {
                 Ok(exp) => exp,
                 // presumably because it is too large
                 Err(e) => return Err(format!("Failed to parse exponent: {}", e)),
-            };
-            let res = BigInt::from(10u64).pow(exp.abs() as u32);
-            if exp < 0 {
-                BigRat::ratio(&BigInt::one(), &res)
-            } else {
-                BigRat::ratio(&res, &BigInt::one())
             }
         } else {
-            BigRat::one()
+            0
         };
-        let num = &BigRat::ratio(&num, &BigInt::one()) + &frac;
-        Ok(Numeric::Rational(&num * &exp))
+        let exp = exp - frac.map(str::len).unwrap_or(0) as i64;
+        // Literals are parsed before any `Limits` apply, so this keeps
+        // something like `1e999999999` from hanging the parser.
+        if exp.abs() > MAX_LITERAL_EXPONENT as i64 {
+            return Err(format!(
+                "Exponent is too large, the limit is {}",
+                MAX_LITERAL_EXPONENT
+            ));
+        }
+        Ok(Numeric::Rational(BigRat::from_decimal(
+            &mantissa, exp as i32,
+        )))
     }
 
     /// Computes the reciprocal (1/x) of the value.
@@ -390,14 +390,22 @@ impl Number {
         if exp.value.abs() >= Numeric::from(1 << 31) {
             return Err("Exponent is too large".to_string());
         }
-        let (num, den) = exp.value.to_rational();
+        let (num, den) = exp
+            .value
+            .to_rational()
+            .ok_or("Exponent must be a finite number")?;
         let one = BigInt::one();
         if den == one {
-            let exp: Option<i64> = num.as_int();
-            Ok(self.powi(exp.unwrap() as i32))
+            let exp = num.as_int().ok_or("Exponent is too large")?;
+            if exp < 0 && self.value == Numeric::zero() {
+                return Err("Division by zero".to_string());
+            }
+            Ok(self.powi(exp as i32))
         } else if num == one {
-            let exp: Option<i64> = den.as_int();
-            self.root(exp.unwrap() as i32)
+            match den.as_int() {
+                Some(exp) if exp <= i32::MAX as i64 => self.root(exp as i32),
+                _ => Err("Root is too large".to_string()),
+            }
         } else if !self.dimless() {
             Err("Exponentiation must result in integer dimensions".to_string())
         } else {
@@ -427,7 +435,9 @@ impl Number {
     /// units, and possibly apply SI prefixes.
     pub fn prettify(&self, context: &Context) -> Number {
         let unit = self.pretty_unit(context);
-        if unit.len() == 1 {
+        // Prefixes are meaningless on units raised to large powers, and
+        // comparing against the powers of every prefix gets expensive.
+        if unit.len() == 1 && unit.values().all(|power| power.abs() <= 12) {
             use std::collections::HashSet;
             let prefixes = [
                 "milli", "micro", "nano", "pico", "femto", "atto", "zepto", "yocto", "kilo",
@@ -3,14 +3,17 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use std::cmp::{Ordering, PartialOrd};
+use std::convert::TryFrom;
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
 use crate::bigint::BigInt;
 use crate::bigrat::BigRat;
 
 /// Number type.
-#[derive(Clone, PartialEq, Debug, Serialize)]
-#[serde(into = "NumericParts")]
+/// Serialized as `NumericParts`. Deserializing always produces a
+/// rational, even if the number was originally a float.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(into = "NumericParts", try_from = "NumericParts")]
 pub enum Numeric {
     /// Arbitrary-precision rational fraction.
     Rational(BigRat),
@@ -30,14 +33,14 @@ enum Parity {
 
 /// Used when converting to string representation to choose desired
 /// output mode.
-#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Digits {
     Default,
     FullInt,
     Digits(u64),
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NumericParts {
     numer: String,
@@ -94,13 +97,16 @@ impl Numeric {
         }
     }
 
-    pub fn to_rational(&self) -> (BigInt, BigInt) {
+    /// Returns the numerator and denominator, or None for NaN and
+    /// infinities.
+    pub fn to_rational(&self) -> Option<(BigInt, BigInt)> {
         match *self {
-            Numeric::Rational(ref rational) => (rational.numer(), rational.denom()),
-            Numeric::Float(x) => {
+            Numeric::Rational(ref rational) => Some((rational.numer(), rational.denom())),
+            Numeric::Float(x) if x.is_finite() => {
                 let rational = BigRat::from(x);
-                (rational.numer(), rational.denom())
+                Some((rational.numer(), rational.denom()))
             }
+            Numeric::Float(_) => None,
         }
     }
 
@@ -121,6 +127,45 @@ impl Numeric {
         self.into()
     }
 
+    /// Memory used by the numerator and denominator, in bits. Floats
+    /// are always 64.
+    pub fn size_in_bits(&self) -> u64 {
+        match *self {
+            Numeric::Rational(ref rational) => rational.numer().bits() + rational.denom().bits(),
+            Numeric::Float(_) => 64,
+        }
+    }
+
+    /// Upper bound on the length of `to_string(base, digits)`, which
+    /// can be computed without actually formatting the number.
+    pub fn string_len_estimate(&self, base: u8, digits: Digits) -> usize {
+        let (num, den) = match self.abs().to_rational() {
+            Some(rational) => rational,
+            // NaN, Inf, -Inf
+            None => return 4,
+        };
+        let intdigits = (&num / &den).size_in_base(base);
+        // Leading zeros after the decimal point, for numbers below one.
+        let zeros = if num != BigInt::zero() && num < den {
+            (&den / &num).size_in_base(base)
+        } else {
+            0
+        };
+        let full = digits != Digits::Default
+            || den == BigInt::one() && (base == 2 || base == 8 || base == 16 || base == 32);
+        let frac = match digits {
+            Digits::Digits(n) => n as usize,
+            Digits::Default | Digits::FullInt => 6,
+        };
+        if full || intdigits + zeros <= 9 * 10 / base as usize {
+            // sign, decimal point
+            2 + intdigits + zeros + frac
+        } else {
+            // sign, decimal point, exponent
+            2 + frac + 1 + 20
+        }
+    }
+
     /// Returns (is_exact, repr).
     pub fn to_string(&self, base: u8, digits: Digits) -> (bool, String) {
         use std::char::from_digit;
@@ -138,30 +183,30 @@ impl Numeric {
         }
 
         let sign = *self < Numeric::zero();
-        let rational = self.abs();
-        let (num, den) = rational.to_rational();
-        let rational = match rational {
+        let rational = match self.abs() {
             Numeric::Rational(rational) => rational,
             Numeric::Float(f) => BigRat::from(f),
         };
+        let (num, den) = (rational.numer(), rational.denom());
         let intdigits = (&num / &den).size_in_base(base) as u32;
         let mut buf = String::new();
         if sign {
             buf.push('-');
         }
-        let zero = BigRat::zero();
-        let one = BigInt::one();
         let ten = BigInt::from(base as u64);
-        let ten_rational = BigRat::ratio(&ten, &one);
-        let mut cursor = &rational / &BigRat::ratio(&ten.pow(intdigits), &one);
+        // The remaining digits are cursor / divisor. These are kept as
+        // separate integers rather than a BigRat, since reducing the
+        // fraction after every digit is very slow for large numbers.
+        let mut cursor = num;
+        let divisor = &den * &ten.pow(intdigits);
         let mut n = 0;
         let mut only_zeros = true;
         let mut zeros = 0;
         let mut placed_decimal = false;
         loop {
-            let exact = cursor == zero;
+            let exact = cursor == BigInt::zero();
             let use_sci = if digits != Digits::Default
-                || den == one && (base == 2 || base == 8 || base == 16 || base == 32)
+                || den == BigInt::one() && (base == 2 || base == 8 || base == 16 || base == 32)
             {
                 false
             } else {
@@ -195,8 +240,23 @@ impl Numeric {
             if n == intdigits {
                 buf.push('.');
                 placed_decimal = true;
+                if only_zeros && !exact {
+                    // Skip over leading zeros in one go rather than
+                    // one digit at a time, which is quadratic. The
+                    // digit counts are estimated from the bit lengths,
+                    // which can overcount the denominator by one.
+                    let skip = (divisor.size_in_base(base) as u32)
+                        .saturating_sub(cursor.size_in_base(base) as u32 + 2);
+                    if skip > 0 {
+                        cursor = &cursor * &ten.pow(skip);
+                        buf.extend((0..skip).map(|_| '0'));
+                        zeros += skip;
+                        n += skip;
+                    }
+                }
             }
-            let digit = &(&(&cursor.numer() * &ten) / &cursor.denom()) % &ten;
+            cursor = &cursor * &ten;
+            let digit = &cursor / &divisor;
             let v: Option<i64> = digit.as_int();
             let v = v.unwrap();
             if v != 0 {
@@ -207,8 +267,7 @@ impl Numeric {
             if !(v == 0 && only_zeros && n < intdigits - 1) {
                 buf.push(from_digit(v as u32, base as u32).unwrap());
             }
-            cursor = &cursor * &ten_rational;
-            cursor = &cursor - &BigRat::ratio(&digit, &one);
+            cursor = &cursor - &(&digit * &divisor);
             n += 1;
         }
     }
@@ -265,16 +324,46 @@ impl<'a> Into<f64> for &'a Numeric {
 impl Into<NumericParts> for Numeric {
     fn into(self) -> NumericParts {
         let (exact, approx) = self.string_repr(10, Digits::Default);
-        let (num, den) = self.to_rational();
+        let (numer, denom) = match self.to_rational() {
+            Some((num, den)) => (num.to_string(), den.to_string()),
+            None => (self.to_string(10, Digits::Default).1, "1".to_owned()),
+        };
         NumericParts {
-            numer: num.to_string(),
-            denom: den.to_string(),
+            numer,
+            denom,
             exact_value: exact,
             approx_value: approx,
         }
     }
 }
 
+impl TryFrom<NumericParts> for Numeric {
+    type Error = String;
+
+    /// Only the numerator and denominator are used, the other fields
+    /// are for display.
+    fn try_from(parts: NumericParts) -> Result<Numeric, String> {
+        let numer = BigInt::from_str_radix(&parts.numer, 10);
+        let denom = BigInt::from_str_radix(&parts.denom, 10);
+        match (numer, denom) {
+            (Ok(_), Ok(ref denom)) if *denom == BigInt::zero() => {
+                Err("Denominator must not be zero".to_owned())
+            }
+            (Ok(numer), Ok(denom)) => Ok(Numeric::Rational(BigRat::ratio(&numer, &denom))),
+            // NaN and infinities.
+            (Err(_), Ok(ref denom)) if *denom == BigInt::one() => parts
+                .numer
+                .parse::<f64>()
+                .ok()
+                .filter(|value| !value.is_finite())
+                .map(Numeric::Float)
+                .ok_or_else(|| format!("Invalid numerator {}", parts.numer)),
+            (Err(_), _) => Err(format!("Invalid numerator {}", parts.numer)),
+            (_, Err(_)) => Err(format!("Invalid denominator {}", parts.denom)),
+        }
+    }
+}
+
 impl PartialOrd for Numeric {
     fn partial_cmp(&self, other: &Numeric) -> Option<Ordering> {
         match self.parity(other) {
@@ -0,0 +1,303 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Balancing chemical reactions like `C3H8 + O2 -> CO2 + H2O`, and
+//! working out how many moles of one formula another turns into.
+//!
+//! A reaction is balanced by finding the coefficients that conserve
+//! every element and the total charge, which is the nullspace of the
+//! element-by-species matrix. Reactions whose nullspace isn't exactly
+//! one-dimensional are rejected, since they either can't be balanced
+//! or are really several reactions at once.
+
+use crate::formula::{parse_formula, Formula};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a.abs()
+}
+
+fn too_large() -> String {
+    "Reaction is too large to balance".to_owned()
+}
+
+fn parse_species(names: &[String]) -> Result<Vec<Formula>, String> {
+    names
+        .iter()
+        .map(|name| {
+            parse_formula(name).map_err(|e| format!("{} is not a chemical formula: {}", name, e))
+        })
+        .collect()
+}
+
+/// Finds the smallest whole coefficients that balance `reactants`
+/// against `products`, in the same order as the reactants followed by
+/// the products.
+pub fn balance(reactants: &[String], products: &[String]) -> Result<Vec<u64>, String> {
+    let names = reactants.iter().chain(products).collect::<Vec<_>>();
+    let species = parse_species(reactants)?
+        .into_iter()
+        .chain(parse_species(products)?)
+        .collect::<Vec<_>>();
+
+    // One row per element plus one for charge, and one column per
+    // species, with products counted negatively.
+    let elements = species
+        .iter()
+        .flat_map(|f| f.atoms.keys())
+        .collect::<BTreeSet<_>>();
+    let mut rows = elements
+        .iter()
+        .map(|&element| {
+            species
+                .iter()
+                .enumerate()
+                .map(|(i, f)| {
+                    let count = i128::from(f.atoms.get(element).cloned().unwrap_or(0));
+                    if i < reactants.len() {
+                        count
+                    } else {
+                        -count
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    if species.iter().any(|f| f.charge != 0) {
+        rows.push(
+            species
+                .iter()
+                .enumerate()
+                .map(|(i, f)| {
+                    let charge = i128::from(f.charge);
+                    if i < reactants.len() {
+                        charge
+                    } else {
+                        -charge
+                    }
+                })
+                .collect(),
+        );
+    }
+
+    // Row reduce, keeping everything integral by cross multiplying and
+    // dividing each row by its gcd.
+    let mut pivots = vec![];
+    for col in 0..species.len() {
+        let rank = pivots.len();
+        let pivot = match (rank..rows.len()).find(|&r| rows[r][col] != 0) {
+            Some(pivot) => pivot,
+            None => continue,
+        };
+        rows.swap(rank, pivot);
+        let pivot_row = rows[rank].clone();
+        for (r, row) in rows.iter_mut().enumerate() {
+            if r == rank || row[col] == 0 {
+                continue;
+            }
+            let (p, q) = (pivot_row[col], row[col]);
+            let mut divisor = 0;
+            for (value, &pivot) in row.iter_mut().zip(&pivot_row) {
+                *value = value
+                    .checked_mul(p)
+                    .and_then(|a| pivot.checked_mul(q).and_then(|b| a.checked_sub(b)))
+                    .ok_or_else(too_large)?;
+                divisor = gcd(divisor, *value);
+            }
+            if divisor > 1 {
+                for value in row.iter_mut() {
+                    *value /= divisor;
+                }
+            }
+        }
+        pivots.push(col);
+    }
+
+    let free = (0..species.len())
+        .filter(|c| !pivots.contains(c))
+        .collect::<Vec<_>>();
+    let free = match free[..] {
+        [] => return Err("Reaction cannot be balanced".to_owned()),
+        [free] => free,
+        _ => {
+            return Err(
+                "Reaction can be balanced in more than one way, try splitting it into \
+                 separate reactions"
+                    .to_owned(),
+            )
+        }
+    };
+
+    // Setting the free coefficient to the lcm of the pivots makes all
+    // the others whole.
+    let mut scale = 1i128;
+    for (r, &col) in pivots.iter().enumerate() {
+        let p = rows[r][col].abs();
+        scale = (scale / gcd(scale, p))
+            .checked_mul(p)
+            .ok_or_else(too_large)?;
+    }
+    let mut coefficients = vec![0i128; species.len()];
+    coefficients[free] = scale;
+    for (r, &col) in pivots.iter().enumerate() {
+        coefficients[col] = -rows[r][free].checked_mul(scale).ok_or_else(too_large)? / rows[r][col];
+    }
+    let divisor = coefficients.iter().fold(0, |acc, &c| gcd(acc, c));
+    // Blame whichever species are outnumbered for being on the wrong
+    // side.
+    let negative = coefficients.iter().filter(|&&c| c < 0).count();
+    let sign = if negative * 2 > coefficients.len() {
+        -1
+    } else {
+        1
+    };
+
+    names
+        .iter()
+        .zip(coefficients)
+        .enumerate()
+        .map(|(i, (name, c))| {
+            let c = c / divisor * sign;
+            if c > 0 {
+                u64::try_from(c).map_err(|_| too_large())
+            } else if c == 0 {
+                Err(format!("{} takes no part in the reaction", name))
+            } else if i < reactants.len() {
+                Err(format!(
+                    "Reaction cannot be balanced with {} as a reactant",
+                    name
+                ))
+            } else {
+                Err(format!(
+                    "Reaction cannot be balanced with {} as a product",
+                    name
+                ))
+            }
+        })
+        .collect()
+}
+
+/// How many moles of `product` each mole of `reagent` turns into, as a
+/// numerator and denominator, going by the elements the two have in
+/// common. These all have to give the same answer, otherwise the
+/// reaction needs to be balanced to tell.
+pub fn yield_ratio(reagent: &str, product: &str) -> Result<(u64, u64), String> {
+    let formulas = parse_species(&[reagent.to_owned(), product.to_owned()])?;
+    let (from, to) = (&formulas[0].atoms, &formulas[1].atoms);
+    let shared = from
+        .iter()
+        .filter_map(|(element, &a)| to.get(element).map(|&b| (element, (a, b))))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut iter = shared.values();
+    let (a, b) = match iter.next() {
+        Some(&ratio) => ratio,
+        None => {
+            return Err(format!(
+                "{} and {} have no elements in common",
+                reagent, product
+            ))
+        }
+    };
+    if iter.any(|&(c, d)| u128::from(a) * u128::from(d) != u128::from(b) * u128::from(c)) {
+        return Err(format!(
+            "{} and {} share {} in different proportions, try `balance <reaction> for <amount>`",
+            reagent,
+            product,
+            shared
+                .keys()
+                .map(|element| element.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    let divisor = gcd(i128::from(a), i128::from(b)) as u64;
+    Ok((a / divisor, b / divisor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn species(list: &[&str]) -> Vec<String> {
+        list.iter().map(|&s| s.to_owned()).collect()
+    }
+
+    fn check(reactants: &[&str], products: &[&str]) -> Result<Vec<u64>, String> {
+        balance(&species(reactants), &species(products))
+    }
+
+    #[test]
+    fn test_balance() {
+        assert_eq!(
+            check(&["C3H8", "O2"], &["CO2", "H2O"]),
+            Ok(vec![1, 5, 3, 4])
+        );
+        assert_eq!(check(&["H2", "O2"], &["H2O"]), Ok(vec![2, 1, 2]));
+        assert_eq!(check(&["Fe", "O2"], &["Fe2O3"]), Ok(vec![4, 3, 2]));
+        assert_eq!(
+            check(&["KMnO4", "HCl"], &["KCl", "MnCl2", "H2O", "Cl2"]),
+            Ok(vec![2, 16, 2, 2, 8, 5])
+        );
+        assert_eq!(check(&["Cu", "Ag+"], &["Cu2+", "Ag"]), Ok(vec![1, 2, 1, 2]));
+        assert_eq!(check(&["CuSO4·5H2O"], &["CuSO4", "H2O"]), Ok(vec![1, 1, 5]));
+    }
+
+    #[test]
+    fn test_balance_errors() {
+        assert_eq!(
+            check(&["H2"], &["O2"]),
+            Err("Reaction cannot be balanced".to_owned())
+        );
+        assert_eq!(
+            check(&["H2", "O2"], &["H2O", "H2O2"]),
+            Err(
+                "Reaction can be balanced in more than one way, try splitting it into \
+                 separate reactions"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            check(&["H2O"], &["H2", "O2", "H2O"]),
+            Err(
+                "Reaction can be balanced in more than one way, try splitting it into \
+                 separate reactions"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            check(&["H2O", "H2"], &["O2"]),
+            Err("Reaction cannot be balanced with H2 as a reactant".to_owned())
+        );
+        assert_eq!(
+            check(&["H2", "O2", "N2"], &["H2O"]),
+            Err("N2 takes no part in the reaction".to_owned())
+        );
+        assert!(check(&["H2", "Xx("], &["H2O"]).is_err());
+    }
+
+    #[test]
+    fn test_yield_ratio() {
+        assert_eq!(yield_ratio("C3H8", "CO2"), Ok((3, 1)));
+        assert_eq!(yield_ratio("C3H8", "H2O"), Ok((4, 1)));
+        assert_eq!(yield_ratio("H2O", "O2"), Ok((1, 2)));
+        assert_eq!(yield_ratio("Fe2O3", "Fe"), Ok((2, 1)));
+        assert_eq!(
+            yield_ratio("C3H8", "NaCl"),
+            Err("C3H8 and NaCl have no elements in common".to_owned())
+        );
+        assert_eq!(
+            yield_ratio("C2H5OH", "CO2"),
+            Err("C2H5OH and CO2 share C, O in different proportions, \
+                 try `balance <reaction> for <amount>`"
+                .to_owned())
+        );
+    }
+}
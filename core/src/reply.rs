@@ -1,15 +1,18 @@
-use crate::ast::{Expr, Precedence, UnaryOpType};
+use crate::ast::{DateFormat, Expr, Precedence, UnaryOpType};
+use crate::date::GenericDateTime;
+use crate::limits::LimitExceeded;
 use crate::number::NumberParts;
 use crate::numeric::Digits;
 use chrono::{DateTime, TimeZone};
 use std::collections::BTreeMap;
 use std::convert::From;
+use std::error::Error;
 use std::fmt::Result as FmtResult;
 use std::fmt::{Display, Formatter};
 use std::iter::once;
 use std::rc::Rc;
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "lowercase")]
 pub enum ExprParts {
@@ -28,13 +31,13 @@ pub enum ExprParts {
     },
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExprReply {
     exprs: Vec<ExprParts>,
     ast: Expr,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DefReply {
     pub canon_name: String,
@@ -44,36 +47,36 @@ pub struct DefReply {
     pub doc: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConversionReply {
     pub value: NumberParts,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FactorizeReply {
     pub factorizations: Vec<BTreeMap<Rc<String>, usize>>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UnitsInCategory {
     pub category: Option<String>,
     pub units: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UnitsForReply {
     pub units: Vec<UnitsInCategory>,
     /// Dimensions and quantity are set.
     pub of: NumberParts,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UnitListReply {
     pub rest: NumberParts,
     pub list: Vec<NumberParts>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DurationReply {
     pub raw: NumberParts,
     pub years: NumberParts,
@@ -85,27 +88,49 @@ pub struct DurationReply {
     pub seconds: NumberParts,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchReply {
     pub results: Vec<NumberParts>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PropertyReply {
     pub name: String,
     pub value: NumberParts,
     pub doc: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// One element of a compound, from `composition of <formula>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ElementReply {
+    /// The symbol as written in the formula, like `C` or `13C`.
+    pub symbol: String,
+    /// The element's substance, like `carbon`.
+    pub name: String,
+    /// Atoms of the element in each unit of the compound.
+    pub count: u64,
+    /// Moles of the element in the amount of the compound.
+    pub amount: NumberParts,
+    /// Mass of the element in the amount of the compound.
+    pub mass: NumberParts,
+    /// Share of the compound's mass, from 0 to 100.
+    pub mass_percent: NumberParts,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubstanceReply {
     pub name: String,
     pub doc: Option<String>,
     pub amount: NumberParts,
     pub properties: Vec<PropertyReply>,
+    /// Only filled in for `composition of <formula>`.
+    #[serde(default)]
+    pub composition: Vec<ElementReply>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DateReply {
     pub year: i32,
     pub month: i32,
@@ -114,13 +139,145 @@ pub struct DateReply {
     pub minute: i32,
     pub second: i32,
     pub nanosecond: i32,
-    /// chrono-humanize output, if enabled.
+    /// The date relative to `Context::now`, like `in 3 days`, if
+    /// `Context::use_humanize` is set.
     pub human: Option<String>,
     pub string: String,
     pub rfc3339: String,
+    /// Seconds since the Unix epoch.
+    #[serde(default)]
+    pub unix: i64,
+    /// Milliseconds since the Unix epoch.
+    #[serde(default)]
+    pub unix_millis: i64,
+    /// ISO 8601 week date, such as `2020-W20-3`. This and the other
+    /// representations below are only filled in when converting to a
+    /// date format, like `-> unix`.
+    #[serde(default)]
+    pub iso_week: Option<String>,
+    /// ISO 8601 ordinal date, such as `2020-134`.
+    #[serde(default)]
+    pub iso_ordinal: Option<String>,
+    /// Left out for years before 0 or after 9999, which RFC 2822 can't
+    /// represent.
+    #[serde(default)]
+    pub rfc2822: Option<String>,
+    #[serde(default)]
+    pub julian_day: Option<String>,
+    #[serde(default)]
+    pub modified_julian_day: Option<String>,
+    /// The date in another calendar, after a conversion like `-> hebrew`.
+    #[serde(default)]
+    pub calendar: Option<CalendarDateReply>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalendarDateReply {
+    pub calendar: String,
+    pub year: i64,
+    pub month: u32,
+    pub month_name: String,
+    pub day: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntervalReply {
+    pub start: DateReply,
+    pub end: DateReply,
+    pub duration: DurationReply,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurrenceReply {
+    pub dates: Vec<DateReply>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BooleanReply {
+    pub value: bool,
+}
+
+/// How a date literal fared against one of the date patterns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatePatternResult {
+    pub pattern: String,
+    /// The date the pattern produced, if it matched.
+    pub date: Option<DateReply>,
+    /// Why the pattern didn't match.
+    pub error: Option<String>,
+}
+
+/// The reply to `datepatterns #date#`, which tries a date literal
+/// against every pattern.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatePatternsReply {
+    /// The text of the date literal, without the surrounding `#`.
+    pub input: String,
+    /// Set when the literal is read without patterns, like `tomorrow`.
+    pub builtin: Option<DateReply>,
+    /// The index of the pattern that the literal is read with, which is
+    /// the first one that matched.
+    pub matched: Option<usize>,
+    pub patterns: Vec<DatePatternResult>,
+}
+
+/// The reply to `dilute <volume> of <concentration> to <concentration>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DilutionReply {
+    /// The volume after diluting, or the mass of solvent for molality.
+    pub volume: NumberParts,
+    /// How much solvent to add.
+    pub solvent: NumberParts,
+}
+
+/// One species in a balanced reaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeciesReply {
+    pub coefficient: u64,
+    pub formula: String,
+    /// How much of the species takes part, when the reaction was
+    /// balanced for an amount of one of them.
+    pub substance: Option<SubstanceReply>,
+}
+
+/// One property of a substance, from `properties of <substance>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertyInfoReply {
+    pub name: String,
+    pub output_name: String,
+    pub output: NumberParts,
+    /// Left out for constant properties, like the `mass` of an
+    /// electron.
+    pub input_name: Option<String>,
+    pub input: Option<NumberParts>,
+    pub doc: Option<String>,
+}
+
+/// The reply to `properties of <substance>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PropertiesReply {
+    pub name: String,
+    pub doc: Option<String>,
+    pub properties: Vec<PropertyInfoReply>,
+}
+
+/// The reply to `substances with <property>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubstanceListReply {
+    pub property: String,
+    pub substances: Vec<String>,
+}
+
+/// The reply to `balance <reactants> -> <products>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceReply {
+    pub reactants: Vec<SpeciesReply>,
+    pub products: Vec<SpeciesReply>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[allow(clippy::large_enum_variant)]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "type")]
@@ -129,6 +286,14 @@ pub enum QueryReply {
     Date(DateReply),
     Substance(SubstanceReply),
     Duration(Box<DurationReply>),
+    Interval(Box<IntervalReply>),
+    Recurrence(RecurrenceReply),
+    Boolean(BooleanReply),
+    DatePatterns(DatePatternsReply),
+    Dilution(DilutionReply),
+    Properties(PropertiesReply),
+    SubstanceList(SubstanceListReply),
+    Balance(BalanceReply),
     Def(Box<DefReply>),
     Conversion(Box<ConversionReply>),
     Factorize(FactorizeReply),
@@ -137,25 +302,93 @@ pub enum QueryReply {
     Search(SearchReply),
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConformanceError {
     pub left: NumberParts,
     pub right: NumberParts,
     pub suggestions: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotFoundError {
     pub got: String,
     pub suggestion: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// The query could not be parsed. The parser doesn't track positions,
+/// so there is no span; the message names the unexpected token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParseError {
+    pub message: String,
+}
+
+/// A date literal did not match any of the known date patterns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DateError {
+    /// The text of the date literal, without the surrounding `#`.
+    pub input: String,
+    pub message: String,
+}
+
+/// A date pattern could not be parsed, from `datepatterns.txt`, a
+/// `!datepattern` directive or `Context::add_datepattern`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DatePatternError {
+    /// The line it was on, counting from 1, if it came from a file.
+    pub line: Option<usize>,
+    pub pattern: String,
+    pub message: String,
+}
+
+/// A line of `holidays.txt` could not be parsed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HolidayError {
+    /// Counting from 1.
+    pub line: usize,
+    pub text: String,
+    pub message: String,
+}
+
+/// A line of `timezones.txt` could not be parsed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimezoneError {
+    /// Counting from 1.
+    pub line: usize,
+    pub text: String,
+    pub message: String,
+}
+
+/// An operator was applied to values it isn't defined for, such as
+/// adding a length to a mass.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperationError {
+    pub message: String,
+    /// The operator symbol, e.g. `+` or `*`.
+    pub operator: String,
+    /// The operands as they would be printed to the user. Binary
+    /// operators have two, negation has one.
+    pub operands: Vec<String>,
+}
+
+/// A context could not be created, e.g. by `simple_context`. Problems
+/// with individual definitions don't fail the load and aren't reported
+/// through this.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadError {
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
 pub enum QueryError {
+    Parse(ParseError),
+    InvalidDate(DateError),
+    Load(LoadError),
+    Operation(OperationError),
     Conformance(Box<ConformanceError>),
     NotFound(NotFoundError),
+    LimitExceeded(LimitExceeded),
     Generic { message: String },
 }
 
@@ -163,6 +396,14 @@ impl QueryError {
     pub fn generic(message: String) -> QueryError {
         QueryError::Generic { message }
     }
+
+    pub fn parse(message: String) -> QueryError {
+        QueryError::Parse(ParseError { message })
+    }
+
+    pub fn load(message: String) -> QueryError {
+        QueryError::Load(LoadError { message })
+    }
 }
 
 impl ExprReply {
@@ -257,6 +498,62 @@ impl ExprReply {
                         literal!(")");
                     }
                 }
+                Expr::Yields {
+                    ref expr,
+                    ref product,
+                } => {
+                    if prec < Precedence::Equals {
+                        literal!("(");
+                    }
+                    recurse(expr, parts, Precedence::Range);
+                    literal!(" yields ");
+                    parts.push(ExprParts::Unit {
+                        name: product.to_owned(),
+                    });
+                    if prec < Precedence::Equals {
+                        literal!(")");
+                    }
+                }
+                Expr::Mixture { ref expr, by } => {
+                    if prec < Precedence::Equals {
+                        literal!("(");
+                    }
+                    recurse(expr, parts, Precedence::Range);
+                    literal!(format!(" by {}", by));
+                    if prec < Precedence::Equals {
+                        literal!(")");
+                    }
+                }
+                Expr::At {
+                    ref expr,
+                    ref states,
+                } => {
+                    if prec < Precedence::Equals {
+                        literal!("(");
+                    }
+                    recurse(expr, parts, Precedence::Range);
+                    literal!(" at ");
+                    for (i, state) in states.iter().enumerate() {
+                        if i > 0 {
+                            literal!(", ");
+                        }
+                        recurse(state, parts, Precedence::Range);
+                    }
+                    if prec < Precedence::Equals {
+                        literal!(")");
+                    }
+                }
+                Expr::After { ref expr, ref time } => {
+                    if prec < Precedence::Equals {
+                        literal!("(");
+                    }
+                    recurse(expr, parts, Precedence::Range);
+                    literal!(" after ");
+                    recurse(time, parts, Precedence::Range);
+                    if prec < Precedence::Equals {
+                        literal!(")");
+                    }
+                }
                 Expr::Error { ref message } => parts.push(ExprParts::Error {
                     message: message.to_owned(),
                 }),
@@ -278,6 +575,12 @@ impl From<NotFoundError> for QueryError {
     }
 }
 
+impl From<LimitExceeded> for QueryError {
+    fn from(v: LimitExceeded) -> Self {
+        QueryError::LimitExceeded(v)
+    }
+}
+
 impl From<String> for QueryError {
     fn from(message: String) -> Self {
         QueryError::Generic { message }
@@ -291,6 +594,14 @@ impl Display for QueryReply {
             QueryReply::Date(ref v) => write!(fmt, "{}", v),
             QueryReply::Substance(ref v) => write!(fmt, "{}", v),
             QueryReply::Duration(ref v) => write!(fmt, "{}", v),
+            QueryReply::Interval(ref v) => write!(fmt, "{}", v),
+            QueryReply::Recurrence(ref v) => write!(fmt, "{}", v),
+            QueryReply::Boolean(ref v) => write!(fmt, "{}", v),
+            QueryReply::DatePatterns(ref v) => write!(fmt, "{}", v),
+            QueryReply::Dilution(ref v) => write!(fmt, "{}", v),
+            QueryReply::Properties(ref v) => write!(fmt, "{}", v),
+            QueryReply::SubstanceList(ref v) => write!(fmt, "{}", v),
+            QueryReply::Balance(ref v) => write!(fmt, "{}", v),
             QueryReply::Def(ref v) => write!(fmt, "{}", v),
             QueryReply::Conversion(ref v) => write!(fmt, "{}", v),
             QueryReply::Factorize(ref v) => write!(fmt, "{}", v),
@@ -305,12 +616,92 @@ impl Display for QueryError {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
         match *self {
             QueryError::Generic { ref message } => write!(fmt, "{}", message),
+            QueryError::Parse(ref v) => write!(fmt, "{}", v),
+            QueryError::InvalidDate(ref v) => write!(fmt, "{}", v),
+            QueryError::Load(ref v) => write!(fmt, "{}", v),
+            QueryError::Operation(ref v) => write!(fmt, "{}", v),
             QueryError::Conformance(ref v) => write!(fmt, "{}", v),
             QueryError::NotFound(ref v) => write!(fmt, "{}", v),
+            QueryError::LimitExceeded(ref v) => write!(fmt, "{}", v),
+        }
+    }
+}
+
+// The variants display as the error they wrap, so there is no separate
+// source to report.
+impl Error for QueryError {}
+
+impl Display for ParseError {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        write!(fmt, "{}", self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+impl Display for DateError {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        write!(fmt, "{}", self.message)
+    }
+}
+
+impl Error for DateError {}
+
+impl Display for DatePatternError {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        if let Some(line) = self.line {
+            write!(fmt, "Line {}: ", line)?;
         }
+        write!(fmt, "{}: {}", self.message, self.pattern)
+    }
+}
+
+impl Error for DatePatternError {}
+
+impl Display for HolidayError {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        write!(fmt, "Line {}: {}: {}", self.line, self.message, self.text)
     }
 }
 
+impl Error for HolidayError {}
+
+impl Display for TimezoneError {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        write!(fmt, "Line {}: {}: {}", self.line, self.message, self.text)
+    }
+}
+
+impl Error for TimezoneError {}
+
+impl Display for LoadError {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        write!(fmt, "{}", self.message)
+    }
+}
+
+impl Error for LoadError {}
+
+impl Display for OperationError {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        write!(fmt, "{}:", self.message)?;
+        match self.operands.as_slice() {
+            [operand] => write!(fmt, " {} <{}>", self.operator, operand),
+            operands => {
+                for (i, operand) in operands.iter().enumerate() {
+                    if i != 0 {
+                        write!(fmt, " {}", self.operator)?;
+                    }
+                    write!(fmt, " <{}>", operand)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for OperationError {}
+
 impl Display for NotFoundError {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
         match self.suggestion.as_ref() {
@@ -320,6 +711,8 @@ impl Display for NotFoundError {
     }
 }
 
+impl Error for NotFoundError {}
+
 impl Display for ConformanceError {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
         writeln!(fmt, "Conformance error: {} != {}", self.left, self.right)?;
@@ -327,6 +720,8 @@ impl Display for ConformanceError {
     }
 }
 
+impl Error for ConformanceError {}
+
 impl DateReply {
     pub fn new<Tz>(ctx: &crate::context::Context, date: DateTime<Tz>) -> DateReply
     where
@@ -337,6 +732,13 @@ impl DateReply {
         DateReply {
             string: date.to_string(),
             rfc3339: date.to_rfc3339(),
+            unix: date.timestamp(),
+            unix_millis: date.timestamp_millis(),
+            iso_week: None,
+            iso_ordinal: None,
+            rfc2822: None,
+            julian_day: None,
+            modified_julian_day: None,
             year: date.year(),
             month: date.month() as i32,
             day: date.day() as i32,
@@ -345,6 +747,29 @@ impl DateReply {
             second: date.second() as i32,
             nanosecond: date.nanosecond() as i32,
             human: ctx.humanize(date),
+            calendar: None,
+        }
+    }
+
+    /// Fills in the other representations of the date, for conversions
+    /// to a date format.
+    pub fn add_formats<Tz>(&mut self, ctx: &crate::context::Context, date: &DateTime<Tz>)
+    where
+        Tz: TimeZone,
+        Tz::Offset: Display,
+    {
+        let format = |format: DateFormat| crate::date::format_date(ctx, date, &format).ok();
+        self.iso_week = format(DateFormat::IsoWeek);
+        self.iso_ordinal = format(DateFormat::IsoOrdinal);
+        self.rfc2822 = format(DateFormat::Rfc2822);
+        self.julian_day = format(DateFormat::JulianDay);
+        self.modified_julian_day = format(DateFormat::ModifiedJulianDay);
+    }
+
+    pub fn from_generic(ctx: &crate::context::Context, date: &GenericDateTime) -> DateReply {
+        match *date {
+            GenericDateTime::Fixed(ref date) => DateReply::new(ctx, *date),
+            GenericDateTime::Timezone(ref date) => DateReply::new(ctx, *date),
         }
     }
 }
@@ -382,10 +807,57 @@ impl Display for SubstanceReply {
                 ))
                 .collect::<Vec<_>>()
                 .join("; ")
+        )?;
+        for element in &self.composition {
+            write!(
+                fmt,
+                "\n  {} ({}): {} {}, {}, {}, {}%",
+                element.symbol,
+                element.name,
+                element.count,
+                if element.count == 1 { "atom" } else { "atoms" },
+                element.amount.format("n u"),
+                element.mass.format("n u"),
+                element.mass_percent.format("n"),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for DilutionReply {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            fmt,
+            "Dilute to {} by adding {} of solvent",
+            self.volume.format("n u"),
+            self.solvent.format("n u")
         )
     }
 }
 
+impl Display for BalanceReply {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        fn side(species: &[SpeciesReply]) -> String {
+            species
+                .iter()
+                .map(|s| match s.coefficient {
+                    1 => s.formula.clone(),
+                    n => format!("{} {}", n, s.formula),
+                })
+                .collect::<Vec<_>>()
+                .join(" + ")
+        }
+        write!(fmt, "{} -> {}", side(&self.reactants), side(&self.products))?;
+        for species in self.reactants.iter().chain(&self.products) {
+            if let Some(ref substance) = species.substance {
+                write!(fmt, "\n  {}", substance)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Display for DefReply {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
         write!(fmt, "Definition: {}", self.canon_name)?;
@@ -479,6 +951,71 @@ impl Display for DurationReply {
     }
 }
 
+impl Display for IntervalReply {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            fmt,
+            "{} .. {}, lasting {}",
+            self.start, self.end, self.duration
+        )
+    }
+}
+
+impl Display for RecurrenceReply {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            fmt,
+            "{}",
+            self.dates
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+impl Display for BooleanReply {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        write!(fmt, "{}", self.value)
+    }
+}
+
+impl Display for DatePatternResult {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        match (&self.date, &self.error) {
+            (Some(date), _) => write!(fmt, "`{}`: matched {}", self.pattern, date),
+            (None, Some(error)) => write!(fmt, "`{}`: {}", self.pattern, error),
+            (None, None) => write!(fmt, "`{}`", self.pattern),
+        }
+    }
+}
+
+impl Display for DatePatternsReply {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        let matched = self.matched.and_then(|i| self.patterns.get(i));
+        match (&self.builtin, matched) {
+            (Some(date), _) => write!(
+                fmt,
+                "#{}# is read as {} without date patterns",
+                self.input, date
+            )?,
+            (None, Some(pat)) => write!(
+                fmt,
+                "#{}# is read by `{}` as {}",
+                self.input,
+                pat.pattern,
+                pat.date.as_ref().unwrap()
+            )?,
+            (None, None) => write!(fmt, "#{}# matches no date patterns", self.input)?,
+        }
+        for pat in &self.patterns {
+            write!(fmt, "\n  {}", pat)?;
+        }
+        Ok(())
+    }
+}
+
 impl Display for UnitListReply {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
         write!(
@@ -498,6 +1035,42 @@ impl Display for UnitListReply {
     }
 }
 
+impl Display for PropertiesReply {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        write!(fmt, "Properties of {}:", self.name)?;
+        if let Some(ref doc) = self.doc {
+            write!(fmt, " {}", doc)?;
+        }
+        for prop in &self.properties {
+            write!(
+                fmt,
+                "\n  {}: {} {}",
+                prop.name,
+                prop.output_name,
+                prop.output.format("n u")
+            )?;
+            if let (Some(name), Some(input)) = (&prop.input_name, &prop.input) {
+                write!(fmt, " / {} {}", name, input.format("n u"))?;
+            }
+            if let Some(ref doc) = prop.doc {
+                write!(fmt, " ({})", doc)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for SubstanceListReply {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            fmt,
+            "Substances with {}: {}",
+            self.property,
+            self.substances.join(", ")
+        )
+    }
+}
+
 impl Display for SearchReply {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
         write!(
@@ -0,0 +1,57 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Versioning for the serialized forms of `Query`, `Expr`,
+//! `QueryReply` and `QueryError`, so that they can be passed between
+//! processes, for example to send a pre-parsed query to a worker and
+//! decode the reply.
+//!
+//! ```json
+//! {"version": 1, "value": {"type": "expr", "value": ...}}
+//! ```
+//!
+//! `SCHEMA_VERSION` is incremented whenever a change is made that an
+//! older reader couldn't handle, such as renaming or removing a field or
+//! variant, or changing how a value is represented. Adding a variant or
+//! an optional field doesn't change the version.
+
+use serde::de::{Deserializer, Error};
+use serde::Deserialize;
+
+/// The current version of the serialized format.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Wraps a value together with the schema version it was written with.
+/// Deserializing fails if the version doesn't match `SCHEMA_VERSION`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Versioned<T> {
+    #[serde(deserialize_with = "check_version")]
+    pub version: u32,
+    pub value: T,
+}
+
+impl<T> Versioned<T> {
+    pub fn new(value: T) -> Versioned<T> {
+        Versioned {
+            version: SCHEMA_VERSION,
+            value,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+fn check_version<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let version = u32::deserialize(deserializer)?;
+    if version == SCHEMA_VERSION {
+        Ok(version)
+    } else {
+        Err(D::Error::custom(format!(
+            "Unsupported schema version {}, expected {}",
+            version, SCHEMA_VERSION
+        )))
+    }
+}
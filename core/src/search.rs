@@ -29,7 +29,9 @@ pub fn search<'a>(ctx: &'a Context, query: &str, num_results: usize) -> Vec<&'a
     let mut results = BinaryHeap::new();
     let query = query.to_lowercase();
     {
-        let mut r#try = |x: &'a str| {
+        // `matched` is set for substances with a property whose name or
+        // doc mentions the query.
+        let mut r#try = |x: &'a str, matched: bool| {
             let borrow = x;
             let x = x.to_lowercase();
             let modifier = if x == query {
@@ -38,7 +40,7 @@ pub fn search<'a>(ctx: &'a Context, query: &str, num_results: usize) -> Vec<&'a
                 3_000
             } else if x.ends_with(&query) {
                 2_000
-            } else if x.contains(&query) {
+            } else if x.contains(&query) || matched {
                 1_000
             } else {
                 0_000
@@ -54,16 +56,23 @@ pub fn search<'a>(ctx: &'a Context, query: &str, num_results: usize) -> Vec<&'a
         };
 
         for k in &ctx.dimensions {
-            r#try(&**k.id);
+            r#try(&**k.id, false);
         }
         for k in ctx.units.keys() {
-            r#try(&**k);
+            r#try(&**k, false);
         }
         for k in ctx.quantities.values() {
-            r#try(&**k);
+            r#try(&**k, false);
         }
-        for k in ctx.substances.keys() {
-            r#try(&**k);
+        for (k, v) in &ctx.substances {
+            let matched = v.properties.properties.iter().any(|(name, prop)| {
+                name.to_lowercase().contains(&query)
+                    || prop
+                        .doc
+                        .iter()
+                        .any(|doc| doc.to_lowercase().contains(&query))
+            });
+            r#try(&**k, matched);
         }
     }
     results
@@ -33,10 +33,50 @@ pub struct Property {
     pub doc: Option<String>,
 }
 
+/// Values of a property at points of a state variable, like the
+/// density of water against temperature.
+#[derive(Debug, Clone)]
+pub struct StateTable {
+    /// The quantity of the states, like `temperature`.
+    pub variable: String,
+    /// Pairs of a state and the property's output per unit of input,
+    /// sorted by state.
+    pub points: Vec<(Number, Number)>,
+    pub doc: Option<String>,
+}
+
+impl StateTable {
+    /// Interpolates linearly between the two points around `state`.
+    /// States outside the table aren't extrapolated.
+    pub fn interpolate(&self, state: &Number) -> Option<Number> {
+        let after = self
+            .points
+            .iter()
+            .position(|(at, _)| at.value >= state.value)?;
+        let (at1, value1) = &self.points[after];
+        if after == 0 {
+            return if at1.value == state.value {
+                Some(value1.clone())
+            } else {
+                None
+            };
+        }
+        let (at0, value0) = &self.points[after - 1];
+        let t = (&(state - at0)? / &(at1 - at0)?)?;
+        &(&(value1 - value0)? * &t)? + value0
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Properties {
     pub name: String,
     pub properties: BTreeMap<String, Property>,
+    /// Tables of properties that depend on the state, keyed by the
+    /// property name.
+    pub tables: BTreeMap<String, Vec<StateTable>>,
+    /// Properties that `at` left out because their table doesn't
+    /// cover the state, with the error saying so.
+    pub left_out: BTreeMap<String, (Property, String)>,
 }
 
 #[derive(Debug, Clone)]
@@ -57,62 +97,126 @@ impl Substance {
             properties: Arc::new(Properties {
                 name,
                 properties: self.properties.properties.clone(),
+                tables: self.properties.tables.clone(),
+                left_out: self.properties.left_out.clone(),
             }),
         }
     }
 
+    /// Fails with the error of a property that `at` left out if
+    /// putting it back would change `known`.
+    fn check_left_out<T: PartialEq>(
+        &self,
+        known: &T,
+        attempt: impl Fn(&Substance) -> T,
+    ) -> Result<(), String> {
+        for (name, (prop, message)) in &self.properties.left_out {
+            let mut properties = (*self.properties).clone();
+            properties.left_out = BTreeMap::new();
+            properties.properties.insert(name.clone(), prop.clone());
+            let other = Substance {
+                amount: self.amount.clone(),
+                properties: Arc::new(properties),
+            };
+            if attempt(&other) != *known {
+                return Err(message.clone());
+            }
+        }
+        Ok(())
+    }
+
     pub fn get(&self, name: &str) -> Result<Number, SubstanceGetError> {
+        let res = self.get_known(name);
+        self.check_left_out(&res.is_ok(), |sub| sub.get_known(name).is_ok())
+            .map_err(SubstanceGetError::Generic)?;
+        res
+    }
+
+    /// Looks up a property among the ones the substance has.
+    fn get_known(&self, name: &str) -> Result<Number, SubstanceGetError> {
         if self.amount.dimless() {
-            self.properties
-                .properties
-                .get(name)
-                .ok_or_else(|| {
-                    SubstanceGetError::Generic(format!(
-                        "No such property {} of {}",
-                        name, self.properties.name
-                    ))
-                })
-                .map(|prop| {
-                    (&(&self.amount * &prop.output).unwrap() / &prop.input)
-                        .expect("Non-zero property")
-                })
+            let prop = self.properties.properties.get(name).ok_or_else(|| {
+                SubstanceGetError::Generic(format!(
+                    "No such property {} of {}",
+                    name, self.properties.name
+                ))
+            })?;
+            (&(&self.amount * &prop.output).unwrap() / &prop.input).ok_or_else(|| {
+                SubstanceGetError::Generic(format!(
+                    "Division by zero: {} of {} is given per zero {}",
+                    name, self.properties.name, prop.input_name
+                ))
+            })
         } else {
+            let direct = self.get_direct(name);
+            if direct.is_ok() {
+                return direct;
+            }
+            // Amounts like `1 cup milk` reach properties given per mass,
+            // like its energy, through another property like its density.
             for prop in self.properties.properties.values() {
-                if name == prop.output_name {
-                    let input = (&prop.input / &self.amount)
+                let amount = match (&prop.input / &self.amount, &prop.output / &self.amount) {
+                    (Some(ref input), _) if input.dimless() => &prop.output / input,
+                    (_, Some(ref output)) if output.dimless() => &prop.input / output,
+                    _ => None,
+                };
+                let amount = match amount {
+                    Some(amount) if amount.unit != self.amount.unit => amount,
+                    _ => continue,
+                };
+                let other = Substance {
+                    amount,
+                    properties: self.properties.clone(),
+                };
+                if let Ok(res) = other.get_direct(name) {
+                    return Ok(res);
+                }
+            }
+            direct
+        }
+    }
+
+    /// Looks up a property that converts the amount directly.
+    fn get_direct(&self, name: &str) -> Result<Number, SubstanceGetError> {
+        // Several properties can give the same quantity, like the
+        // mass of an amount of water from either its volume or its
+        // moles, so only the last mismatch is reported.
+        let mut mismatch = None;
+        for prop in self.properties.properties.values() {
+            if name == prop.output_name {
+                let input = (&prop.input / &self.amount)
+                    .ok_or_else(|| SubstanceGetError::Generic("Division by zero".to_owned()))?;
+                if input.dimless() {
+                    let res = (&prop.output / &input)
                         .ok_or_else(|| SubstanceGetError::Generic("Division by zero".to_owned()))?;
-                    if input.dimless() {
-                        let res = (&prop.output / &input).ok_or_else(|| {
-                            SubstanceGetError::Generic("Division by zero".to_owned())
-                        })?;
-                        return Ok(res);
-                    } else {
-                        return Err(SubstanceGetError::Conformance(
-                            self.amount.clone(),
-                            prop.input.clone(),
-                        ));
-                    }
-                } else if name == prop.input_name {
-                    let output = (&prop.output / &self.amount)
+                    return Ok(res);
+                } else {
+                    mismatch = Some(SubstanceGetError::Conformance(
+                        self.amount.clone(),
+                        prop.input.clone(),
+                    ));
+                }
+            } else if name == prop.input_name {
+                let output = (&prop.output / &self.amount)
+                    .ok_or_else(|| SubstanceGetError::Generic("Division by zero".to_owned()))?;
+                if output.dimless() {
+                    let res = (&prop.input / &output)
                         .ok_or_else(|| SubstanceGetError::Generic("Division by zero".to_owned()))?;
-                    if output.dimless() {
-                        let res = (&prop.input / &output).ok_or_else(|| {
-                            SubstanceGetError::Generic("Division by zero".to_owned())
-                        })?;
-                        return Ok(res);
-                    } else {
-                        return Err(SubstanceGetError::Conformance(
-                            self.amount.clone(),
-                            prop.output.clone(),
-                        ));
-                    }
+                    return Ok(res);
+                } else {
+                    mismatch = Some(SubstanceGetError::Conformance(
+                        self.amount.clone(),
+                        prop.output.clone(),
+                    ));
                 }
             }
-            Err(SubstanceGetError::Generic(format!(
+        }
+        Err(mismatch.unwrap_or_else(|| {
+            SubstanceGetError::Generic(format!(
                 "No such property {} of {}",
                 name, self.properties.name
-            )))
-        }
+            ))
+        }))
     }
 
     /// Analogous to Context::show()
@@ -124,6 +228,32 @@ impl Substance {
         bottom_const: Numeric,
         base: u8,
         digits: Digits,
+    ) -> Result<SubstanceReply, String> {
+        let shown = |sub: &Substance| {
+            sub.get_in_unit_known(
+                unit.clone(),
+                context,
+                bottom_name.clone(),
+                bottom_const.clone(),
+                base,
+                digits,
+            )
+        };
+        let reply = shown(self)?;
+        self.check_left_out(&reply.properties.len(), |sub| {
+            shown(sub).map_or(0, |reply| reply.properties.len())
+        })?;
+        Ok(reply)
+    }
+
+    fn get_in_unit_known(
+        &self,
+        unit: Number,
+        context: &Context,
+        bottom_name: BTreeMap<String, isize>,
+        bottom_const: Numeric,
+        base: u8,
+        digits: Digits,
     ) -> Result<SubstanceReply, String> {
         if self.amount.dimless() {
             Ok(SubstanceReply {
@@ -143,8 +273,13 @@ impl Substance {
                         };
                         let (input, output) = if output.unit != unit.unit {
                             if let Some(input) = input {
+                                let ratio = try_div!(output, input, context);
                                 if input.unit == unit.unit {
                                     (Some(output), input)
+                                } else if ratio.unit == unit.unit {
+                                    // Properties like `molar_mass` shown in
+                                    // a unit like g/mol.
+                                    (None, (&ratio * &self.amount).unwrap())
                                 } else {
                                     return Ok(None);
                                 }
@@ -190,6 +325,7 @@ impl Substance {
                     })
                     .filter_map(|x| x.map(|x| x.map(Ok)).unwrap_or_else(|e| Some(Err(e))))
                     .collect::<Result<Vec<PropertyReply>, String>>()?,
+                composition: vec![],
             })
         } else {
             let func = |(_k, v): (&String, &Property)| {
@@ -244,13 +380,28 @@ impl Substance {
                     doc: v.doc.clone(),
                 }))
             };
+            // `1 mCi Co60 -> Bq` shows the amount itself in the unit.
+            let value = if self.amount.unit == unit.unit {
+                context
+                    .show(
+                        &try_div!(self.amount, unit, context),
+                        &unit,
+                        bottom_name.clone(),
+                        bottom_const.clone(),
+                        base,
+                        digits,
+                    )
+                    .value
+            } else {
+                self.amount.to_parts(context)
+            };
             let amount = PropertyReply {
                 name: self
                     .amount
                     .to_parts(context)
                     .quantity
                     .unwrap_or_else(|| "amount".to_owned()),
-                value: self.amount.to_parts(context),
+                value,
                 doc: None,
             };
             Ok(SubstanceReply {
@@ -263,11 +414,21 @@ impl Substance {
                     .into_iter()
                     .filter_map(|x| x)
                     .collect(),
+                composition: vec![],
             })
         }
     }
 
     pub fn to_reply(&self, context: &Context) -> Result<SubstanceReply, String> {
+        let reply = self.to_reply_known(context)?;
+        self.check_left_out(&reply.properties.len(), |sub| {
+            sub.to_reply_known(context)
+                .map_or(0, |reply| reply.properties.len())
+        })?;
+        Ok(reply)
+    }
+
+    fn to_reply_known(&self, context: &Context) -> Result<SubstanceReply, String> {
         if self.amount.dimless() {
             Ok(SubstanceReply {
                 name: self.properties.name.clone(),
@@ -303,6 +464,7 @@ impl Substance {
                         })
                     })
                     .collect::<Result<Vec<PropertyReply>, String>>()?,
+                composition: vec![],
             })
         } else {
             let func = |(_k, v): (&String, &Property)| {
@@ -355,6 +517,7 @@ impl Substance {
                     .into_iter()
                     .filter_map(|x| x)
                     .collect(),
+                composition: vec![],
             })
         }
     }
@@ -430,8 +593,7 @@ impl<'a, 'b> Add<&'b Substance> for &'a Substance {
                             k.clone(),
                             Property {
                                 output: (&(&self.amount * &prop1.output).unwrap()
-                                    + &(&other.amount * &prop2.output).unwrap())
-                                    .expect("Add"),
+                                    + &(&other.amount * &prop2.output).unwrap())?,
                                 input_name: prop1.input_name.clone(),
                                 input: mol,
                                 output_name: prop1.output_name.clone(),
@@ -440,6 +602,8 @@ impl<'a, 'b> Add<&'b Substance> for &'a Substance {
                         ))
                     })
                     .collect(),
+                tables: BTreeMap::new(),
+                left_out: BTreeMap::new(),
             }),
         };
         if res.properties.properties.is_empty() {
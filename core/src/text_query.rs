@@ -32,6 +32,7 @@ pub enum Token {
     Minus,
     Asterisk,
     DashArrow,
+    DotDot,
     Colon,
     Date(Vec<DateToken>),
     Comma,
@@ -61,6 +62,7 @@ fn describe(token: &Token) -> String {
         Token::Minus => "`-`".to_owned(),
         Token::Asterisk => "`*`".to_owned(),
         Token::DashArrow => "`->`".to_owned(),
+        Token::DotDot => "`..`".to_owned(),
         Token::Colon => "`:`".to_owned(),
         Token::Date(_) => "date literal".to_owned(),
         Token::Comma => "`,`".to_owned(),
@@ -144,6 +146,10 @@ impl<'a> Iterator for TokenIterator<'a> {
                 }
                 _ => Token::Slash,
             },
+            '.' if self.0.peek() == Some(&'.') => {
+                self.0.next();
+                Token::DotDot
+            }
             x @ '0'..='9' | x @ '.' => {
                 if x == '0' && self.0.peek() == Some(&'x') {
                     self.0.next();
@@ -218,6 +224,24 @@ impl<'a> Iterator for TokenIterator<'a> {
                     while let Some(c) = self.0.peek().cloned() {
                         match c {
                             '0'..='9' => integer.push(self.0.next().unwrap()),
+                            // Names like 6061_aluminum start with digits.
+                            // See split_leading_number() for `3_kg`.
+                            '_' if {
+                                let mut ahead = self.0.clone();
+                                ahead.next();
+                                matches!(ahead.peek(), Some(c) if c.is_alphabetic())
+                            } =>
+                            {
+                                let mut buf = integer;
+                                while let Some(c) = self.0.peek().cloned() {
+                                    if c.is_alphanumeric() || c == '_' || c == '$' {
+                                        buf.push(self.0.next().unwrap());
+                                    } else {
+                                        break;
+                                    }
+                                }
+                                return Some(Token::Ident(buf));
+                            }
                             '\u{2009}' | '_' => {
                                 self.0.next();
                             }
@@ -295,7 +319,15 @@ impl<'a> Iterator for TokenIterator<'a> {
                             break;
                         }
                     }
-                    let v = u32::from_str_radix(&*buf, 16).unwrap();
+                    let v = match u32::from_str_radix(&*buf, 16) {
+                        Ok(v) => v,
+                        Err(_) => {
+                            return Some(Token::Error(format!(
+                                "Malformed unicode escape: \\u{}",
+                                buf
+                            )))
+                        }
+                    };
                     if let Some(c) = ::std::char::from_u32(v) {
                         let mut buf = String::new();
                         buf.push(c);
@@ -416,23 +448,17 @@ impl<'a> Iterator for TokenIterator<'a> {
                 let mut buf = String::new();
                 buf.push(x);
                 while let Some(c) = self.0.peek().cloned() {
-                    if c.is_alphanumeric() || c == '_' || c == '$' {
+                    // Hydrate dots keep `CuSO4·5H2O` together.
+                    if c.is_alphanumeric() || matches!(c, '_' | '$' | '·' | '•' | '∙') {
                         buf.push(self.0.next().unwrap());
                     } else {
                         break;
                     }
                 }
+                if let Some(degree) = Degree::from_name(&buf) {
+                    return Some(Token::Degree(degree));
+                }
                 match &*buf {
-                    "degC" | "°C" | "celsius" | "℃" => Token::Degree(Degree::Celsius),
-                    "degF" | "°F" | "fahrenheit" | "℉" => Token::Degree(Degree::Fahrenheit),
-                    "degRé" | "°Ré" | "degRe" | "°Re" | "réaumur" | "reaumur" => {
-                        Token::Degree(Degree::Reaumur)
-                    }
-                    "degRø" | "°Rø" | "degRo" | "°Ro" | "rømer" | "romer" => {
-                        Token::Degree(Degree::Romer)
-                    }
-                    "degDe" | "°De" | "delisle" => Token::Degree(Degree::Delisle),
-                    "degN" | "°N" | "degnewton" => Token::Degree(Degree::Newton),
                     "per" => Token::Slash,
                     "to" | "in" => Token::DashArrow,
                     _ => Token::Ident(buf),
@@ -443,6 +469,21 @@ impl<'a> Iterator for TokenIterator<'a> {
     }
 }
 
+/// Splits a name the tokenizer kept whole, like `3_kg`, into its
+/// leading number and the rest. Returns None for names that don't
+/// start with digits.
+pub(crate) fn split_leading_number(name: &str) -> Option<(Numeric, &str)> {
+    let split = name.find(|c: char| !c.is_ascii_digit())?;
+    let (digits, rest) = name.split_at(split);
+    let rest = rest.strip_prefix('_')?;
+    if digits.is_empty() || rest.is_empty() {
+        return None;
+    }
+    crate::number::Number::from_parts(digits, None, None)
+        .ok()
+        .map(|number| (number, rest))
+}
+
 pub type Iter<'a> = Peekable<TokenIterator<'a>>;
 
 fn attr_from_name(name: &str) -> Option<&'static str> {
@@ -465,7 +506,7 @@ fn attr_from_name(name: &str) -> Option<&'static str> {
 }
 
 fn parse_function(iter: &mut Iter<'_>, func: Function) -> Expr {
-    let args = match iter.peek().cloned().unwrap() {
+    let args = match iter.peek().cloned().unwrap_or(Token::Eof) {
         Token::LPar => {
             iter.next();
             let mut args = vec![];
@@ -475,7 +516,7 @@ fn parse_function(iter: &mut Iter<'_>, func: Function) -> Expr {
                     break;
                 }
                 args.push(parse_expr(iter));
-                match iter.peek().cloned().unwrap() {
+                match iter.peek().cloned().unwrap_or(Token::Eof) {
                     Token::Comma => {
                         iter.next();
                     }
@@ -504,12 +545,12 @@ fn parse_radix(num: &str, base: u32, description: &str) -> Expr {
 }
 
 fn parse_term(iter: &mut Iter<'_>) -> Expr {
-    match iter.next().unwrap() {
+    match iter.next().unwrap_or(Token::Eof) {
         Token::Ident(ref id) => {
             if let Some(func) = Function::from_name(id) {
                 parse_function(iter, func)
             } else if let Some(attr) = attr_from_name(id) {
-                match iter.peek().cloned().unwrap() {
+                match iter.peek().cloned().unwrap_or(Token::Eof) {
                     Token::Ident(ref name) => {
                         iter.next();
                         Expr::new_unit(format!("{}{}", attr, name))
@@ -520,7 +561,7 @@ fn parse_term(iter: &mut Iter<'_>) -> Expr {
                     )),
                 }
             } else {
-                match iter.peek().cloned().unwrap() {
+                match iter.peek().cloned().unwrap_or(Token::Eof) {
                     Token::Ident(ref s) if s == "of" => {
                         iter.next();
                         Expr::new_of(id, parse_juxt(iter))
@@ -543,8 +584,9 @@ fn parse_term(iter: &mut Iter<'_>) -> Expr {
         Token::Plus => Expr::new_plus(parse_term(iter)),
         Token::Minus => Expr::new_negate(parse_term(iter)),
         Token::LPar => {
-            let res = parse_expr(iter);
-            match iter.next().unwrap() {
+            let expr = parse_expr(iter);
+            let res = parse_loose_words(expr, iter);
+            match iter.next().unwrap_or(Token::Eof) {
                 Token::RPar => res,
                 x => Expr::new_error(format!("Expected `)`, got {}", describe(&x))),
             }
@@ -558,7 +600,7 @@ fn parse_term(iter: &mut Iter<'_>) -> Expr {
 
 fn parse_suffix(iter: &mut Iter<'_>) -> Expr {
     let left = parse_term(iter);
-    match *iter.peek().unwrap() {
+    match iter.peek().cloned().unwrap_or(Token::Eof) {
         Token::Percent => {
             let mut left = left;
             while let Some(&Token::Percent) = iter.peek() {
@@ -573,7 +615,7 @@ fn parse_suffix(iter: &mut Iter<'_>) -> Expr {
 
 fn parse_pow(iter: &mut Iter<'_>) -> Expr {
     let left = parse_suffix(iter);
-    match *iter.peek().unwrap() {
+    match iter.peek().cloned().unwrap_or(Token::Eof) {
         Token::Caret => {
             iter.next();
             let right = parse_pow(iter);
@@ -585,7 +627,7 @@ fn parse_pow(iter: &mut Iter<'_>) -> Expr {
 
 fn parse_frac(iter: &mut Iter<'_>) -> Expr {
     let left = parse_pow(iter);
-    match *iter.peek().unwrap() {
+    match iter.peek().cloned().unwrap_or(Token::Eof) {
         Token::Pipe => {
             iter.next();
             let right = parse_pow(iter);
@@ -598,7 +640,7 @@ fn parse_frac(iter: &mut Iter<'_>) -> Expr {
 fn parse_juxt(iter: &mut Iter<'_>) -> Expr {
     let mut terms = vec![parse_frac(iter)];
     loop {
-        match iter.peek().cloned().unwrap() {
+        match iter.peek().cloned().unwrap_or(Token::Eof) {
             Token::Asterisk
             | Token::Slash
             | Token::Comma
@@ -606,6 +648,7 @@ fn parse_juxt(iter: &mut Iter<'_>) -> Expr {
             | Token::Plus
             | Token::Minus
             | Token::DashArrow
+            | Token::DotDot
             | Token::RPar
             | Token::Newline
             | Token::Comment(_)
@@ -627,7 +670,7 @@ fn parse_juxt(iter: &mut Iter<'_>) -> Expr {
 fn parse_div(iter: &mut Iter<'_>) -> Expr {
     let mut terms = vec![parse_juxt(iter)];
     loop {
-        match iter.peek().cloned().unwrap() {
+        match iter.peek().cloned().unwrap_or(Token::Eof) {
             Token::Slash => {
                 iter.next();
                 let left = if terms.len() == 1 {
@@ -654,7 +697,7 @@ fn parse_div(iter: &mut Iter<'_>) -> Expr {
 fn parse_add(iter: &mut Iter<'_>) -> Expr {
     let mut left = parse_div(iter);
     loop {
-        match *iter.peek().unwrap() {
+        match iter.peek().cloned().unwrap_or(Token::Eof) {
             Token::Plus => {
                 iter.next();
                 let right = parse_div(iter);
@@ -670,12 +713,24 @@ fn parse_add(iter: &mut Iter<'_>) -> Expr {
     }
 }
 
-fn parse_eq(iter: &mut Iter<'_>) -> Expr {
+fn parse_range(iter: &mut Iter<'_>) -> Expr {
     let left = parse_add(iter);
-    match iter.peek().cloned().unwrap() {
-        Token::Equals => {
+    match iter.peek().cloned().unwrap_or(Token::Eof) {
+        Token::DotDot => {
             iter.next();
             let right = parse_add(iter);
+            Expr::new_range(left, right)
+        }
+        _ => left,
+    }
+}
+
+fn parse_eq(iter: &mut Iter<'_>) -> Expr {
+    let left = parse_range(iter);
+    match iter.peek().cloned().unwrap_or(Token::Eof) {
+        Token::Equals => {
+            iter.next();
+            let right = parse_range(iter);
             Expr::new_equals(left, right)
         }
         _ => left,
@@ -690,7 +745,7 @@ pub fn parse_unitlist(iter: &mut Iter<'_>) -> Option<Vec<String>> {
     let mut expecting_term = true;
     let mut res = vec![];
     loop {
-        match iter.next().unwrap() {
+        match iter.next().unwrap_or(Token::Eof) {
             Token::Ident(ref ident) if expecting_term => {
                 res.push(ident.clone());
                 expecting_term = false;
@@ -712,27 +767,328 @@ pub fn parse_unitlist(iter: &mut Iter<'_>) -> Option<Vec<String>> {
 pub fn parse_offset(iter: &mut Iter<'_>) -> Option<i64> {
     use std::str::FromStr;
 
-    let sign = match iter.next().unwrap() {
+    let sign = match iter.next().unwrap_or(Token::Eof) {
         Token::Plus => 1,
         Token::Minus => -1,
         _ => return None,
     };
-    let hour = match iter.next().unwrap() {
+    let hour = match iter.next().unwrap_or(Token::Eof) {
         Token::Decimal(ref i, None, None) if i.len() == 2 => i.clone(),
         _ => return None,
     };
-    match iter.next().unwrap() {
+    match iter.next().unwrap_or(Token::Eof) {
         Token::Colon => (),
         _ => return None,
     }
-    let min = match iter.next().unwrap() {
+    let min = match iter.next().unwrap_or(Token::Eof) {
         Token::Decimal(ref i, None, None) if i.len() == 2 => i.clone(),
         _ => return None,
     };
-    Some(sign * (i64::from_str(&*hour).unwrap() * 3600 + i64::from_str(&*min).unwrap() * 60))
+    Some(sign * (i64::from_str(&*hour).ok()? * 3600 + i64::from_str(&*min).ok()? * 60))
+}
+
+/// Splits `expr` at the first juxtaposed `word`, which binds more
+/// loosely than any operator, so `a + b and c` gives `a + b` and `c`.
+fn split_at_word(expr: Expr, word: &str) -> Result<(Expr, Expr), Expr> {
+    fn from_terms(mut terms: Vec<Expr>) -> Expr {
+        if terms.len() == 1 {
+            terms.pop().unwrap()
+        } else {
+            Expr::new_mul(terms)
+        }
+    }
+    match expr {
+        Expr::Mul { mut exprs } => {
+            match exprs
+                .iter()
+                .position(|e| matches!(e, Expr::Unit { name } if name == word))
+            {
+                Some(pos) if pos > 0 && pos + 1 < exprs.len() => {
+                    let right = exprs.split_off(pos + 1);
+                    exprs.pop();
+                    Ok((from_terms(exprs), from_terms(right)))
+                }
+                _ => Err(Expr::Mul { exprs }),
+            }
+        }
+        // The suffix in `water at 80 °C` applies to the whole product.
+        Expr::UnaryOp(UnaryOpExpr {
+            op: UnaryOpType::Degree(degree),
+            expr,
+        }) => match split_at_word(*expr, word) {
+            Ok((first, rest)) => Ok((first, Expr::new_suffix(degree, rest))),
+            Err(expr) => Err(Expr::new_suffix(degree, expr)),
+        },
+        Expr::BinOp(BinOpExpr { op, left, right }) => match split_at_word(*left, word) {
+            Ok((first, middle)) => Ok((
+                first,
+                Expr::BinOp(BinOpExpr {
+                    op,
+                    left: Box::new(middle),
+                    right,
+                }),
+            )),
+            Err(left) => match split_at_word(*right, word) {
+                Ok((middle, rest)) => Ok((
+                    Expr::BinOp(BinOpExpr {
+                        op,
+                        left: Box::new(left),
+                        right: Box::new(middle),
+                    }),
+                    rest,
+                )),
+                Err(right) => Err(Expr::BinOp(BinOpExpr {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                })),
+            },
+        },
+        expr => Err(expr),
+    }
+}
+
+/// Reads the words that bind more loosely than any operator, like
+/// `<amount> yields <formula>`, `<substances> by volume`,
+/// `<substance> at <state>, <state>` and `<substance> after <time>`.
+fn parse_loose_words(expr: Expr, iter: &mut Iter<'_>) -> Expr {
+    // `density of water at 80 °C` is the density of water at 80 °C.
+    // `water at -30 °C` parses as `(water at) - 30 °C`.
+    fn ends_with_at(expr: &Expr) -> bool {
+        match *expr {
+            Expr::Of { ref expr, .. } => ends_with_at(expr),
+            Expr::Mul { ref exprs } => {
+                exprs.len() > 1 && matches!(exprs.last(), Some(Expr::Unit { name }) if name == "at")
+            }
+            _ => false,
+        }
+    }
+    fn negative_at(expr: Expr, state: Expr) -> Expr {
+        match expr {
+            Expr::Of { property, expr } => Expr::new_of(&property, negative_at(*expr, state)),
+            Expr::Mul { mut exprs } => {
+                exprs.pop();
+                let expr = if exprs.len() == 1 {
+                    exprs.pop().unwrap()
+                } else {
+                    Expr::new_mul(exprs)
+                };
+                fn negate(expr: Expr) -> Expr {
+                    match expr {
+                        Expr::Const { value } => Expr::new_const(-&value),
+                        Expr::Mul { mut exprs } if exprs.len() == 1 => negate(exprs.pop().unwrap()),
+                        expr => Expr::new_negate(expr),
+                    }
+                }
+                let state = match state {
+                    Expr::UnaryOp(UnaryOpExpr {
+                        op: UnaryOpType::Degree(degree),
+                        expr,
+                    }) => Expr::new_suffix(degree, negate(*expr)),
+                    state => negate(state),
+                };
+                Expr::new_at(expr, vec![state])
+            }
+            expr => expr,
+        }
+    }
+    fn split_at(expr: Expr) -> Expr {
+        match expr {
+            Expr::BinOp(BinOpExpr {
+                op: BinOpType::Sub,
+                left,
+                right,
+            }) if ends_with_at(&left) => negative_at(*left, *right),
+            Expr::Of { property, expr } => Expr::new_of(&property, split_at(*expr)),
+            expr => match split_at_word(expr, "at") {
+                Ok((expr, state)) => Expr::new_at(expr, vec![state]),
+                Err(expr) => expr,
+            },
+        }
+    }
+    fn states_mut(expr: &mut Expr) -> Option<&mut Vec<Expr>> {
+        match *expr {
+            Expr::At { ref mut states, .. } => Some(states),
+            Expr::Of { ref mut expr, .. } => states_mut(expr),
+            _ => None,
+        }
+    }
+
+    let mut expr = split_at(expr);
+    let has_states = match states_mut(&mut expr) {
+        Some(states) => {
+            while let Some(Token::Comma) = iter.peek() {
+                iter.next();
+                states.push(parse_eq(iter));
+            }
+            true
+        }
+        None => false,
+    };
+    // `activity of 1 g Co60 after 1 year` is the activity after a year,
+    // and `after` binds like `at`, so it can come before or after the
+    // states.
+    fn split_after(expr: Expr) -> Expr {
+        match expr {
+            Expr::Of { property, expr } => Expr::new_of(&property, split_after(*expr)),
+            Expr::At { expr, mut states } => {
+                let expr = split_after(*expr);
+                match states.pop().map(|state| split_at_word(state, "after")) {
+                    Some(Ok((state, time))) => {
+                        states.push(state);
+                        chain_after(Expr::new_at(expr, states), time)
+                    }
+                    Some(Err(state)) => {
+                        states.push(state);
+                        Expr::new_at(expr, states)
+                    }
+                    None => Expr::new_at(expr, states),
+                }
+            }
+            expr => match split_at_word(expr, "after") {
+                Ok((expr, time)) => chain_after(expr, time),
+                Err(expr) => expr,
+            },
+        }
+    }
+    // `Co60 after 1 year after 1 year` decays it twice.
+    fn chain_after(expr: Expr, time: Expr) -> Expr {
+        match split_at_word(time, "after") {
+            Ok((time, rest)) => chain_after(Expr::new_after(expr, time), rest),
+            Err(time) => Expr::new_after(expr, time),
+        }
+    }
+
+    let expr = split_after(expr);
+    if has_states {
+        return expr;
+    }
+    let expr = match split_at_word(expr, "yields") {
+        Ok((expr, Expr::Unit { name })) => Expr::new_yields(expr, &name),
+        Ok(_) => return Expr::new_error("Expected chemical formula after yields".to_owned()),
+        Err(expr) => expr,
+    };
+    let expected = || Expr::new_error("Expected mass, volume or mole after by".to_owned());
+    match split_at_word(expr, "by") {
+        Ok((expr, Expr::Unit { name })) => match MixtureBasis::from_name(&name) {
+            Some(basis) => Expr::new_mixture(expr, basis),
+            None => expected(),
+        },
+        Ok(_) => expected(),
+        Err(expr) => expr,
+    }
+}
+
+fn parse_business_days(iter: &mut Iter<'_>, calendar: Option<String>) -> Query {
+    let (start, end) = match split_at_word(parse_eq(iter), "and") {
+        Ok(split) => split,
+        Err(_) => return Query::Error("Expected `<date> and <date>` after between".to_owned()),
+    };
+    match iter.peek().cloned().unwrap_or(Token::Eof) {
+        Token::Eof => Query::BusinessDays(start, end, calendar),
+        x => Query::Error(format!("Expected eof, got {}", describe(&x))),
+    }
+}
+
+fn parse_recurrence(iter: &mut Iter<'_>) -> Query {
+    let split = split_at_word(parse_eq(iter), "from").and_then(|(step, rest)| {
+        split_at_word(rest, "until").map(|(start, end)| (step, start, end))
+    });
+    let (step, start, end) = match split {
+        Ok(split) => split,
+        Err(_) => {
+            return Query::Error(
+                "Expected `<step> from <date> until <date>` after every".to_owned(),
+            )
+        }
+    };
+    match iter.peek().cloned().unwrap_or(Token::Eof) {
+        Token::Eof => Query::Recurrence(step, start, end),
+        x => Query::Error(format!("Expected eof, got {}", describe(&x))),
+    }
+}
+
+/// Reads one side of a reaction, like `C3H8 + 5 O2`. Coefficients
+/// are skipped, since balancing works them out again.
+fn parse_species(iter: &mut Iter<'_>) -> Result<Vec<String>, String> {
+    let mut species = vec![];
+    loop {
+        if let Some(Token::Decimal(_, None, None)) = iter.peek() {
+            iter.next();
+        }
+        match iter.next().unwrap_or(Token::Eof) {
+            Token::Ident(name) => species.push(name),
+            x => return Err(format!("Expected chemical formula, got {}", describe(&x))),
+        }
+        match iter.peek() {
+            Some(Token::Plus) => {
+                iter.next();
+            }
+            _ => return Ok(species),
+        }
+    }
+}
+
+fn parse_balance(iter: &mut Iter<'_>) -> Query {
+    let reactants = match parse_species(iter) {
+        Ok(species) => species,
+        Err(e) => return Query::Error(e),
+    };
+    match iter.next().unwrap_or(Token::Eof) {
+        Token::DashArrow | Token::Equals => (),
+        x => return Query::Error(format!("Expected `->` or `=`, got {}", describe(&x))),
+    }
+    let products = match parse_species(iter) {
+        Ok(species) => species,
+        Err(e) => return Query::Error(e),
+    };
+    let amount = match iter.peek().cloned().unwrap_or(Token::Eof) {
+        Token::Eof => None,
+        Token::Ident(ref s) if s == "for" => {
+            iter.next();
+            Some(parse_eq(iter))
+        }
+        x => return Query::Error(format!("Expected `for` or eof, got {}", describe(&x))),
+    };
+    match iter.peek().cloned().unwrap_or(Token::Eof) {
+        Token::Eof => Query::Balance(reactants, products, amount),
+        x => Query::Error(format!("Expected eof, got {}", describe(&x))),
+    }
+}
+
+fn is_business_days(name: &str) -> bool {
+    name == "businessdays" || name == "workdays"
+}
+
+/// Consumes `[calendar] businessdays between`, returning the calendar
+/// name if one was given.
+fn business_days_prefix(iter: &mut Iter<'_>) -> Option<Option<String>> {
+    let mut copy = iter.clone();
+    let first = match copy.next() {
+        Some(Token::Ident(name)) => name,
+        _ => return None,
+    };
+    let calendar = if is_business_days(&first) {
+        None
+    } else {
+        match copy.next() {
+            Some(Token::Ident(ref name)) if is_business_days(name) => Some(first),
+            _ => return None,
+        }
+    };
+    match copy.next() {
+        Some(Token::Ident(ref name)) if name == "between" => {
+            *iter = copy;
+            Some(calendar)
+        }
+        _ => None,
+    }
 }
 
 pub fn parse_query(iter: &mut Iter<'_>) -> Query {
+    if let Some(calendar) = business_days_prefix(iter) {
+        return parse_business_days(iter, calendar);
+    }
     match iter.peek().cloned() {
         Some(Token::Ident(ref s)) if s == "factorize" => {
             iter.next();
@@ -747,6 +1103,66 @@ pub fn parse_query(iter: &mut Iter<'_>) -> Query {
             }
             return Query::UnitsFor(parse_eq(iter));
         }
+        Some(Token::Ident(ref s)) if s == "every" => {
+            iter.next();
+            return parse_recurrence(iter);
+        }
+        Some(Token::Ident(ref s)) if s == "datepatterns" => {
+            iter.next();
+            return match (iter.next(), iter.next()) {
+                (Some(Token::Date(tokens)), Some(Token::Eof)) => Query::DatePatterns(tokens),
+                _ => Query::Error("Expected a date literal after datepatterns".to_owned()),
+            };
+        }
+        Some(Token::Ident(ref s)) if s == "composition" => {
+            iter.next();
+            if let Some(Token::Ident(ref s)) = iter.peek().cloned() {
+                if s == "of" {
+                    iter.next();
+                }
+            }
+            return Query::Composition(parse_eq(iter));
+        }
+        Some(Token::Ident(ref s)) if s == "properties" => {
+            iter.next();
+            if let Some(Token::Ident(ref s)) = iter.peek().cloned() {
+                if s == "of" {
+                    iter.next();
+                }
+            }
+            let expr = parse_eq(iter);
+            return Query::Properties(parse_loose_words(expr, iter));
+        }
+        Some(Token::Ident(ref s)) if s == "substances" => {
+            iter.next();
+            if let Some(Token::Ident(ref s)) = iter.peek().cloned() {
+                if s == "with" {
+                    iter.next();
+                }
+            }
+            return match (iter.next(), iter.next()) {
+                (Some(Token::Ident(property)), Some(Token::Eof)) => Query::SubstancesWith(property),
+                _ => Query::Error("Expected a property name after substances with".to_owned()),
+            };
+        }
+        Some(Token::Ident(ref s)) if s == "dilute" => {
+            iter.next();
+            let solution = parse_eq(iter);
+            if !matches!(iter.next(), Some(Token::DashArrow)) {
+                return Query::Error(
+                    "Expected `dilute <volume> of <concentration> to <concentration>`".to_owned(),
+                );
+            }
+            let target = parse_eq(iter);
+            return match iter.peek().cloned().unwrap_or(Token::Eof) {
+                Token::Eof => Query::Dilution(solution, target),
+                x => Query::Error(format!("Expected eof, got {}", describe(&x))),
+            };
+        }
+        Some(Token::Ident(ref s)) if s == "balance" => {
+            iter.next();
+            return parse_balance(iter);
+        }
         Some(Token::Ident(ref s)) if s == "search" => {
             iter.next();
             if let Some(Token::Ident(ref s)) = iter.peek().cloned() {
@@ -756,7 +1172,8 @@ pub fn parse_query(iter: &mut Iter<'_>) -> Query {
         _ => (),
     }
     let left = parse_eq(iter);
-    match iter.peek().cloned().unwrap() {
+    let left = parse_loose_words(left, iter);
+    match iter.peek().cloned().unwrap_or(Token::Eof) {
         Token::DashArrow => {
             use std::str::FromStr;
             iter.next();
@@ -765,7 +1182,7 @@ pub fn parse_query(iter: &mut Iter<'_>) -> Query {
                 *iter = copy;
                 return Query::Convert(left, Conversion::List(res), None, Digits::Default);
             }
-            let digits = match iter.peek().cloned().unwrap() {
+            let digits = match iter.peek().cloned().unwrap_or(Token::Eof) {
                 Token::Ident(ref s) if s == "digits" => {
                     iter.next();
                     match iter.peek().cloned() {
@@ -783,7 +1200,7 @@ pub fn parse_query(iter: &mut Iter<'_>) -> Query {
                 }
                 _ => Digits::Default,
             };
-            let base = match iter.peek().cloned().unwrap() {
+            let base = match iter.peek().cloned().unwrap_or(Token::Eof) {
                 Token::Ident(ref s) if s == "base" => {
                     iter.next();
                     match iter.next() {
@@ -824,7 +1241,7 @@ pub fn parse_query(iter: &mut Iter<'_>) -> Query {
                 }
                 _ => None,
             };
-            let right = match iter.peek().cloned().unwrap() {
+            let right = match iter.peek().cloned().unwrap_or(Token::Eof) {
                 Token::Eof => Conversion::None,
                 Token::Degree(deg) => Conversion::Degree(deg),
                 Token::Plus | Token::Minus => {
@@ -835,9 +1252,50 @@ pub fn parse_query(iter: &mut Iter<'_>) -> Query {
                         Conversion::Expr(parse_eq(&mut old))
                     }
                 }
-                Token::Ident(ref s) if Tz::from_str(s).is_ok() => Conversion::Timezone(
-                    Tz::from_str(s).expect("Running from_str a second time failed"),
-                ),
+                // UT1 follows the Earth's rotation, so converting to it needs
+                // measured DUT1 values, which aren't shipped.
+                Token::Ident(ref s) if s.eq_ignore_ascii_case("ut1") => {
+                    return Query::Error(
+                        "UT1 is not supported, since it depends on measured DUT1 values. \
+                         TAI, TT and GPS time are"
+                            .to_owned(),
+                    )
+                }
+                Token::Ident(ref s) if s == "strftime" => {
+                    iter.next();
+                    match iter.next() {
+                        Some(Token::Quote(pattern)) => {
+                            Conversion::DateFormat(DateFormat::Strftime(pattern))
+                        }
+                        Some(x) => {
+                            return Query::Error(format!(
+                                "Expected quoted strftime pattern, got {}",
+                                describe(&x)
+                            ))
+                        }
+                        None => {
+                            return Query::Error(
+                                "Expected quoted strftime pattern, got eof".to_string(),
+                            )
+                        }
+                    }
+                }
+                Token::Ident(ref s) => match (
+                    DateFormat::from_name(s),
+                    Calendar::from_name(s),
+                    Tz::from_str(s),
+                ) {
+                    (Some(format), _, _) => {
+                        iter.next();
+                        Conversion::DateFormat(format)
+                    }
+                    (None, Some(calendar), _) => {
+                        iter.next();
+                        Conversion::Calendar(calendar)
+                    }
+                    (None, None, Ok(tz)) => Conversion::Timezone(tz),
+                    (None, None, Err(_)) => Conversion::Expr(parse_eq(iter)),
+                },
                 _ => Conversion::Expr(parse_eq(iter)),
             };
             Query::Convert(left, right, base, digits)
@@ -856,7 +1314,8 @@ mod test {
 
     #[test]
     fn add_assoc() {
-        assert_eq!(parse("a + b - c + d - e"), "(((a + b) - c) + d) - e");
+        assert_eq!(parse("a + b - c + d - e"), "a + b - c + d - e");
+        assert_eq!(parse("a - (b - c)"), "a - (b - c)");
     }
 
     #[test]
@@ -868,10 +1327,11 @@ mod test {
     fn mul_assoc() {
         assert_eq!(
             parse("a b * c / d / e f * g h"),
-            "(((a b) c / d) / e f) (g h)"
+            "((a b) c / d / e f) (g h)"
         );
         assert_eq!(parse("a|b c / g e|f"), "(a / b) c / g (e / f)");
-        assert_eq!(parse("a / b / c"), "(a / b) / c");
+        assert_eq!(parse("a / b / c"), "a / b / c");
+        assert_eq!(parse("a / (b / c)"), "a / (b / c)");
     }
 
     #[test]
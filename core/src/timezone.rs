@@ -0,0 +1,225 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Timezone abbreviations like `PDT` and city names like `Tokyo`,
+//! which can be used in date literals and after `->` alongside IANA
+//! names. See `timezones.txt`.
+
+use crate::reply::TimezoneError;
+use chrono::FixedOffset;
+use chrono_tz::Tz;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zone {
+    /// A fixed offset from UTC, used for abbreviations like `PDT`
+    /// which already say whether daylight saving time is in effect.
+    Offset(FixedOffset),
+    /// An IANA timezone, used for places.
+    Named(Tz),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimezoneAlias {
+    pub name: String,
+    pub zone: Zone,
+    pub description: String,
+}
+
+/// Aliases keyed by their normalized name. A name with several entries
+/// is ambiguous, like `IST`.
+#[derive(Debug, Clone, Default)]
+pub struct TimezoneAliases {
+    aliases: BTreeMap<String, Vec<TimezoneAlias>>,
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Zone::Offset(ref offset) => write!(fmt, "{}", offset),
+            Zone::Named(ref tz) => write!(fmt, "{}", tz.name()),
+        }
+    }
+}
+
+impl fmt::Display for TimezoneAlias {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.description.is_empty() {
+            write!(fmt, "{}", self.zone)
+        } else {
+            write!(fmt, "{} ({})", self.zone, self.description)
+        }
+    }
+}
+
+/// Names are matched case insensitively, and spaces and underscores
+/// are interchangeable so that `New York` and `new_york` both work.
+fn normalize(name: &str) -> String {
+    name.split([' ', '_'])
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+        .to_lowercase()
+}
+
+impl TimezoneAliases {
+    pub fn insert(&mut self, alias: TimezoneAlias) {
+        let entries = self.aliases.entry(normalize(&alias.name)).or_default();
+        if !entries.contains(&alias) {
+            entries.push(alias);
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.aliases.contains_key(&normalize(name))
+    }
+
+    /// Looks up an IANA name, then an alias. Returns None if the name
+    /// is unknown, and an error listing the candidates if it's
+    /// ambiguous.
+    pub fn resolve(&self, name: &str) -> Option<Result<Zone, String>> {
+        if let Ok(tz) = Tz::from_str(name) {
+            return Some(Ok(Zone::Named(tz)));
+        }
+        match &self.aliases.get(&normalize(name))?[..] {
+            [] => None,
+            [alias] => Some(Ok(alias.zone)),
+            candidates => Some(Err(format!(
+                "Timezone {} is ambiguous, it could be: {}. Use a UTC offset or \
+                 an IANA name instead",
+                name,
+                candidates
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
+        }
+    }
+}
+
+fn parse_offset(offset: &str) -> Option<FixedOffset> {
+    let (sign, rest) = match offset.as_bytes().first()? {
+        b'+' => (1, &offset[1..]),
+        b'-' => (-1, &offset[1..]),
+        _ => return None,
+    };
+    let mut parts = rest.split(':');
+    let hours = parts.next()?;
+    let minutes = parts.next()?;
+    if hours.len() != 2 || minutes.len() != 2 || parts.next().is_some() {
+        return None;
+    }
+    let hours = hours.parse::<i32>().ok()?;
+    let minutes = minutes.parse::<i32>().ok()?;
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Parses the format of `timezones.txt`: a name, then either an offset
+/// like `-07:00` or an IANA timezone, then an optional description.
+/// Lines that can't be parsed are skipped and returned as errors.
+pub fn parse_timezones(file: &str) -> (Vec<TimezoneAlias>, Vec<TimezoneError>) {
+    let mut aliases = vec![];
+    let mut errors = vec![];
+    for (num, line) in file.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap();
+        let zone = words.next().and_then(|zone| {
+            parse_offset(zone)
+                .map(Zone::Offset)
+                .or_else(|| Tz::from_str(zone).ok().map(Zone::Named))
+        });
+        match zone {
+            Some(zone) => aliases.push(TimezoneAlias {
+                name: name.to_owned(),
+                zone,
+                description: words.collect::<Vec<_>>().join(" "),
+            }),
+            None => errors.push(TimezoneError {
+                line: num + 1,
+                text: line.to_owned(),
+                message: "Expected a name followed by an offset or IANA timezone".to_owned(),
+            }),
+        }
+    }
+    (aliases, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases() -> TimezoneAliases {
+        let mut aliases = TimezoneAliases::default();
+        for alias in parse_timezones(crate::TIMEZONES_FILE).0 {
+            aliases.insert(alias);
+        }
+        aliases
+    }
+
+    #[test]
+    fn test_parse_timezones() {
+        let (parsed, errors) = parse_timezones(
+            "PDT -07:00 Pacific Daylight Time\n\
+             New_York America/New_York # comment\n\
+             bad\n\
+             XYZ +5:00\n",
+        );
+        assert_eq!(
+            parsed,
+            vec![
+                TimezoneAlias {
+                    name: "PDT".to_owned(),
+                    zone: Zone::Offset(FixedOffset::west(7 * 3600)),
+                    description: "Pacific Daylight Time".to_owned(),
+                },
+                TimezoneAlias {
+                    name: "New_York".to_owned(),
+                    zone: Zone::Named(Tz::America__New_York),
+                    description: "".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(
+            errors.iter().map(|error| error.line).collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "Line 3: Expected a name followed by an offset or IANA timezone: bad"
+        );
+        assert!(parse_timezones(crate::TIMEZONES_FILE).1.is_empty());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let aliases = aliases();
+        assert_eq!(
+            aliases.resolve("JST"),
+            Some(Ok(Zone::Offset(FixedOffset::east(9 * 3600))))
+        );
+        assert_eq!(
+            aliases.resolve("new york"),
+            Some(Ok(Zone::Named(Tz::America__New_York)))
+        );
+        assert_eq!(
+            aliases.resolve("Europe/Paris"),
+            Some(Ok(Zone::Named(Tz::Europe__Paris)))
+        );
+        assert_eq!(aliases.resolve("Atlantis"), None);
+        let err = aliases.resolve("IST").unwrap().unwrap_err();
+        assert!(err.contains("+05:30 (India Standard Time)"), "{}", err);
+        assert!(err.contains("+02:00 (Israel Standard Time)"), "{}", err);
+    }
+}
@@ -2,10 +2,14 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::business::{self, BusinessCalendar};
 use crate::context::Context;
 use crate::date;
-use crate::date::GenericDateTime;
+use crate::date::{GenericDateTime, Interval};
+use crate::limits::LimitExceeded;
 use crate::number::Number;
+use crate::numeric::Numeric;
+use crate::reply::{OperationError, QueryError};
 use crate::substance::Substance;
 use chrono::{DateTime, FixedOffset};
 use chrono_tz::Tz;
@@ -15,6 +19,7 @@ use std::ops::{Add, Div, Mul, Neg, Sub};
 pub enum Value {
     Number(Number),
     DateTime(date::GenericDateTime),
+    Interval(Interval),
     Substance(Substance),
 }
 
@@ -52,79 +57,173 @@ impl Show for GenericDateTime {
     }
 }
 
+impl Show for Interval {
+    fn show(&self, context: &Context) -> String {
+        format!("{} .. {}", self.start.show(context), self.end.show(context))
+    }
+}
+
 impl Show for Value {
     fn show(&self, context: &Context) -> String {
         match *self {
             Value::Number(ref num) => num.show(context),
             Value::DateTime(ref dt) => dt.show(context),
+            Value::Interval(ref interval) => interval.show(context),
             Value::Substance(ref v) => v.show(context),
         }
     }
 }
 
 impl Value {
-    pub fn pow(&self, exp: &Value) -> Result<Value, String> {
+    pub fn pow(&self, exp: &Value) -> Result<Value, QueryError> {
         match (self, exp) {
-            (&Value::Number(ref left), &Value::Number(ref right)) => {
-                left.pow(right).map(Value::Number)
+            (&Value::Number(ref left), &Value::Number(ref right)) => left
+                .pow(right)
+                .map(Value::Number)
+                .map_err(|message| operation_error("^", message)),
+            (_, _) => Err(not_defined("^")),
+        }
+    }
+
+    /// Creates the interval `self .. end`.
+    pub fn range(&self, end: &Value) -> Result<Value, QueryError> {
+        match (self, end) {
+            (&Value::DateTime(ref start), &Value::DateTime(ref end)) => Interval::new(*start, *end)
+                .map(Value::Interval)
+                .map_err(|message| operation_error("..", message)),
+            (_, _) => Err(operation_error(
+                "..",
+                "Intervals must start and end with dates".to_string(),
+            )),
+        }
+    }
+
+    /// Moves a date by whole business days, skipping the weekends and
+    /// holidays of `calendar`. `step` is called for each day walked.
+    pub fn add_business_days(
+        &self,
+        days: i64,
+        calendar: &BusinessCalendar,
+        step: &dyn Fn() -> Result<(), LimitExceeded>,
+    ) -> Result<Value, QueryError> {
+        match *self {
+            Value::DateTime(ref date) => {
+                business::add_business_days(date, days, calendar, step).map(Value::DateTime)
             }
-            (_, _) => Err("Operation is not defined".to_string()),
+            _ => Err(QueryError::generic(
+                "Business days can only be added to dates".to_string(),
+            )),
         }
     }
+
+    /// Number of business days from this date to `other`, see
+    /// `BusinessCalendar::business_days_between`.
+    pub fn business_days_between(
+        &self,
+        other: &Value,
+        calendar: &BusinessCalendar,
+        step: &dyn Fn() -> Result<(), LimitExceeded>,
+    ) -> Result<Number, QueryError> {
+        match (self, other) {
+            (&Value::DateTime(ref left), &Value::DateTime(ref right)) => {
+                business::business_days_between(left, right, calendar, step)
+                    .map(|days| Number::new(Numeric::from(days)))
+            }
+            (_, _) => Err(QueryError::generic(
+                "Business days can only be counted between dates".to_string(),
+            )),
+        }
+    }
+}
+
+/// An error from applying `operator` to values. The operands are left
+/// empty, since showing them needs a context; `Context::eval` fills
+/// them in.
+pub(crate) fn operation_error(operator: &str, message: String) -> QueryError {
+    QueryError::Operation(OperationError {
+        message,
+        operator: operator.to_owned(),
+        operands: vec![],
+    })
+}
+
+fn not_defined(operator: &str) -> QueryError {
+    operation_error(operator, "Operation is not defined".to_string())
 }
 
 impl<'a, 'b> Add<&'b Value> for &'a Value {
-    type Output = Result<Value, String>;
+    type Output = Result<Value, QueryError>;
 
-    fn add(self, other: &Value) -> Result<Value, String> {
+    fn add(self, other: &Value) -> Result<Value, QueryError> {
         match (self, other) {
             (&Value::Number(ref left), &Value::Number(ref right)) => (left + right)
                 .ok_or_else(|| {
-                    "Addition of units with mismatched units is not meaningful".to_string()
+                    operation_error(
+                        "+",
+                        "Addition of units with mismatched units is not meaningful".to_string(),
+                    )
                 })
                 .map(Value::Number),
             (&Value::DateTime(ref left), &Value::Number(ref right))
             | (&Value::Number(ref right), &Value::DateTime(ref left)) => match *left {
                 GenericDateTime::Fixed(left) => left
-                    .checked_add_signed(date::to_duration(right)?)
+                    .checked_add_signed(to_duration("+", right)?)
                     .map(GenericDateTime::Fixed),
                 GenericDateTime::Timezone(left) => left
-                    .checked_add_signed(date::to_duration(right)?)
+                    .checked_add_signed(to_duration("+", right)?)
                     .map(GenericDateTime::Timezone),
             }
             .ok_or_else(|| {
-                "Implementation error: value is out of range representable by datetime".to_string()
+                operation_error(
+                    "+",
+                    "Implementation error: value is out of range representable by datetime"
+                        .to_string(),
+                )
             })
             .map(Value::DateTime),
-            (&Value::Substance(ref left), &Value::Substance(ref right)) => {
-                left.add(right).map(Value::Substance)
+            (&Value::Interval(ref interval), &Value::Number(_)) => {
+                shift_interval(interval, "+", |date| date + other)
             }
-            (_, _) => Err("Operation is not defined".to_string()),
+            (&Value::Number(_), &Value::Interval(ref interval)) => {
+                shift_interval(interval, "+", |date| date + self)
+            }
+            (&Value::Substance(ref left), &Value::Substance(ref right)) => left
+                .add(right)
+                .map(Value::Substance)
+                .map_err(|message| operation_error("+", message)),
+            (_, _) => Err(not_defined("+")),
         }
     }
 }
 
 impl<'a, 'b> Sub<&'b Value> for &'a Value {
-    type Output = Result<Value, String>;
+    type Output = Result<Value, QueryError>;
 
-    fn sub(self, other: &Value) -> Result<Value, String> {
+    fn sub(self, other: &Value) -> Result<Value, QueryError> {
         match (self, other) {
             (&Value::Number(ref left), &Value::Number(ref right)) => (left - right)
                 .ok_or_else(|| {
-                    "Subtraction of units with mismatched units is not meaningful".to_string()
+                    operation_error(
+                        "-",
+                        "Subtraction of units with mismatched units is not meaningful".to_string(),
+                    )
                 })
                 .map(Value::Number),
             (&Value::DateTime(ref left), &Value::Number(ref right))
             | (&Value::Number(ref right), &Value::DateTime(ref left)) => match *left {
                 GenericDateTime::Fixed(left) => left
-                    .checked_sub_signed(date::to_duration(right)?)
+                    .checked_sub_signed(to_duration("-", right)?)
                     .map(GenericDateTime::Fixed),
                 GenericDateTime::Timezone(left) => left
-                    .checked_sub_signed(date::to_duration(right)?)
+                    .checked_sub_signed(to_duration("-", right)?)
                     .map(GenericDateTime::Timezone),
             }
             .ok_or_else(|| {
-                "Implementation error: value is out of range representable by datetime".to_string()
+                operation_error(
+                    "-",
+                    "Implementation error: value is out of range representable by datetime"
+                        .to_string(),
+                )
             })
             .map(Value::DateTime),
             (&Value::DateTime(ref left), &Value::DateTime(ref right)) => {
@@ -144,54 +243,80 @@ impl<'a, 'b> Sub<&'b Value> for &'a Value {
                     }
                 })
                 .map(Value::Number)
+                .map_err(|message| operation_error("-", message))
+            }
+            (&Value::Interval(ref interval), &Value::Number(_)) => {
+                shift_interval(interval, "-", |date| date - other)
             }
-            (_, _) => Err("Operation is not defined".to_string()),
+            (_, _) => Err(not_defined("-")),
         }
     }
 }
 
+fn to_duration(operator: &str, number: &Number) -> Result<chrono::Duration, QueryError> {
+    date::to_duration(number).map_err(|message| operation_error(operator, message))
+}
+
+/// Moves both ends of an interval, for `interval + duration`.
+fn shift_interval<F>(interval: &Interval, operator: &str, shift: F) -> Result<Value, QueryError>
+where
+    F: Fn(&Value) -> Result<Value, QueryError>,
+{
+    match (
+        shift(&Value::DateTime(interval.start))?,
+        shift(&Value::DateTime(interval.end))?,
+    ) {
+        (Value::DateTime(start), Value::DateTime(end)) => Interval::new(start, end)
+            .map(Value::Interval)
+            .map_err(|message| operation_error(operator, message)),
+        _ => Err(QueryError::generic(
+            "Bug: Shifting a date should give a date".to_string(),
+        )),
+    }
+}
+
 impl<'a> Neg for &'a Value {
-    type Output = Result<Value, String>;
+    type Output = Result<Value, QueryError>;
 
     fn neg(self) -> Self::Output {
         match *self {
             Value::Number(ref num) => (-num)
-                .ok_or_else(|| "Bug: Negation should not fail".to_string())
+                .ok_or_else(|| operation_error("-", "Bug: Negation should not fail".to_string()))
                 .map(Value::Number),
-            _ => Err("Operation is not defined".to_string()),
+            _ => Err(not_defined("-")),
         }
     }
 }
 
 impl<'a, 'b> Mul<&'b Value> for &'a Value {
-    type Output = Result<Value, String>;
+    type Output = Result<Value, QueryError>;
 
-    fn mul(self, other: &Value) -> Result<Value, String> {
+    fn mul(self, other: &Value) -> Result<Value, QueryError> {
         match (self, other) {
             (&Value::Number(ref left), &Value::Number(ref right)) => (left * right)
-                .ok_or_else(|| "Bug: Mul should not fail".to_string())
+                .ok_or_else(|| operation_error("*", "Bug: Mul should not fail".to_string()))
                 .map(Value::Number),
             (&Value::Number(ref co), &Value::Substance(ref sub))
-            | (&Value::Substance(ref sub), &Value::Number(ref co)) => {
-                (sub * co).map(Value::Substance)
-            }
-            (_, _) => Err("Operation is not defined".to_string()),
+            | (&Value::Substance(ref sub), &Value::Number(ref co)) => (sub * co)
+                .map(Value::Substance)
+                .map_err(|message| operation_error("*", message)),
+            (_, _) => Err(not_defined("*")),
         }
     }
 }
 
 impl<'a, 'b> Div<&'b Value> for &'a Value {
-    type Output = Result<Value, String>;
+    type Output = Result<Value, QueryError>;
 
-    fn div(self, other: &Value) -> Result<Value, String> {
+    fn div(self, other: &Value) -> Result<Value, QueryError> {
         match (self, other) {
             (&Value::Number(ref left), &Value::Number(ref right)) => (left / right)
-                .ok_or_else(|| "Division by zero".to_string())
+                .ok_or_else(|| operation_error("/", "Division by zero".to_string()))
                 .map(Value::Number),
-            (&Value::Substance(ref sub), &Value::Number(ref co)) => {
-                (sub / co).map(Value::Substance)
-            }
-            (_, _) => Err("Operation is not defined".to_string()),
+            (&Value::Substance(ref sub), &Value::Number(ref co)) => (sub / co)
+                .map(Value::Substance)
+                .map_err(|message| operation_error("/", message)),
+            (_, _) => Err(not_defined("/")),
         }
     }
 }
@@ -3,7 +3,9 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use assert_json_diff::assert_json_eq;
-use rink_core::ast::{Def, DefEntry, Expr, ExprString, Property, Query};
+use rink_core::ast::{Def, DefEntry, Expr, ExprString, Property, PropertyTable, Query, TablePoint};
+use rink_core::reply::{QueryError, QueryReply};
+use rink_core::schema::Versioned;
 use rink_core::*;
 use serde_json;
 use serde_json::{json, to_value};
@@ -156,6 +158,15 @@ fn check_defs() {
                     output: ExprString(expr("cm^3")),
                     output_name: "volume".to_owned(),
                 }],
+                tables: vec![PropertyTable {
+                    property: "density".to_owned(),
+                    variable: "temperature".to_owned(),
+                    points: vec![TablePoint {
+                        state: ExprString(expr("20 °C")),
+                        value: ExprString(expr("0.99821 g/cm^3")),
+                    }],
+                    doc: None,
+                }],
             }
         ))
         .unwrap(),
@@ -172,7 +183,158 @@ fn check_defs() {
                 "inputName": "mass",
                 "output": "cm^3",
                 "outputName": "volume"
+            }],
+            "tables": [{
+                "property": "density",
+                "variable": "temperature",
+                "points": [{"state": "20 °C", "value": "0.99821 g / cm^3"}],
+                "doc": null
             }]
         })
     );
 }
+
+fn roundtrip_query(input: &str) {
+    let query = query(input);
+    let json = serde_json::to_string(&Versioned::new(query.clone())).unwrap();
+    let back: Versioned<Query> = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.into_inner(), query, "{} serialized as {}", input, json);
+}
+
+#[test]
+fn check_query_roundtrip() {
+    roundtrip_query("1 + 2");
+    roundtrip_query("-3 m^2 / s");
+    roundtrip_query("0.1234567891 kg");
+    roundtrip_query("10 °C + 5 °F -> °Re");
+    roundtrip_query("sqrt(2) * log(3, 4)");
+    roundtrip_query("density of water");
+    roundtrip_query("'a quote' = \"weird name\"");
+    roundtrip_query("#2020-01-01 12:00:00 +05:00#");
+    roundtrip_query("now -> \"Europe/London\"");
+    roundtrip_query("now -> +05:30");
+    roundtrip_query("now -> isoweek");
+    roundtrip_query("now -> strftime '%d %B'");
+    roundtrip_query("now -> hebrew");
+    roundtrip_query("1 m -> ft, in");
+    roundtrip_query("pi -> digits 20");
+    roundtrip_query("255 -> hex");
+    roundtrip_query("factorize J");
+    roundtrip_query("units for power");
+    roundtrip_query("search horse");
+    roundtrip_query("us businessdays between #2024-03-01# and now");
+    roundtrip_query("#2024-01-01# .. #2024-03-31# -> days");
+    roundtrip_query("every 2 weeks from #2024-01-01# until #2024-03-01#");
+    roundtrip_query("datepatterns #2024-03-01T12:00 +02:00#");
+    roundtrip_query("composition of 10 g C3H8");
+    roundtrip_query("density of (40% ethanol + 60% water by volume)");
+    roundtrip_query("dilute 100 mL of 2 M NaCl to 0.5 M");
+    roundtrip_query("properties of water");
+    roundtrip_query("substances with density");
+    roundtrip_query("density of R134a at -30 °C");
+    roundtrip_query("water at 300 K, 1 atm");
+    roundtrip_query("1 mCi Co60 after 3 years -> Bq");
+    roundtrip_query("balance C3H8 + O2 -> CO2 + H2O");
+    roundtrip_query("balance H2 + O2 = H2O for 2 g H2");
+    roundtrip_query("10 g C3H8 yields CO2 -> g");
+    roundtrip_query("1 +");
+}
+
+#[test]
+fn check_unaryop() {
+    assert_json_eq!(
+        to_value(expr("-a")).unwrap(),
+        json!({
+            "type": "unaryop",
+            "op": "negative",
+            "expr": { "type": "unit", "name": "a" }
+        })
+    );
+    assert_json_eq!(
+        to_value(expr("a °C")).unwrap(),
+        json!({
+            "type": "unaryop",
+            "op": "celsius",
+            "expr": {
+                "type": "mul",
+                "exprs": [{ "type": "unit", "name": "a" }]
+            }
+        })
+    );
+}
+
+#[test]
+fn check_timezone() {
+    assert_json_eq!(
+        to_value(query("now -> \"Europe/London\"")).unwrap(),
+        json!({
+            "type": "convert",
+            "value": [
+                { "type": "unit", "name": "now" },
+                { "Timezone": "Europe/London" },
+                null,
+                "Default"
+            ]
+        })
+    );
+}
+
+#[test]
+fn check_numeric() {
+    let value: Expr = serde_json::from_value(json!({
+        "type": "const",
+        "value": { "numer": "-3", "denom": "6" }
+    }))
+    .unwrap();
+    assert_eq!(value.to_string(), "-0.5");
+
+    let nan: Expr = serde_json::from_value(json!({
+        "type": "const",
+        "value": { "numer": "NaN", "denom": "1" }
+    }))
+    .unwrap();
+    assert_eq!(nan.to_string(), "NaN");
+
+    let err = serde_json::from_value::<Expr>(json!({
+        "type": "const",
+        "value": { "numer": "1", "denom": "0" }
+    }))
+    .unwrap_err();
+    assert_eq!(err.to_string(), "Denominator must not be zero");
+}
+
+fn roundtrip_reply(ctx: &Context, input: &str) {
+    let reply = ctx.eval_outer(&query(input));
+    let json = to_value(Versioned::new(reply)).unwrap();
+    let back: Versioned<Result<QueryReply, QueryError>> =
+        serde_json::from_value(json.clone()).unwrap();
+    assert_json_eq!(to_value(back).unwrap(), json);
+}
+
+#[test]
+fn check_reply_roundtrip() {
+    let mut ctx = simple_context().unwrap();
+    ctx.use_humanize = false;
+    roundtrip_reply(&ctx, "kWh / year -> W");
+    roundtrip_reply(&ctx, "#2020-01-01#");
+    roundtrip_reply(&ctx, "water");
+    roundtrip_reply(&ctx, "#2020-02-01# - #2020-01-01#");
+    roundtrip_reply(&ctx, "foot");
+    roundtrip_reply(&ctx, "1 m -> ft, in");
+    roundtrip_reply(&ctx, "factorize J");
+    roundtrip_reply(&ctx, "units for power");
+    roundtrip_reply(&ctx, "search horse");
+    roundtrip_reply(&ctx, "m + s");
+    roundtrip_reply(&ctx, "nonexistentunitname");
+    roundtrip_reply(&ctx, "1 +");
+}
+
+#[test]
+fn check_version() {
+    let err = serde_json::from_value::<Versioned<Query>>(json!({
+        "version": 0,
+        "value": { "type": "search", "value": "horse" }
+    }))
+    .unwrap_err();
+    assert_eq!(err.to_string(), "Unsupported schema version 0, expected 1");
+}
@@ -0,0 +1,89 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Runs the fuzz target properties over the checked in corpus. Inputs
+//! found by `cargo fuzz` that broke a property should be minimized
+//! with `cargo fuzz tmin` and added to `tests/corpus/<target>/`.
+
+mod roundtrip;
+
+use rink_core::text_query;
+use std::fs;
+use std::path::Path;
+
+fn corpus(target: &str) -> Vec<(String, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/corpus")
+        .join(target);
+    let mut inputs = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let bytes = fs::read(&path).unwrap();
+            (
+                path.display().to_string(),
+                String::from_utf8_lossy(&bytes).into_owned(),
+            )
+        })
+        .collect::<Vec<_>>();
+    inputs.sort();
+    assert!(!inputs.is_empty(), "{} is empty", dir.display());
+    inputs
+}
+
+#[test]
+fn test_text_query_corpus() {
+    for (_path, input) in corpus("text_query") {
+        roundtrip::check_text_query(&input);
+    }
+}
+
+#[test]
+fn test_gnu_units_corpus() {
+    for (_path, input) in corpus("gnu_units") {
+        roundtrip::check_gnu_units(&input);
+    }
+}
+
+#[test]
+fn test_date_pattern_corpus() {
+    for (_path, input) in corpus("date_pattern") {
+        roundtrip::check_date_pattern(&input);
+    }
+    for line in rink_core::DATES_FILE.lines() {
+        roundtrip::check_date_pattern(line);
+    }
+}
+
+#[test]
+fn test_formula_corpus() {
+    let ctx = roundtrip::sandbox_context();
+    for (_path, input) in corpus("formula") {
+        roundtrip::check_formula(&ctx, &input);
+    }
+}
+
+fn display(input: &str) -> String {
+    let mut iter = text_query::TokenIterator::new(input).peekable();
+    text_query::parse_expr(&mut iter).to_string()
+}
+
+#[test]
+fn test_display() {
+    assert_eq!(display("a / (b / c)"), "a / (b / c)");
+    assert_eq!(display("(a / b) / c"), "a / b / c");
+    assert_eq!(display("a - (b - c)"), "a - (b - c)");
+    assert_eq!(display("(a^b)^c"), "(a^b)^c");
+    assert_eq!(display("a^b^c"), "a^b^c");
+    assert_eq!(display("a*-b"), "a (-b)");
+    assert_eq!(display("% of"), "percent (of)");
+    assert_eq!(display("b of a | c"), "b of (a / c)");
+    assert_eq!(display("0.1234567891"), "1234567891e-10");
+    assert_eq!(display("0.125"), "0.125");
+    assert_eq!(display("1230000000000000000000"), "1.23e21");
+    assert_eq!(display("1234567800000000000000"), "12345678e14");
+    assert_eq!(display("'it\\'s'"), "'it\\'s'");
+    assert_eq!(display("\"foo bar\""), "\"foo bar\"");
+    assert_eq!(display("#2020-01-01#"), "#2020-01-01#");
+}
@@ -0,0 +1,150 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use rink_core::number::Dimension;
+use rink_core::reply::{DateError, OperationError, ParseError};
+use rink_core::*;
+use std::cell::RefCell;
+use std::error::Error;
+
+thread_local! {
+    static CONTEXT: RefCell<Context> = {
+        let mut ctx = simple_context().unwrap();
+        ctx.use_humanize = false;
+        RefCell::new(ctx)
+    };
+}
+
+fn eval(input: &str) -> Result<QueryReply, QueryError> {
+    CONTEXT.with(|ctx| eval_line(&mut ctx.borrow_mut(), input))
+}
+
+fn error(input: &str) -> QueryError {
+    match eval(input) {
+        Ok(reply) => panic!("expected {:?} to fail, got {}", input, reply),
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn test_parse_error() {
+    match error("(") {
+        QueryError::Parse(ParseError { message }) => {
+            assert_eq!(message, "Expected `)`, got eof")
+        }
+        err => panic!("{:?}", err),
+    }
+    match error("1 -> base") {
+        QueryError::Parse(_) => (),
+        err => panic!("{:?}", err),
+    }
+}
+
+#[test]
+fn test_date_error() {
+    match error("#foo#") {
+        QueryError::InvalidDate(DateError { input, message }) => {
+            assert_eq!(input, "foo");
+            assert!(message.starts_with("Most likely pattern"), "{}", message);
+        }
+        err => panic!("{:?}", err),
+    }
+}
+
+#[test]
+fn test_operation_error() {
+    let err = error("m + s");
+    assert_eq!(
+        err.to_string(),
+        "Addition of units with mismatched units is not meaningful: \
+         <1 meter (length)> + <1 second (time)>"
+    );
+    match err {
+        QueryError::Operation(OperationError {
+            operator, operands, ..
+        }) => {
+            assert_eq!(operator, "+");
+            assert_eq!(operands, ["1 meter (length)", "1 second (time)"]);
+        }
+        err => panic!("{:?}", err),
+    }
+
+    match error("-now") {
+        QueryError::Operation(OperationError {
+            operator, operands, ..
+        }) => {
+            assert_eq!(operator, "-");
+            assert_eq!(operands.len(), 1);
+        }
+        err => panic!("{:?}", err),
+    }
+
+    match error("1 L of 2 M NaCl -> g KCl") {
+        QueryError::Operation(OperationError { operator, .. }) => assert_eq!(operator, "->"),
+        err => panic!("{:?}", err),
+    }
+
+    // Operators on values leave the operands to the context, which
+    // knows how to show them.
+    let meter = Value::Number(Number::one_unit(Dimension::new("m")));
+    let second = Value::Number(Number::one_unit(Dimension::new("s")));
+    match &meter - &second {
+        Err(QueryError::Operation(OperationError {
+            operator, operands, ..
+        })) => {
+            assert_eq!(operator, "-");
+            assert!(operands.is_empty());
+        }
+        res => panic!("{:?}", res),
+    }
+}
+
+#[test]
+fn test_not_found_error() {
+    match error("meterr") {
+        QueryError::NotFound(err) => {
+            assert_eq!(err.got, "meterr");
+            assert_eq!(err.suggestion.as_deref(), Some("meter"));
+        }
+        err => panic!("{:?}", err),
+    }
+}
+
+#[test]
+fn test_conformance_error() {
+    match error("m -> s") {
+        QueryError::Conformance(err) => assert_eq!(err.left.quantity.as_deref(), Some("length")),
+        err => panic!("{:?}", err),
+    }
+}
+
+#[test]
+fn test_source() {
+    // The variants display as what they wrap, which isn't repeated as a
+    // source.
+    let err = error("meterr");
+    assert!(err.source().is_none());
+    assert!(err.to_string().starts_with("No such unit meterr"));
+
+    let err = QueryError::generic("message".to_owned());
+    assert!(err.source().is_none());
+    assert_eq!(err.to_string(), "message");
+
+    // Works as a boxed error with `?`.
+    fn boxed() -> Result<QueryReply, Box<dyn Error>> {
+        Ok(eval("(")?)
+    }
+    assert_eq!(boxed().unwrap_err().to_string(), "Expected `)`, got eof");
+}
+
+#[test]
+fn test_one_line_matches_eval_line() {
+    for input in &["(", "#foo#", "m + s", "meterr", "m -> s", "2 m"] {
+        let text = match eval(input) {
+            Ok(reply) => Ok(reply.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        CONTEXT.with(|ctx| assert_eq!(one_line(&mut ctx.borrow_mut(), input), text));
+    }
+}
@@ -0,0 +1,189 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use rink_core::limits::{LimitExceeded, Limits};
+use rink_core::reply::QueryError;
+use rink_core::*;
+use serde_json::{json, to_value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+thread_local! {
+    static CONTEXT: Context = {
+        let mut ctx = simple_context().unwrap();
+        ctx.use_humanize = false;
+        ctx.limits = Limits {
+            timeout: None,
+            ..Limits::sandbox()
+        };
+        ctx
+    };
+}
+
+fn eval(input: &str) -> Result<String, QueryError> {
+    let mut iter = text_query::TokenIterator::new(input.trim()).peekable();
+    let expr = text_query::parse_query(&mut iter);
+    CONTEXT.with(|ctx| ctx.eval_outer(&expr).map(|v| v.to_string()))
+}
+
+fn limit(input: &str) -> LimitExceeded {
+    match eval(input) {
+        Err(QueryError::LimitExceeded(limit)) => limit,
+        res => panic!("expected {} to exceed a limit, got {:?}", input, res),
+    }
+}
+
+#[test]
+fn test_within_limits() {
+    assert_eq!(
+        eval("2^1000 -> hex").unwrap(),
+        format!("1{} (dimensionless)", "0".repeat(250))
+    );
+    assert_eq!(eval("1^1000000000").unwrap(), "1 (dimensionless)");
+    assert_eq!(
+        eval("kWh / year -> W").unwrap(),
+        "approx. 0.1140795 watt (power)"
+    );
+    assert_eq!(eval("1e-30000").unwrap(), "1.0e-30000 (dimensionless)");
+}
+
+#[test]
+fn test_bigint_size() {
+    assert_eq!(
+        limit("2^2^2^2^2^2"),
+        LimitExceeded::BigIntSize {
+            max_bits: 1 << 17,
+            bits: u64::MAX,
+        }
+    );
+    assert!(matches!(
+        limit("10^(2^30)"),
+        LimitExceeded::BigIntSize { .. }
+    ));
+    assert!(matches!(
+        limit("1e300000"),
+        LimitExceeded::BigIntSize { .. }
+    ));
+    assert!(matches!(
+        limit("(3 m)^100000"),
+        LimitExceeded::BigIntSize { .. }
+    ));
+}
+
+#[test]
+fn test_output_length() {
+    assert_eq!(
+        limit("pi -> digits 100000000"),
+        LimitExceeded::OutputLength {
+            max: 10_000,
+            len: 100_000_000,
+        }
+    );
+    assert!(matches!(
+        limit("2^100000 -> base 2"),
+        LimitExceeded::OutputLength { .. }
+    ));
+}
+
+#[test]
+fn test_steps() {
+    assert_eq!(
+        limit("factorize kg m^5 / s^7 A^3 K"),
+        LimitExceeded::Steps { max: 100_000 }
+    );
+    // Each day walked is a step.
+    assert_eq!(
+        limit("#2024-03-01# + 1e9 businessdays"),
+        LimitExceeded::Steps { max: 100_000 }
+    );
+    assert_eq!(
+        limit("businessdays between #2024-03-01# and #2824-03-01#"),
+        LimitExceeded::Steps { max: 100_000 }
+    );
+}
+
+#[test]
+fn test_limits_reset_per_query() {
+    limit("factorize kg m^5 / s^7 A^3 K");
+    assert_eq!(eval("1 + 2").unwrap(), "3 (dimensionless)");
+}
+
+#[test]
+fn test_timeout() {
+    let mut ctx = simple_context().unwrap();
+    ctx.limits = Limits {
+        timeout: Some(Duration::from_millis(0)),
+        ..Limits::default()
+    };
+    assert_eq!(
+        one_line(&mut ctx, "factorize kg m^5 / s^7 A^3 K"),
+        Err("Evaluation exceeded the time limit of 0ms".to_owned())
+    );
+}
+
+#[test]
+fn test_cancel() {
+    let mut ctx = simple_context().unwrap();
+    let token = ctx.cancel_token();
+    let query = text_query::parse_query(
+        &mut text_query::TokenIterator::new("factorize kg m^5 / s^7 A^3 K").peekable(),
+    );
+    let done = Arc::new(AtomicBool::new(false));
+    let watchdog = {
+        let token = token.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            while !done.load(Ordering::SeqCst) {
+                token.cancel();
+                thread::yield_now();
+            }
+        })
+    };
+    assert!(matches!(
+        ctx.eval_outer(&query),
+        Err(QueryError::LimitExceeded(LimitExceeded::Cancelled))
+    ));
+    done.store(true, Ordering::SeqCst);
+    watchdog.join().unwrap();
+    // A cancel that arrives after the query finished doesn't carry
+    // over to the next one.
+    token.cancel();
+    assert_eq!(
+        one_line(&mut ctx, "1 + 2"),
+        Ok("3 (dimensionless)".to_owned())
+    );
+    assert_eq!(
+        one_line(&mut ctx, "2 + 2"),
+        Ok("4 (dimensionless)".to_owned())
+    );
+}
+
+#[test]
+fn test_literal_exponent() {
+    assert_eq!(
+        eval("1e1000001").unwrap_err().to_string(),
+        "Exponent is too large, the limit is 1000000"
+    );
+}
+
+#[test]
+fn test_serialize() {
+    assert_eq!(
+        to_value(limit("pi -> digits 100000000")).unwrap(),
+        json!({
+            "limit": "outputLength",
+            "max": 10_000,
+            "len": 100_000_000,
+        })
+    );
+    assert_eq!(
+        to_value(QueryError::from(LimitExceeded::Cancelled)).unwrap(),
+        json!({
+            "type": "limitExceeded",
+            "limit": "cancelled",
+        })
+    );
+}
@@ -0,0 +1,186 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Queries that used to panic. Everything here is allowed to return an
+//! error, but `one_line` must never panic. New inputs found by the
+//! fuzzer in `core/fuzz` should be added to `REGRESSIONS`.
+
+use rink_core::limits::Limits;
+use rink_core::*;
+use std::cell::RefCell;
+
+thread_local! {
+    static CONTEXT: RefCell<Context> = {
+        let mut ctx = simple_context().unwrap();
+        ctx.use_humanize = false;
+        ctx.limits = Limits {
+            timeout: None,
+            ..Limits::sandbox()
+        };
+        RefCell::new(ctx)
+    };
+}
+
+const REGRESSIONS: &[&str] = &[
+    "0^-1",
+    "0 m^-1",
+    "m^1e-400",
+    "kelvin^1e-400",
+    "2^(1/1e20)",
+    "1e-400 -> 2.5",
+    "\\u",
+    "\\uzzzz",
+    "\\U+",
+    "#99999999999-01-01#",
+    "#2020-01-01 12:00:00.#",
+    "#2020-01-01 12:00:00.12345678901234567890#",
+    "#2020-01-01# -> +99:99",
+    "#2020-01-01# -> +9999999999",
+    "#2020-01-01# -> +1:2",
+    "#12:00# -> UTC",
+    "#jd 999999999999999999999999999999999999999999#",
+    "#unix -170141183460469231731687303715884105728#",
+    "#mjd 1.99999999999999999999999999999999999999#",
+    "#+262143-12-31 23:59:59 TT#",
+    "#-262144-01-01 TAI#",
+    "now + ln(-1) s",
+    "now + 1e300 s",
+    "now -> 1e10 s",
+    "H99999999999O",
+    "H99999999999999999999O",
+    "Xx2",
+    "factorize m^99999",
+    "factorize m^200",
+    "m^99999",
+    "m^-99999",
+    "10 °C -> °F^2",
+    "units for",
+    "search",
+    "(",
+    ")",
+    "->",
+    "1 -> digits 99999999999999999999",
+    "1 ->",
+    "m -> [",
+    "m -> m, 0 m",
+];
+
+fn run(input: &str) {
+    CONTEXT.with(|ctx| {
+        let _ = one_line(&mut ctx.borrow_mut(), input);
+    });
+}
+
+#[test]
+fn test_regressions() {
+    for input in REGRESSIONS {
+        run(input);
+    }
+}
+
+#[test]
+fn test_generated() {
+    const ATOMS: &[&str] = &[
+        "1",
+        "0",
+        "-1",
+        "2.5",
+        "1e-400",
+        "1e400",
+        "0x10",
+        "pi",
+        "m",
+        "kg",
+        "s",
+        "kelvin",
+        "°C",
+        "°F",
+        "degC",
+        "dimensionless",
+        "now",
+        "#2020-01-01#",
+        "#12:00#",
+        "H2O",
+        "C6H12O6",
+        "ln",
+        "sqrt",
+        "^",
+        "*",
+        "/",
+        "+",
+        "-",
+        "->",
+        "(",
+        ")",
+        "[",
+        "]",
+        ",",
+        "=",
+        "|",
+        "%",
+        "°",
+        "factorize",
+        "units for",
+        "search",
+        "digits",
+        "base",
+        "hex",
+        "UTC",
+        "+05:00",
+        "\\u",
+        "'",
+        "\"",
+    ];
+    // Fixed seed so failures are reproducible.
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    for _ in 0..2000 {
+        let len = 1 + next() % 6;
+        let input = (0..len)
+            .map(|_| ATOMS[(next() % ATOMS.len() as u64) as usize])
+            .collect::<Vec<_>>()
+            .join(" ");
+        run(&input);
+    }
+}
+
+#[test]
+fn test_missing_definitions() {
+    // A context without the units that operators like `°C` rely on.
+    let units = "m !\ns !\nkm 1000 m\n";
+    let mut iter = gnu_units::TokenIterator::new(units).peekable();
+    let mut ctx = Context::new();
+    ctx.load(gnu_units::parse(&mut iter));
+    ctx.use_humanize = false;
+
+    assert_eq!(one_line(&mut ctx, "2 km").unwrap(), "2000 m (m)");
+    for input in &["3 °C", "3 °F", "m -> °C", "factorize m", "m -> [km, m]"] {
+        let _ = one_line(&mut ctx, input);
+    }
+    assert_eq!(
+        one_line(&mut ctx, "3 °C"),
+        Err("No such unit kelvin".to_owned())
+    );
+}
+
+#[test]
+fn test_gas_without_gas_constant() {
+    // Treating a substance as a gas needs `R`, which this context lacks.
+    let units = "m !\nkg !\ns !\nK !kelvin\nmol !mole\nPa kg / m s^2\n\
+                 pressure ? Pa\ntemperature ? K\n\
+                 air {\n    molar_mass mass 0.029 kg / amount mol\n}\n";
+    let mut iter = gnu_units::TokenIterator::new(units).peekable();
+    let mut ctx = Context::new();
+    ctx.load(gnu_units::parse(&mut iter));
+
+    assert_eq!(
+        one_line(&mut ctx, "density of air at 300 K, 100000 Pa"),
+        Err("No such unit R, did you mean air?".to_owned())
+    );
+}
@@ -458,6 +458,9 @@ fn test_leading_dot() {
 #[test]
 fn test_underscores_in_number() {
     test("123_456\u{2009}789", "123456789 (dimensionless)");
+    test("3_kg", "3 kilogram (mass)");
+    test("2_000 km", "2 megameter (length)");
+    test("1_000_kg", "1 tonne (mass)");
 }
 
 #[test]
@@ -495,6 +498,226 @@ fn test_search() {
         "search cm",
         "Search results: cmil (area), cminv (energy), cmcapacitance (capacitance), sccm (power), mcm (area)",
     );
+    test(
+        "search vaporization",
+        "Search results: water (substance), H2O (substance)",
+    );
+}
+
+#[test]
+fn test_properties() {
+    test(
+        "properties of ethanol",
+        "Properties of ethanol:\n  \
+         density: mass 789.45 milligram / volume 1000 millimeter^3 (At 20 °C.)\n  \
+         energy_density_HHV: energy_HHV approx. 88.62469 megajoule / \
+         volume_HHV approx. 3785411.7 millimeter^3\n  \
+         energy_density_LHV: energy_LHV approx. 79.86772 megajoule / \
+         volume_LHV approx. 3785411.7 millimeter^3\n  \
+         molar_mass: mass 46.06844 gram / amount 1 mole\n  \
+         specific_heat: specific_energy 2.3 kilogray / temperature 1 kelvin",
+    );
+    test_starts_with(
+        "properties electron",
+        "Properties of electron:\n  charge: charge approx. 160.2176 zeptocoulomb\n",
+    );
+    test_starts_with(
+        "properties of water at 20 °C",
+        "Properties of water at 20 °C:\n  \
+         density: mass 998.21 milligram / volume 1000 millimeter^3 (At 1 atm.)\n",
+    );
+    test("properties of 3 m", "3 m is not a substance");
+    test(
+        "substances with density",
+        "Substances with density: R134a, apple, baking_powder, banana, brown_rice, \
+         brownsugar_dark, brownsugar_light, butter, butter_clarified, cocoa_butter, cooked_rice, \
+         cornstarch, cornsyrup, ethanol, flour, heavycream, honey, lentils, mercury, milk, \
+         molasses, oats, olive_oil, peanut_butter, powdered_sugar, rice, salt, shortening, \
+         sourcream, sugar, vegetable_oil, water",
+    );
+    test(
+        "substances with half_life",
+        "Substances with half_life: americium241, carbon14, cesium137, cobalt60, fluorine18, \
+         iodine125, iodine131, phosphorus32, plutonium239, potassium40, radium226, radon222, \
+         strontium90, sulfur35, technetium99m, tritium, uranium235, uranium238",
+    );
+    test(
+        "substances with bogus",
+        "No substances have a property named bogus",
+    );
+    test(
+        "substances with",
+        "Expected a property name after substances with",
+    );
+}
+
+#[test]
+fn test_property_tables() {
+    test(
+        "density of water at 80 °C",
+        "971.82 kilogram / meter^3 (density)",
+    );
+    test(
+        "density of R134a at -30 °C",
+        "1386.55 kilogram / meter^3 (density)",
+    );
+    test(
+        "vapor_pressure of water at 100 °C",
+        "101.42 kilopascal (pressure)",
+    );
+    test(
+        "2 L water at 80 °C -> kg",
+        "2 L water at 80 °C: volume = 2000000 millimeter^3; \
+         mass = 1.94364 kilogram (At 1 atm.)",
+    );
+    test(
+        "specific_heat of water at 95 °C",
+        "<368.15 kelvin> is outside the specific_heat table of water, \
+         which goes from <273.15 kelvin> to <363.15 kelvin>",
+    );
+    test(
+        "water at 95 °C",
+        "<368.15 kelvin> is outside the specific_heat table of water, \
+         which goes from <273.15 kelvin> to <363.15 kelvin>",
+    );
+    test(
+        "water at 95 °C -> g/cm^3",
+        "water at 95 °C: density = 0.96185 gram / centimeter^3 (At 1 atm.)",
+    );
+    test(
+        "2 L water at 200 °C -> kg",
+        "<473.15 kelvin> is outside the density table of water, \
+         which goes from <273.15 kelvin> to <373.15 kelvin>",
+    );
+    test(
+        "water at 300 K, 1 atm",
+        "water has no properties that depend on pressure",
+    );
+    test(
+        "3 m at 20 °C",
+        "Expected a substance before at, got <3 meter (length)>",
+    );
+}
+
+#[test]
+fn test_ideal_gas() {
+    test(
+        "volume of 2 kg nitrogen at 300 K, 1 atm",
+        "approx. 1.756370 meter^3 (volume)",
+    );
+    test(
+        "density of air at 25 °C, 101 kPa",
+        "approx. 1.180237 kilogram / meter^3 (density)",
+    );
+    test(
+        "1 mol CO2 at 0 °C, 1 atm -> L",
+        "1 mol CO2 at 0 °C, 1 atm: amount = 1 mole; volume = approx. 22.41396 liter (Ideal gas.)",
+    );
+    test(
+        "10 L oxygen at 20 °C, 200 bar -> kg",
+        "10 L oxygen at 20 °C, 200 bar: volume = 10000000 millimeter^3; \
+         mass = approx. 2.940490 kilogram (Van der Waals gas.)",
+    );
+    test(
+        "air at 25 °C",
+        "Treating air as a gas needs both a temperature and a pressure, \
+         got only <298.15 kelvin (temperature)>",
+    );
+    test(
+        "ammonia at 300 K, 1 atm",
+        "Treating ammonia as a gas needs its molar_mass",
+    );
+    test(
+        "density of air at -300 °C, 1 atm",
+        "Treating air as a gas needs a temperature above absolute zero, \
+         got <-26.85 kelvin (temperature)>",
+    );
+    test(
+        "density of air at 20 °C, 0 atm",
+        "Treating air as a gas needs a positive pressure, got <0 pascal (pressure)>",
+    );
+    test(
+        "molar_mass of nitrogen at 0 °C, 1 atm",
+        "0.01400674 kilogram / mole (molar_mass)",
+    );
+}
+
+#[test]
+fn test_foods() {
+    test("energy of 150 g rice -> kcal", "547.5 kilocal_IT (energy)");
+    test("energy of 1 cup milk -> kcal", "147.62 kilocal_IT (energy)");
+    test("protein of 2 cups oats", "21.303 gram (mass)");
+    test("protein of 100 g sugar", "0 gram (mass)");
+    test(
+        "2 cups flour -> g",
+        "flour: All-purpose white wheat flour, enriched. Spooned into the cup. \
+         volume = approx. 473176.4 millimeter^3; mass = 240 gram; \
+         mass_scooped = approx. 283.4952 gram; mass_sifted = approx. 226.7961 gram; \
+         mass_spooned = approx. 240.9709 gram",
+    );
+    test(
+        "2 cups sugar -> g",
+        "sugar: Granulated white sugar. volume = approx. 473176.4 millimeter^3; mass = 400 gram",
+    );
+}
+
+#[test]
+fn test_radioactive_decay() {
+    test(
+        "1 mCi Co60 after 3 years -> Bq",
+        "1 mCi Co60 after 3 years: frequency = approx. 24939432.7 becquerel",
+    );
+    test(
+        "activity of 1 g Ra226 -> Ci",
+        "approx. 0.9885381 curie (frequency)",
+    );
+    test("mass of 1 mCi I131", "approx. 8.045535 nanogram (mass)");
+    test(
+        "specific_activity of Co60 -> Ci/g",
+        "approx. 1131.547 curie / gram",
+    );
+    test(
+        "1 g water after 3 years",
+        "water has no half_life, so it doesn't decay",
+    );
+    test(
+        "1 g Co60 after 3 m",
+        "Expected a time after after, got <3 meter (length)>",
+    );
+    test(
+        "1 g Co60 after 5 years",
+        "1 g Co60 after 5 years: mass = approx. 518.1729 milligram; \
+         amount = approx. 8.645752 millimole; activity = approx. 2.169448e13 / second",
+    );
+    test(
+        "1 g Co60 after 1 year after 1 year",
+        "(1 g Co60 after 1 year) after 1 year: mass = approx. 768.7584 milligram; \
+         amount = approx. 12.82678 millimole; activity = approx. 3.218580e13 / second",
+    );
+    test(
+        "activity of 1 g Co60 after 1 year after 1 year",
+        "approx. 3.218580e13 / second (frequency)",
+    );
+    // `after` can come before or after the states of a gas.
+    test(
+        "density of tritium after 1 year at 300 K, 1 atm",
+        "approx. 0.1158153 kilogram / meter^3 (density)",
+    );
+    test(
+        "density of tritium at 300 K, 1 atm after 1 year",
+        "approx. 0.1158153 kilogram / meter^3 (density)",
+    );
+}
+
+#[test]
+fn test_zero_half_life() {
+    let mut ctx = simple_context().unwrap();
+    let csv = "name,half_life (s)\nunobtainium,0\n";
+    ctx.load(materials::material_defs(materials::parse_csv(csv).unwrap()));
+    assert_eq!(
+        one_line(&mut ctx, "1 g unobtainium after 1 s"),
+        Err("unobtainium has a half_life of zero".to_owned())
+    );
 }
 
 #[test]
@@ -594,19 +817,208 @@ fn test_try_decode_fail() {
 
 #[test]
 fn test_formula() {
+    test("methane=CH4", "CH4: molar_mass = 16.04276 gram / mole");
+    test("NaCl", "NaCl: molar_mass = approx. 58.44246 gram / mole");
     test(
-        "methane=CH4",
-        "CH4: molar_mass = 0.01604276 kilogram / mole",
+        "C8H10N4O2",
+        "C8H10N4O2: molar_mass = approx. 194.1931 gram / mole",
     );
+    test("C60", "C60: molar_mass = 720.66 gram / mole");
     test(
-        "NaCl",
-        "NaCl: molar_mass = approx. 0.05844246 kilogram / mole",
+        "\"Ca(OH)2\" -> g/mol",
+        "Ca(OH)2: molar_mass = 74.09268 gram / mole",
     );
     test(
-        "C8H10N4O2",
-        "C8H10N4O2: molar_mass = approx. 0.1941931 kilogram / mole",
+        "\"CuSO4·5H2O\"",
+        "CuSO4·5H2O: molar_mass = 249.686 gram / mole",
+    );
+    test(
+        "\"[Fe(CN)6]3-\"",
+        "[Fe(CN)6]3-: molar_mass = approx. 211.9530 gram / mole",
+    );
+    test(
+        "\"SO4^2-\"",
+        "SO4^2-: molar_mass = approx. 96.06469 gram / mole",
+    );
+    test("\"Na+\"", "Na+: molar_mass = approx. 22.98921 gram / mole");
+    test("D2O", "D2O: molar_mass = approx. 20.02760 gram / mole");
+    test(
+        "\"[2H]2O\"",
+        "[2H]2O: molar_mass = approx. 20.02760 gram / mole",
+    );
+    test(
+        "\"13CO2\"",
+        "13CO2: molar_mass = approx. 45.00215 gram / mole",
+    );
+    // Hydrate dots don't need quotes.
+    test("CuSO4·5H2O", "CuSO4·5H2O: molar_mass = 249.686 gram / mole");
+    test(
+        "\"5H2O\"",
+        "No such unit 5H2O. Digits at the start of a formula are a mass number, and there is \
+         no such isotope. For a count, write `5 H2O`",
+    );
+}
+
+#[test]
+fn test_concentration() {
+    test(
+        "1 L of 2 M NaCl -> g NaCl",
+        "NaCl: amount = 2 mole; mass = approx. 116.8849 gram",
+    );
+    test(
+        "250 ml of 0.1 M \"CuSO4·5H2O\" -> g",
+        "CuSO4·5H2O: amount = 25 millimole; mass = 6.24215 gram",
+    );
+    test(
+        "1 kg of 0.5 molal NaCl -> g",
+        "NaCl: amount = 500 millimole; mass = approx. 29.22123 gram",
+    );
+    test(
+        "1 L of 2 M NaCl -> g KCl",
+        "Operation is not defined: <2 mole (amount; mol) NaCl> -> <1 gram (mass; kg) KCl>",
+    );
+    // Only units that measure moles out of a concentration make an
+    // amount.
+    test("ft of water", "No such property ft of water");
+    test("L of NaCl", "No such property L of NaCl");
+    test("mass of 1 mol water", "18.01528 gram (mass)");
+    test("mass of 1 L water", "1 kilogram (mass)");
+}
+
+#[test]
+fn test_dilution() {
+    test(
+        "dilute 100 mL of 2 M NaCl to 0.5 M",
+        "Dilute to 400 milliliter by adding 300 milliliter of solvent",
+    );
+    test(
+        "dilute 1 kg of 2 molal NaCl to 0.5 molal",
+        "Dilute to 4 kilogram by adding 3 kilogram of solvent",
+    );
+    test(
+        "dilute 100 mL of 2 M NaCl to 4 M",
+        "Can't dilute <2000 mole / meter^3 (molar_concentration)> \
+         to the higher concentration <4000 mole / meter^3 (molar_concentration)>",
+    );
+    test(
+        "dilute 100 mL 2 M NaCl to 4 M",
+        "Expected `dilute <volume> of <concentration> to <concentration>`",
+    );
+}
+
+#[test]
+fn test_mixture() {
+    test(
+        "density of (40% ethanol + 60% water by volume)",
+        "915.78 kilogram / meter^3 (density)",
+    );
+    test(
+        "40% ethanol + 60% water by mass",
+        "(40 percent) ethanol + (60 percent) water by mass: \
+         density = approx. 0.9036020 milligram / millimeter^3; \
+         molar_mass = approx. 23.81644 gram / mole; \
+         specific_heat = 3.43208 kilogray / kelvin",
+    );
+    test(
+        "molar_mass of (50% ethanol + 50% water by mole)",
+        "0.03204186 kilogram / mole (molar_mass)",
+    );
+    test(
+        "100 ml ethanol + 200 ml water by volume -> g",
+        "100 ml ethanol + 200 ml water by volume: \
+         volume = 300000 millimeter^3; mass = 278.945 gram",
+    );
+    test(
+        "100 ml ethanol + 200 g water by volume",
+        "Expected fractions or amounts by volume in mixture, got <200 gram (mass)> of water",
+    );
+    test(
+        "40% ethanol + 60% wood by volume",
+        "wood has no density, which is needed to mix by volume",
+    );
+    test(
+        "40% ethanol + 60% water by colour",
+        "Expected mass, volume or mole after by",
+    );
+}
+
+#[test]
+fn test_composition() {
+    test(
+        "composition of C3H8",
+        "C3H8: amount = 1 mole; mass = 44.09652 gram\n  \
+         C (carbon): 3 atoms, 3 mole, 36.033 gram, approx. 81.71393%\n  \
+         H (hydrogen): 8 atoms, 8 mole, 8.06352 gram, approx. 18.28606%",
+    );
+    test(
+        "composition 10 g NaCl",
+        "NaCl: amount = approx. 171.1084 millimole; mass = 10 gram\n  \
+         Cl (chlorine): 1 atom, approx. 171.1084 millimole, approx. 6.066256 gram, \
+         approx. 60.66256%\n  \
+         Na (sodium): 1 atom, approx. 171.1084 millimole, approx. 3.933743 gram, \
+         approx. 39.33743%",
+    );
+    test("composition of water", "water is not a chemical formula");
+}
+
+#[test]
+fn test_balance() {
+    test(
+        "balance C3H8 + O2 -> CO2 + H2O",
+        "C3H8 + 5 O2 -> 3 CO2 + 4 H2O",
+    );
+    test("balance 2 H2 + O2 = H2O", "2 H2 + O2 -> 2 H2O");
+    test(
+        "balance Cu + \"Ag+\" -> \"Cu2+\" + Ag",
+        "Cu + 2 Ag+ -> Cu2+ + 2 Ag",
+    );
+    test(
+        "balance C3H8 + O2 -> CO2 + H2O for 2 mol O2",
+        "C3H8 + 5 O2 -> 3 CO2 + 4 H2O\n  \
+         C3H8: amount = 400 millimole; mass = approx. 17.63860 gram\n  \
+         O2: amount = 2 mole; mass = 63.9976 gram\n  \
+         CO2: amount = 1.2 mole; mass = 52.81176 gram\n  \
+         H2O: amount = 1.6 mole; mass = approx. 28.82444 gram",
+    );
+    test(
+        "balance H2 + O2 -> H2O for 1 g N2",
+        "N2 is not part of the reaction",
+    );
+    test(
+        "balance H2 + O2 -> H2O + H2O2",
+        "Reaction can be balanced in more than one way, \
+         try splitting it into separate reactions",
+    );
+    test("balance H2 O2 -> H2O", "Expected `->` or `=`, got ident");
+}
+
+#[test]
+fn test_yields() {
+    test(
+        "10 g C3H8 yields CO2",
+        "CO2: amount = approx. 680.3257 millimole; mass = approx. 29.94100 gram",
+    );
+    test(
+        "2 mol H2O yields O2",
+        "O2: amount = 1 mole; mass = 31.9988 gram",
+    );
+    test(
+        "C3H8 yields H2O",
+        "H2O: amount = 4 mole; mass = 72.06112 gram",
+    );
+    test(
+        "10 g C2H5OH yields CO2",
+        "C2H5OH and CO2 share C, O in different proportions, \
+         try `balance <reaction> for <amount>`",
+    );
+    test(
+        "10 s C3H8 yields CO2",
+        "Expected an amount of substance or a mass of C3H8, got <10 second (time)>",
+    );
+    test(
+        "10 g C3H8 yields 2 CO2",
+        "Expected chemical formula after yields",
     );
-    test("C60", "C60: molar_mass = 0.72066 kilogram / mole");
 }
 
 #[test]
@@ -622,6 +1034,505 @@ fn test_offset_date_math() {
     );
 }
 
+#[test]
+fn test_calendar_arithmetic() {
+    test("#2020-01-31# + 1 month", "2020-02-29 00:00:00 +00:00");
+    test("#2019-01-31# + 1 month", "2019-02-28 00:00:00 +00:00");
+    test("#2020-02-29# + 1 year", "2021-02-28 00:00:00 +00:00");
+    test("#2020-03-31# - 1 month", "2020-02-29 00:00:00 +00:00");
+    test("#2020-01-31# + 13 months", "2021-02-28 00:00:00 +00:00");
+    test("#2020-01-31# + 2 decades", "2040-01-31 00:00:00 +00:00");
+    test("1 mo + #2020-01-31#", "2020-02-29 00:00:00 +00:00");
+    test(
+        "#2020-01-31 10:00 Europe/London# + 6 months",
+        "2020-07-31 10:00:00 BST",
+    );
+    test(
+        "#2024-01-31# + 1.5 months",
+        "Can't offset a date by `1.5 months`: it isn't a whole number of months. Use a \
+         fixed length like `gregorianyear / 12` for an average month",
+    );
+    test(
+        "#2024-01-31# + 1.5 gregorianyear / 12",
+        "2024-03-16 15:43:39 +00:00",
+    );
+    test("#2024-01-31# + 1.5 years", "2025-07-31 00:00:00 +00:00");
+    test("#2020-01-31# + 1 months * 2", "2020-03-31 00:00:00 +00:00");
+    test("#2020-01-31# + 2 * 6 months", "2021-01-31 00:00:00 +00:00");
+    test("#2020-01-31# - year / 12", "2019-12-31 00:00:00 +00:00");
+    test(
+        "#2020-01-31# + (1 month + 1 day)",
+        "Can't offset a date by `1 month + 1 day`: calendar months and years can only be \
+         multiplied or divided by numbers",
+    );
+}
+
+#[test]
+fn test_calendar_difference() {
+    test(
+        "#2021-03-15# - #2020-01-31#",
+        "1 year, 1 month, 15 day, 0 second (time)",
+    );
+    test(
+        "#2020-01-31# - #2021-03-15#",
+        "-1 year, -1 month, -15 day, 0 second (time)",
+    );
+    test(
+        "#2020-03-01 12:30# - #2020-01-15 08:00#",
+        "1 month, 15 day, 4 hour, 30 minute, 0 second (time)",
+    );
+    test("(#2021-03-15# - #2020-01-31#) -> days", "409 day (time)");
+}
+
+#[test]
+fn test_relative_dates() {
+    test("tomorrow - yesterday", "2 day, 0 second (time)");
+    test("#tomorrow# - today", "1 day, 0 second (time)");
+    test("(tomorrow + 1 day - today) -> hours", "48 hour (time)");
+    test(
+        "#end of day# - today",
+        "23 hour, 59 minute, 59 second (time)",
+    );
+}
+
+#[test]
+fn test_date_formats() {
+    test("#2020-05-13 15:30:00# -> unix", "1589383800");
+    test("#2020-05-13 15:30:00.123# -> unixms", "1589383800123");
+    test(
+        "#2020-05-13 15:30:00# -> iso8601",
+        "2020-05-13T15:30:00+00:00",
+    );
+    test("#2020-05-13# -> isoweek", "2020-W20-3");
+    test("#2021-01-01# -> isoweek", "2020-W53-5");
+    test("#2020-05-13# -> isoordinal", "2020-134");
+    test(
+        "#2020-05-13 15:30:00# -> rfc2822",
+        "Wed, 13 May 2020 15:30:00 +0000",
+    );
+    test(
+        "#2020-05-13# + 8000 years -> rfc2822",
+        "RFC 2822 can't represent the year 10020, only 0 to 9999",
+    );
+    test("#2000-01-01 12:00:00# -> jd", "2451545");
+    test("#2020-05-13 15:30:00# -> jd", "2458983.145833");
+    test("#1858-11-17# -> mjd", "0");
+    test("#2020-05-13 15:30:00# -> mjd", "58982.645833");
+    test(
+        "#2020-05-13 15:30:00# -> strftime '%A, %d %B %Y'",
+        "Wednesday, 13 May 2020",
+    );
+    test(
+        "#2020-05-13 15:30:00 Europe/London# -> strftime '%H:%M %Z'",
+        "15:30 BST",
+    );
+    test(
+        "#2020-05-13# -> strftime '%Q'",
+        "Invalid strftime pattern: %Q",
+    );
+    test(
+        "#2020-05-13# -> strftime",
+        "Expected quoted strftime pattern, got eof",
+    );
+    test("3 m -> unix", "Cannot convert <3 meter (length)> to unix");
+}
+
+#[test]
+fn test_date_format_reply() {
+    let reply = |input: &str| {
+        let mut iter = text_query::TokenIterator::new(input).peekable();
+        let expr = text_query::parse_query(&mut iter);
+        match CONTEXT.with(|ctx| ctx.eval_outer(&expr)) {
+            Ok(reply::QueryReply::Date(date)) => date,
+            res => panic!("{:?}", res),
+        }
+    };
+    // Plain dates don't carry the other representations.
+    let date = reply("#2020-05-13#");
+    assert_eq!(date.iso_week, None);
+    assert_eq!(date.rfc2822, None);
+
+    let date = reply("#2020-05-13# -> unix");
+    assert_eq!(date.iso_week.as_deref(), Some("2020-W20-3"));
+    assert_eq!(date.julian_day.as_deref(), Some("2458982.5"));
+    assert!(date.rfc2822.is_some());
+
+    let date = reply("#2020-05-13# + 8000 years -> jd");
+    assert_eq!(date.year, 10020);
+    assert_eq!(date.rfc2822, None);
+}
+
+#[test]
+fn test_time_scales() {
+    test("#2020-05-13 15:30:00# -> tai", "2020-05-13 15:30:37 TAI");
+    test("#2020-05-13 15:30:00# -> tt", "2020-05-13 15:31:09.184 TT");
+    test("#2020-05-13 15:30:00# -> gps", "2020-05-13 15:30:18 GPS");
+    test("#2016-12-31 23:59:59# -> tai", "2017-01-01 00:00:35 TAI");
+    test("#2020-05-13 15:30:37 TAI#", "2020-05-13 15:30:00 +00:00");
+    test("#2020-05-13 15:31:09.184 TT#", "2020-05-13 15:30:00 +00:00");
+    test(
+        "#2020-05-13 15:30:18 gps# -> tai",
+        "2020-05-13 15:30:37 TAI",
+    );
+    test(
+        "#1970-01-01# -> tai",
+        "Leap seconds are only known from 1972-01-01",
+    );
+    test(
+        "#2020-05-13 15:30:00# -> UT1",
+        "UT1 is not supported, since it depends on measured DUT1 values. TAI, TT and GPS \
+         time are",
+    );
+}
+
+#[test]
+fn test_numeric_dates() {
+    test("#jd 2451545#", "2000-01-01 12:00:00 +00:00");
+    test("#MJD 51544.5#", "2000-01-01 12:00:00 +00:00");
+    test("#unix 1589383800.5#", "2020-05-13 15:30:00.500 +00:00");
+    test("#unix -86400#", "1969-12-31 00:00:00 +00:00");
+    test("#jd 2458983.145833# -> jd", "2458983.145833");
+    test("#jd 9999999999999999#", "Date is out of range");
+}
+
+#[test]
+fn test_business_days() {
+    test(
+        "#2024-03-01# + 10 businessdays",
+        "2024-03-15 00:00:00 +00:00",
+    );
+    test("10 workdays + #2024-03-01#", "2024-03-15 00:00:00 +00:00");
+    test("#2024-03-04# - businessday", "2024-03-01 00:00:00 +00:00");
+    test(
+        "#2024-03-01T12:00:00 Europe/Berlin# + 1 businessday",
+        "2024-03-04 12:00:00 CET",
+    );
+    test(
+        "#2024-12-24# + 1 us businessday",
+        "2024-12-26 00:00:00 +00:00",
+    );
+    test(
+        "#2024-03-01# + 1.5 businessdays",
+        "Business days must be a whole number",
+    );
+    test(
+        "3 + 2 businessdays",
+        "Business days can only be added to dates: <3 (dimensionless)> + <2 businessdays>",
+    );
+    test(
+        "businessdays between #2024-03-01# and #2024-04-01#",
+        "21 (dimensionless)",
+    );
+    test(
+        "businessdays between #2024-04-01# and #2024-03-01#",
+        "-21 (dimensionless)",
+    );
+    test(
+        "us businessdays between #2024-11-01# and #2024-12-01#",
+        "18 (dimensionless)",
+    );
+    test(
+        "businessdays between #2024-03-01# + 1 day and #2024-04-01#",
+        "21 (dimensionless)",
+    );
+    test(
+        "businessdays between #2024-03-01#",
+        "Expected `<date> and <date>` after between",
+    );
+    test(
+        "xx businessdays between #2024-03-01# and #2024-04-01#",
+        "No such business calendar: xx. Known calendars are: us",
+    );
+}
+
+#[test]
+fn test_timezone_aliases() {
+    test("#2024-03-01 09:00 PDT#", "2024-03-01 09:00:00 -07:00");
+    test("#2024-03-01 09:00 jst#", "2024-03-01 09:00:00 +09:00");
+    test("#2024-03-01 09:00 Tokyo#", "2024-03-01 09:00:00 JST");
+    test("#2024-07-01 09:00 New York#", "2024-07-01 09:00:00 EDT");
+    test("#2024-03-01 09:00 UTC# -> Tokyo", "2024-03-01 18:00:00 JST");
+    test(
+        "#2024-03-01 09:00 UTC# -> new york",
+        "2024-03-01 04:00:00 EST",
+    );
+    test(
+        "#2024-03-01 09:00 UTC# -> PDT",
+        "2024-03-01 02:00:00 -07:00",
+    );
+    test(
+        "#2024-03-01 09:00 UTC# -> IST",
+        "Timezone IST is ambiguous, it could be: +01:00 (Irish Standard Time), \
+         +05:30 (India Standard Time), +02:00 (Israel Standard Time). \
+         Use a UTC offset or an IANA name instead",
+    );
+    test(
+        "#2024-03-01 09:00 CST#",
+        "Timezone CST is ambiguous, it could be: -06:00 (Central Standard Time), \
+         +08:00 (China Standard Time). Use a UTC offset or an IANA name instead",
+    );
+}
+
+#[test]
+fn test_date_intervals() {
+    test(
+        "#2024-01-01# .. #2024-03-31#",
+        "2024-01-01 00:00:00 +00:00 .. 2024-03-31 00:00:00 +00:00, \
+         lasting 2 month, 30 day, 0 second (time)",
+    );
+    test(
+        "#2024-03-31# .. #2024-01-01#",
+        "Interval ends before it starts: \
+         <2024-03-31 00:00:00 +00:00> .. <2024-01-01 00:00:00 +00:00>",
+    );
+    test(
+        "1 .. 2",
+        "Intervals must start and end with dates: \
+         <1 (dimensionless)> .. <2 (dimensionless)>",
+    );
+    test("(#2024-01-01# .. #2024-03-31#) -> days", "90 day (time)");
+    test(
+        "start of (#2024-01-01# .. #2024-03-31#)",
+        "2024-01-01 00:00:00 +00:00",
+    );
+    test(
+        "end of (#2024-01-01# .. #2024-03-31#)",
+        "2024-03-31 00:00:00 +00:00",
+    );
+    test("#2024-02-15# in #2024-01-01# .. #2024-03-31#", "true");
+    test("#2024-03-31# in #2024-01-01# .. #2024-03-31#", "true");
+    test("#2024-04-01# in #2024-01-01# .. #2024-03-31#", "false");
+    test(
+        "(#2024-02-01# .. #2024-04-01#) in #2024-01-01# .. #2024-03-31#",
+        "false",
+    );
+    test(
+        "overlap(#2024-01-01# .. #2024-03-31#, #2024-03-01# .. #2024-05-01#)",
+        "2024-03-01 00:00:00 +00:00 .. 2024-03-31 00:00:00 +00:00, \
+         lasting 30 day, 0 second (time)",
+    );
+    test(
+        "overlap(#2024-01-01# .. #2024-01-31#, #2024-03-01# .. #2024-05-01#)",
+        "Intervals do not overlap: overlap(\
+         2024-01-01 00:00:00 +00:00 .. 2024-01-31 00:00:00 +00:00, \
+         2024-03-01 00:00:00 +00:00 .. 2024-05-01 00:00:00 +00:00)",
+    );
+    test(
+        "(#2024-01-01# .. #2024-01-31#) + 1 week",
+        "2024-01-08 00:00:00 +00:00 .. 2024-02-07 00:00:00 +00:00, \
+         lasting 30 day, 0 second (time)",
+    );
+}
+
+#[test]
+fn test_humanize() {
+    use chrono::{TimeZone, Utc};
+
+    let mut ctx = simple_context().unwrap();
+    ctx.set_time(Utc.ymd(2024, 3, 1).and_hms(12, 0, 0));
+    let eval = |ctx: &Context, input: &str| {
+        let mut iter = text_query::TokenIterator::new(input).peekable();
+        let expr = text_query::parse_query(&mut iter);
+        ctx.eval_outer(&expr).unwrap().to_string()
+    };
+    assert_eq!(eval(&ctx, "now"), "2024-03-01 12:00:00 +00:00 (now)");
+    assert_eq!(
+        eval(&ctx, "#2021-10-25#"),
+        "2021-10-25 00:00:00 +00:00 (2 years ago)"
+    );
+    ctx.humanize_precision = 2;
+    assert_eq!(
+        eval(&ctx, "#2021-10-25#"),
+        "2021-10-25 00:00:00 +00:00 (2 years, 4 months ago)"
+    );
+    assert_eq!(
+        eval(&ctx, "now + 3 days + 6 hours"),
+        "2024-03-04 18:00:00 +00:00 (in 3 days, 6 hours)"
+    );
+}
+
+#[test]
+fn test_date_patterns() {
+    test_starts_with(
+        "datepatterns #2024-03-01#",
+        "#2024-03-01# is read by `year-monthnum-fullday['T'hour24:min[:sec][ offset]]` \
+         as 2024-03-01 00:00:00 +00:00\n  \
+         `year-monthnum-fullday['T'hour24:min[:sec][ offset]]`: matched \
+         2024-03-01 00:00:00 +00:00\n",
+    );
+    test_starts_with(
+        "datepatterns #2024-13-01#",
+        "#2024-13-01# matches no date patterns\n  \
+         `year-monthnum-fullday['T'hour24:min[:sec][ offset]]`: \
+         Failed to construct a useful datetime\n",
+    );
+    test_starts_with(
+        "datepatterns #unix 1700000000#",
+        "#unix 1700000000# is read as 2023-11-14 22:13:20 +00:00 without date patterns\n",
+    );
+    test(
+        "datepatterns now",
+        "Expected a date literal after datepatterns",
+    );
+}
+
+#[test]
+fn test_datepattern_directive() {
+    let mut ctx = simple_context().unwrap();
+    ctx.use_humanize = false;
+    let mut iter = gnu_units::TokenIterator::new(
+        "!datepattern \"day'/'monthnum'/'fullyear\"\n\
+         !datepattern \"day'/'foo\"\n\
+         !datepattern\n\
+         bogon 3 m\n",
+    )
+    .peekable();
+    ctx.load(gnu_units::parse(&mut iter));
+    assert_eq!(ctx.datepattern_errors.len(), 2);
+    assert_eq!(ctx.datepattern_errors[0].pattern, "day'/'foo");
+    assert_eq!(ctx.datepattern_errors[0].message, "Unknown matcher foo");
+    assert_eq!(ctx.datepattern_errors[1].message, "Pattern is empty");
+    assert_eq!(
+        one_line(&mut ctx, "#25/12/2024#"),
+        Ok("2024-12-25 00:00:00 +00:00".to_owned())
+    );
+    assert_eq!(
+        one_line(&mut ctx, "bogon"),
+        Ok("Definition: bogon = 3 m = 3 meter (length; m)".to_owned())
+    );
+    let err = ctx
+        .add_datepattern("fullday monthnum fullyear]")
+        .unwrap_err();
+    assert_eq!(err.line, None);
+    assert_eq!(err.message, "Unexpected ]");
+    assert!(ctx.add_datepattern("fullday monthnum fullyear").is_ok());
+    assert_eq!(
+        one_line(&mut ctx, "#25 12 2024#"),
+        Ok("2024-12-25 00:00:00 +00:00".to_owned())
+    );
+}
+
+#[test]
+fn test_materials() {
+    let mut ctx = simple_context().unwrap();
+    ctx.use_humanize = false;
+    let csv =
+        "name,doc,density mass/volume (g/cm^3),thermal_conductivity (W/m K),yield_strength (MPa)\n\
+               6061_aluminum,Aluminium alloy in the T6 temper.,2.70,167,276\n\
+               a36_steel,,7.85,,250\n";
+    ctx.load(materials::material_defs(materials::parse_csv(csv).unwrap()));
+    let json = r#"[{"name": "pine", "density mass/volume (g/cm^3)": 0.5, "hardness (N)": "1690"}]"#;
+    ctx.load(materials::material_defs(
+        materials::parse_json(json).unwrap(),
+    ));
+    assert_eq!(
+        one_line(&mut ctx, "thermal_conductivity of 6061_aluminum"),
+        Ok("167 newton / kelvin second (thermal_conductivity)".to_owned())
+    );
+    assert_eq!(
+        one_line(&mut ctx, "1 L 6061_aluminum -> kg"),
+        Ok("6061_aluminum: Aluminium alloy in the T6 temper. \
+            volume = 1000000 millimeter^3; mass = 2.7 kilogram"
+            .to_owned())
+    );
+    assert_eq!(
+        one_line(&mut ctx, "a36_steel"),
+        Ok("a36_steel: density = 0.00785 gram / millimeter^3; \
+            yield_strength = 250 megapascal"
+            .to_owned())
+    );
+    assert_eq!(
+        one_line(&mut ctx, "2 m^3 pine -> kg"),
+        Ok("pine: volume = 2 meter^3; mass = 1000 kilogram".to_owned())
+    );
+    assert_eq!(
+        one_line(&mut ctx, "hardness of pine"),
+        Ok("1.69 kilonewton (force)".to_owned())
+    );
+    assert_eq!(
+        one_line(&mut ctx, "1_000 m"),
+        Ok("1 kilometer (length)".to_owned())
+    );
+}
+
+#[test]
+fn test_recurrence() {
+    test(
+        "every 2 weeks from #2024-01-01# until #2024-03-01#",
+        "2024-01-01 00:00:00 +00:00, 2024-01-15 00:00:00 +00:00, \
+         2024-01-29 00:00:00 +00:00, 2024-02-12 00:00:00 +00:00, \
+         2024-02-26 00:00:00 +00:00",
+    );
+    test(
+        "every month from #2024-01-31# until #2024-04-30#",
+        "2024-01-31 00:00:00 +00:00, 2024-02-29 00:00:00 +00:00, \
+         2024-03-31 00:00:00 +00:00, 2024-04-30 00:00:00 +00:00",
+    );
+    test(
+        "every 2 weeks from #2024-01-01# + 1 day until #2024-02-01# - 1 day",
+        "2024-01-02 00:00:00 +00:00, 2024-01-16 00:00:00 +00:00, \
+         2024-01-30 00:00:00 +00:00",
+    );
+    test(
+        "every 0 days from #2024-01-01# until #2024-03-01#",
+        "Recurrence step must be positive",
+    );
+    test(
+        "every 3 m from #2024-01-01# until #2024-03-01#",
+        "Recurrence step must be a duration",
+    );
+    test(
+        "every second from #2024-01-01# until #2024-03-01#",
+        "Recurrence has more than 1000 dates",
+    );
+    test(
+        "every 2 weeks",
+        "Expected `<step> from <date> until <date>` after every",
+    );
+}
+
+#[test]
+fn test_calendars() {
+    test("#2024-04-23# -> hebrew", "15 Nisan 5784 AM");
+    test("#2024-04-23# -> julian", "10 April 2024 Julian");
+    test("#2024-04-10# -> islamic", "1 Shawwal 1445 AH");
+    test("#2024-03-20# -> persian", "1 Farvardin 1403 AP");
+    test("#-0043-03-13# -> julian", "15 March 44 BC Julian");
+    // The local date is converted, not the date in UTC.
+    test(
+        "#2024-04-23 01:00 Asia/Tokyo# -> hebrew",
+        "15 Nisan 5784 AM",
+    );
+    test(
+        "3 m -> hebrew",
+        "Cannot convert <3 meter (length)> to hebrew",
+    );
+    test("#2020-05-13# -> dayofyear", "134");
+
+    test("#15 Nisan 5784 AM#", "2024-04-23 00:00:00 +00:00");
+    test("#1 Adar II 5784#", "2024-03-11 00:00:00 +00:00");
+    test("#1 Shawwal 1445 AH#", "2024-04-10 00:00:00 +00:00");
+    test("#1 Farvardin 1403#", "2024-03-20 00:00:00 +00:00");
+    test("#15 March 44 BC Julian#", "-0043-03-13 00:00:00 +00:00");
+    test("#1582-10-05 julian#", "1582-10-15 00:00:00 +00:00");
+    test("#1 January 1970#", "1970-01-01 00:00:00 +00:00");
+    test(
+        "#1 Adar II 5783#",
+        "Most likely pattern `day calmonth year[ adbc][ calendar]` failed: \
+         Year 5783 of the Hebrew calendar has no month 13",
+    );
+    test(
+        "#30 Esfand 1404 AP#",
+        "Most likely pattern `day calmonth year[ adbc][ calendar]` failed: \
+         Esfand 1404 of the Persian calendar has 29 days",
+    );
+
+    test("#2020-W20-3#", "2020-05-13 00:00:00 +00:00");
+    test("#2020-W20#", "2020-05-11 00:00:00 +00:00");
+    test("#2020-W53-5#", "2021-01-01 00:00:00 +00:00");
+    test("#2020-134#", "2020-05-13 00:00:00 +00:00");
+}
+
 #[test]
 fn test_bad_floats() {
     // Log10
@@ -645,3 +1556,16 @@ fn test_bad_floats() {
 fn test_large_floats() {
     test("5.2*10^15*300^(3/2)", "approx. 2.701999e19 (dimensionless)");
 }
+
+#[test]
+fn test_small_numbers() {
+    test("1e-100", "1.0e-100 (dimensionless)");
+    test("1e-30000", "1.0e-30000 (dimensionless)");
+    test("1/7000", "approx. 0.0001428571 (dimensionless)");
+    test("1/1023 -> hex", "approx. 0.004010040 (dimensionless)");
+    test(
+        "2^-40 -> bin",
+        "0.0000000000000000000000000000000000000001 (dimensionless)",
+    );
+    test("7/1000000 -> oct", "approx. 0.000001653414 (dimensionless)");
+}
@@ -0,0 +1,138 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Properties shared by the fuzz targets in `core/fuzz` and the
+//! regression corpus in `core/tests/corpus`. Each check takes arbitrary
+//! input and panics if a property doesn't hold.
+
+#![allow(dead_code)]
+
+use rink_core::ast::{DatePattern, DateToken, Expr, ExprString, Query};
+use rink_core::limits::Limits;
+use rink_core::text_query::{parse_expr, Token, TokenIterator};
+use rink_core::{date, gnu_units, Context};
+
+fn has_error(expr: &Expr) -> bool {
+    match *expr {
+        Expr::Error { .. } => true,
+        Expr::BinOp(ref binop) => has_error(&binop.left) || has_error(&binop.right),
+        Expr::UnaryOp(ref unaryop) => has_error(&unaryop.expr),
+        Expr::Mul { ref exprs }
+        | Expr::Call {
+            args: ref exprs, ..
+        } => exprs.iter().any(has_error),
+        Expr::Of { ref expr, .. }
+        | Expr::Yields { ref expr, .. }
+        | Expr::Mixture { ref expr, .. } => has_error(expr),
+        Expr::At {
+            ref expr,
+            ref states,
+        } => has_error(expr) || states.iter().any(has_error),
+        Expr::After { ref expr, ref time } => has_error(expr) || has_error(time),
+        Expr::Date { ref tokens } => tokens.iter().any(|tok| matches!(tok, DateToken::Error(_))),
+        Expr::Unit { .. } | Expr::Quote { .. } | Expr::Const { .. } => false,
+    }
+}
+
+/// Parses a complete expression, or returns None if the input isn't
+/// one.
+fn parse(input: &str) -> Option<Expr> {
+    let mut iter = TokenIterator::new(input).peekable();
+    let expr = parse_expr(&mut iter);
+    match iter.next() {
+        Some(Token::Eof) if !has_error(&expr) => Some(expr),
+        _ => None,
+    }
+}
+
+/// The tokenizer terminates, and any expression that parses prints as
+/// something that parses back to the same expression, including
+/// through `ExprString`'s serde representation.
+pub fn check_text_query(input: &str) {
+    let tokens = TokenIterator::new(input)
+        .take_while(|tok| !matches!(tok, Token::Eof))
+        .count();
+    assert!(tokens <= input.len());
+
+    let expr = match parse(input) {
+        Some(expr) => expr,
+        None => return,
+    };
+    let printed = expr.to_string();
+    assert_eq!(
+        parse(&printed).as_ref(),
+        Some(&expr),
+        "{:?} printed as {:?}",
+        input,
+        printed
+    );
+
+    let json = serde_json::to_string(&ExprString(expr.clone())).unwrap();
+    let back: ExprString = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.0, expr, "{:?} serialized as {}", input, json);
+}
+
+/// The definitions parser terminates on any input, and queries about
+/// the substances it defines don't panic, even without the units that
+/// builtin features like gases rely on.
+pub fn check_gnu_units(input: &str) {
+    let mut iter = gnu_units::TokenIterator::new(input).peekable();
+    let defs = gnu_units::parse(&mut iter);
+    let mut ctx = Context::new();
+    ctx.use_humanize = false;
+    ctx.limits = Limits {
+        timeout: None,
+        ..Limits::sandbox()
+    };
+    ctx.load(defs);
+    let substances = ctx.substances.clone();
+    for (name, substance) in &substances {
+        let mut queries = vec![
+            name.clone(),
+            format!("{} at 300 K, 1 Pa", name),
+            format!("1 kg {} after 1 s", name),
+        ];
+        for property in substance.properties.properties.keys() {
+            queries.push(format!("{} of {}", property, name));
+        }
+        for query in queries {
+            let _ = rink_core::one_line(&mut ctx, &query);
+        }
+    }
+}
+
+/// Date patterns that parse print as something that parses back to
+/// the same pattern.
+pub fn check_date_pattern(input: &str) {
+    let pattern = match date::parse_datepattern(&mut input.chars().peekable()) {
+        Ok(pattern) => pattern,
+        Err(_) => return,
+    };
+    let printed = DatePattern::show(&pattern);
+    let reparsed = date::parse_datepattern(&mut printed.chars().peekable());
+    assert_eq!(
+        reparsed.as_ref(),
+        Ok(&pattern),
+        "{:?} printed as {:?}",
+        input,
+        printed
+    );
+    date::parse_datefile(input);
+}
+
+/// Chemical formulas are looked up when a unit isn't otherwise
+/// defined. Evaluation may fail, but must not panic.
+pub fn check_formula(ctx: &Context, input: &str) {
+    let _ = ctx.eval_outer(&Query::Expr(Expr::new_unit(input.to_owned())));
+}
+
+pub fn sandbox_context() -> Context {
+    let mut ctx = rink_core::simple_context().unwrap();
+    ctx.use_humanize = false;
+    ctx.limits = Limits {
+        timeout: None,
+        ..Limits::sandbox()
+    };
+    ctx
+}
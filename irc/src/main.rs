@@ -12,15 +12,10 @@ fn main() {
     use rink::*;
     use std::thread;
 
-    #[cfg(feature = "sandbox")]
-    fn eval(line: &str) -> String {
-        one_line_sandbox(line)
-    }
-
-    #[cfg(not(feature = "sandbox"))]
     fn eval(line: &str) -> String {
         let mut ctx = load().unwrap();
         ctx.short_output = true;
+        ctx.limits = limits::Limits::sandbox();
         match one_line(&mut ctx, line) {
             Ok(v) => v,
             Err(e) => e,
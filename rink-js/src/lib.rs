@@ -2,6 +2,7 @@ use chrono::{TimeZone, Utc};
 use js_sys::Date;
 use rink_core;
 use rink_core::ast;
+use rink_core::materials;
 use rink_core::text_query;
 use serde_derive::*;
 use serde_json;
@@ -72,8 +73,12 @@ impl Context {
     pub fn new() -> Context {
         set_panic_hook();
         let mut context = rink_core::simple_context().unwrap();
-        // Will panic if this is set.
-        context.use_humanize = false;
+        // There's no clock to enforce a timeout with, the step limit
+        // bounds evaluation time instead.
+        context.limits = rink_core::limits::Limits {
+            timeout: None,
+            ..rink_core::limits::Limits::sandbox()
+        };
         Context { context }
     }
 
@@ -113,6 +118,20 @@ impl Context {
         Ok(())
     }
 
+    /// Loads a material table as substances, either as CSV or as a JSON
+    /// array of rows.
+    #[wasm_bindgen(js_name = loadMaterials)]
+    pub fn load_materials(&mut self, table: String) -> Result<(), JsValue> {
+        let table = if table.trim_start().starts_with('[') {
+            materials::parse_json(&table)?
+        } else {
+            materials::parse_csv(&table)?
+        };
+        self.context.load(materials::material_defs(table));
+
+        Ok(())
+    }
+
     #[wasm_bindgen]
     pub fn eval(&mut self, expr: &Query) -> JsValue {
         let value = Success::from(self.context.eval_outer(&expr.query));
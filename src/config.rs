@@ -4,10 +4,16 @@
 
 use dirs;
 use rink_core::ast;
+use rink_core::business;
 use rink_core::context::Context;
 use rink_core::date;
 use rink_core::gnu_units;
-use rink_core::{CURRENCY_FILE, DATES_FILE, DEFAULT_FILE};
+use rink_core::materials;
+use rink_core::timezone;
+use rink_core::{
+    CURRENCY_FILE, DATES_FILE, DEFAULT_FILE, FOODS_FILE, HOLIDAYS_FILE, LEAP_SECONDS_FILE,
+    TIMEZONES_FILE,
+};
 use serde_json;
 use std::fs::File;
 use std::io::ErrorKind;
@@ -70,10 +76,29 @@ pub fn load() -> Result<Context, String> {
     let dates = load(Path::new("datepatterns.txt").to_path_buf())
         .or_else(|_| load(path.join("datepatterns.txt")))
         .unwrap_or_else(|_| DATES_FILE.to_owned());
+    let leaps = load(Path::new("leapseconds.txt").to_path_buf())
+        .or_else(|_| load(path.join("leapseconds.txt")))
+        .unwrap_or_else(|_| LEAP_SECONDS_FILE.to_owned());
+    let holidays = load(Path::new("holidays.txt").to_path_buf())
+        .or_else(|_| load(path.join("holidays.txt")))
+        .unwrap_or_else(|_| HOLIDAYS_FILE.to_owned());
+    let timezones = load(Path::new("timezones.txt").to_path_buf())
+        .or_else(|_| load(path.join("timezones.txt")))
+        .unwrap_or_else(|_| TIMEZONES_FILE.to_owned());
+    let foods = load(Path::new("foods.csv").to_path_buf())
+        .or_else(|_| load(path.join("foods.csv")))
+        .unwrap_or_else(|_| FOODS_FILE.to_owned());
 
     let mut iter = gnu_units::TokenIterator::new(&*units).peekable();
     let units = gnu_units::parse(&mut iter);
-    let dates = date::parse_datefile(&*dates);
+    let (dates, date_errors) = date::parse_datefile(&*dates);
+    let leaps = date::parse_leap_seconds(&*leaps);
+    let (holidays, holiday_errors) = business::parse_holidays(&*holidays);
+    let (timezones, timezone_errors) = timezone::parse_timezones(&*timezones);
+    let foods = materials::parse_csv(&*foods).unwrap_or_else(|e| {
+        println!("Failed to load foods.csv: {}", e);
+        vec![]
+    });
     let currency = cached(
         "currency.json",
         CURRENCY_URL,
@@ -100,12 +125,68 @@ pub fn load() -> Result<Context, String> {
     };
 
     let mut ctx = Context::new();
-    ctx.load(units);
+    // Before the units, so `!datepattern` directives come after the
+    // shipped patterns.
     ctx.load_dates(dates);
+    ctx.datepattern_errors = date_errors;
+    ctx.load(units);
+    ctx.load_foods(materials::material_defs(foods));
+    ctx.load_leap_seconds(leaps);
+    ctx.load_business_calendars(holidays);
+    ctx.holiday_errors = holiday_errors;
+    ctx.load_timezones(timezones);
+    ctx.timezone_errors = timezone_errors;
     ctx.load(currency);
+    for defs in load_materials(&path.join("materials")) {
+        ctx.load(defs);
+    }
+    for error in &ctx.datepattern_errors {
+        println!("{}", error);
+    }
+    for error in &ctx.holiday_errors {
+        println!("holidays.txt: {}", error);
+    }
+    for error in &ctx.timezone_errors {
+        println!("timezones.txt: {}", error);
+    }
     Ok(ctx)
 }
 
+/// Loads each `.csv` and `.json` material table in `dir`, in name
+/// order. Missing directories are skipped.
+fn load_materials(dir: &Path) -> Vec<ast::Defs> {
+    let mut paths = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect::<Vec<_>>(),
+        Err(_) => return vec![],
+    };
+    paths.sort();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let table = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("csv") => File::open(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(read_to_string)
+                    .and_then(|file| materials::parse_csv(&file)),
+                Some("json") => File::open(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(read_to_string)
+                    .and_then(|file| materials::parse_json(&file)),
+                _ => return None,
+            };
+            match table {
+                Ok(table) => Some(materials::material_defs(table)),
+                Err(e) => {
+                    println!("Failed to load {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
 fn cached(file: &str, url: &str, expiration: Duration) -> Result<File, String> {
     use std::fmt::Display;
     use std::fs;
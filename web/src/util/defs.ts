@@ -53,6 +53,11 @@ export interface Category extends DefBase {
   displayName: string;
 }
 
+export interface DatePattern extends DefBase {
+  type: "datePattern";
+  pattern: string;
+}
+
 export interface Error extends DefBase {
   type: "error";
   message: string;
@@ -67,4 +72,5 @@ export type Def =
   | Quantity
   | Substance
   | Category
+  | DatePattern
   | Error;
@@ -30,6 +30,7 @@ export enum BinOpType {
   Sub = "sub",
   Frac = "frac",
   Pow = "pow",
+  Range = "range",
   Equals = "equals",
 }
 
@@ -43,7 +44,12 @@ export interface BinOpExpr {
 export enum UnaryOpType {
   Negative = "negative",
   Positive = "positive",
-  // Degree
+  Celsius = "celsius",
+  Fahrenheit = "fahrenheit",
+  Reaumur = "reaumur",
+  Romer = "romer",
+  Delisle = "delisle",
+  Newton = "newton",
 }
 
 export interface UnaryOpExpr {
@@ -63,6 +69,30 @@ export interface OfExpr {
   expr: Expr;
 }
 
+export interface YieldsExpr {
+  type: "yields";
+  expr: Expr;
+  product: string;
+}
+
+export interface MixtureExpr {
+  type: "mixture";
+  expr: Expr;
+  by: "mass" | "volume" | "mole";
+}
+
+export interface AtExpr {
+  type: "at";
+  expr: Expr;
+  states: Expr[];
+}
+
+export interface AfterExpr {
+  type: "after";
+  expr: Expr;
+  time: Expr;
+}
+
 export interface CallExpr {
   type: "call";
   func: string;
@@ -83,5 +113,9 @@ export type Expr =
   | UnaryOpExpr
   | MulExpr
   | OfExpr
+  | YieldsExpr
+  | MixtureExpr
+  | AtExpr
+  | AfterExpr
   | CallExpr
   | ErrorExpr;
@@ -7,7 +7,8 @@ export enum Precedence {
   Mul = 3,
   Div = 4,
   Add = 5,
-  Equals = 6,
+  Range = 6,
+  Equals = 7,
 }
 
 export function symbol(op: BinOpType): string {
@@ -20,6 +21,8 @@ export function symbol(op: BinOpType): string {
       return " / ";
     case BinOpType.Pow:
       return "^";
+    case BinOpType.Range:
+      return " .. ";
     case BinOpType.Equals:
       return " = ";
   }
@@ -35,6 +38,8 @@ export function expectedPrecedence(op: BinOpType): Precedence {
       return Precedence.Div;
     case BinOpType.Pow:
       return Precedence.Pow;
+    case BinOpType.Range:
+      return Precedence.Range;
     case BinOpType.Equals:
       return Precedence.Equals;
   }
@@ -20,8 +20,7 @@ export interface NumberReply extends NumberParts {
   type: "number";
 }
 
-export interface DateReply {
-  type: "date";
+export interface DateParts {
   year: number;
   month: number;
   day: number;
@@ -31,6 +30,26 @@ export interface DateReply {
   nanosecond: number;
   string: string;
   rfc3339: string;
+  unix: number;
+  unixMillis: number;
+  isoWeek: string | null;
+  isoOrdinal: string | null;
+  rfc2822: string | null;
+  julianDay: string | null;
+  modifiedJulianDay: string | null;
+  calendar: CalendarDate | null;
+}
+
+export interface CalendarDate {
+  calendar: string;
+  year: number;
+  month: number;
+  monthName: string;
+  day: number;
+}
+
+export interface DateReply extends DateParts {
+  type: "date";
 }
 
 export interface PropertyReply {
@@ -39,12 +58,22 @@ export interface PropertyReply {
   doc: string | null;
 }
 
+export interface ElementReply {
+  symbol: string;
+  name: string;
+  count: number;
+  amount: NumberParts;
+  mass: NumberParts;
+  massPercent: NumberParts;
+}
+
 export interface SubstanceReply {
   type: "substance";
   name: string;
   doc: string | null;
   amount: NumberParts;
   properties: PropertyReply[];
+  composition: ElementReply[];
 }
 
 export interface Duration {
@@ -62,6 +91,55 @@ export interface DurationReply extends Duration {
   raw: NumberParts;
 }
 
+export interface IntervalReply {
+  type: "interval";
+  start: DateParts;
+  end: DateParts;
+  duration: Duration;
+}
+
+export interface RecurrenceReply {
+  type: "recurrence";
+  dates: DateParts[];
+}
+
+export interface BooleanReply {
+  type: "boolean";
+  value: boolean;
+}
+
+export interface DatePatternResult {
+  pattern: string;
+  date: DateParts | null;
+  error: string | null;
+}
+
+export interface DatePatternsReply {
+  type: "datePatterns";
+  input: string;
+  builtin: DateParts | null;
+  matched: number | null;
+  patterns: DatePatternResult[];
+}
+
+export interface SpeciesReply {
+  coefficient: number;
+  formula: string;
+  substance: Omit<SubstanceReply, "type"> | null;
+}
+
+export interface BalanceReply {
+  type: "balance";
+  reactants: SpeciesReply[];
+  products: SpeciesReply[];
+}
+
+export interface DilutionReply {
+  type: "dilution";
+  volume: NumberParts;
+  solvent: NumberParts;
+}
+
 export interface ExprLiteral {
   type: "literal";
   text: string;
@@ -126,6 +204,28 @@ export interface UnitListReply {
   list: NumberParts[];
 }
 
+export interface PropertyInfoReply {
+  name: string;
+  outputName: string;
+  output: NumberParts;
+  inputName: string | null;
+  input: NumberParts | null;
+  doc: string | null;
+}
+
+export interface PropertiesReply {
+  type: "properties";
+  name: string;
+  doc: string | null;
+  properties: PropertyInfoReply[];
+}
+
+export interface SubstanceListReply {
+  type: "substanceList";
+  property: string;
+  substances: string[];
+}
+
 export interface SearchReply {
   type: "search";
   results: NumberParts[];
@@ -136,6 +236,14 @@ export type QueryReply =
   | DateReply
   | SubstanceReply
   | DurationReply
+  | IntervalReply
+  | RecurrenceReply
+  | BooleanReply
+  | DatePatternsReply
+  | BalanceReply
+  | DilutionReply
+  | PropertiesReply
+  | SubstanceListReply
   | DefReply
   | ConversionReply
   | FactorizeReply
@@ -161,7 +269,37 @@ export interface GenericError {
   message: string;
 }
 
-export type QueryError = ConformanceError | NotFoundError | GenericError;
+export interface ParseError {
+  type: "parse";
+  message: string;
+}
+
+export interface InvalidDateError {
+  type: "invalidDate";
+  input: string;
+  message: string;
+}
+
+export interface LoadError {
+  type: "load";
+  message: string;
+}
+
+export interface OperationError {
+  type: "operation";
+  message: string;
+  operator: string;
+  operands: string[];
+}
+
+export type QueryError =
+  | ParseError
+  | InvalidDateError
+  | LoadError
+  | OperationError
+  | ConformanceError
+  | NotFoundError
+  | GenericError;
 
 export type Result<Ok, Err> =
   | ({ success: "ok" } & Ok)
@@ -176,6 +314,10 @@ export function describe(result: QueryResult): string {
     case "def":
       return `Definition of ${result.canonName}`;
     case "generic":
+    case "parse":
+    case "invalidDate":
+    case "load":
+    case "operation":
       return `Error: ${result.message}`;
     case "notFound":
       return `No such unit ${result.got}.`;
@@ -188,6 +330,9 @@ export function describe(result: QueryResult): string {
     case "conversion":
     case "date":
     case "duration":
+    case "interval":
+    case "recurrence":
+    case "boolean":
     case "factorize":
     case "number":
     case "unitList":